@@ -0,0 +1,148 @@
+//! A shareable handle to an appender that can be replaced at runtime.
+use crate::{shared::poisoned, RollingCondition, RollingFileAppender};
+use chrono::prelude::*;
+use std::{
+    io,
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// Holds a rolling file appender that can be swapped out for a new one
+/// (e.g. after a configuration change) without dropping any data.
+///
+/// Writers only hold the outer lock long enough to clone a reference to the
+/// current appender, so a swap never waits for in-progress writes, and writes
+/// never wait for a swap to finish draining the old appender.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn docs() {
+/// # use rolling_file::*;
+/// # use std::io::Write;
+/// let cell = RollingFileAppenderCell::new(
+///     BasicRollingFileAppender::new("/var/log/myprogram", RollingConditionBasic::new().daily(), 9).unwrap(),
+/// );
+/// (&cell).write_all(b"hello\n").unwrap();
+/// cell.swap(BasicRollingFileAppender::new("/var/log/myprogram", RollingConditionBasic::new().hourly(), 24).unwrap())
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RollingFileAppenderCell<RC>
+where
+    RC: RollingCondition,
+{
+    inner: RwLock<Arc<Mutex<RollingFileAppender<RC>>>>,
+}
+
+impl<RC> RollingFileAppenderCell<RC>
+where
+    RC: RollingCondition,
+{
+    /// Creates a new cell holding the given appender.
+    pub fn new(appender: RollingFileAppender<RC>) -> RollingFileAppenderCell<RC> {
+        RollingFileAppenderCell {
+            inner: RwLock::new(Arc::new(Mutex::new(appender))),
+        }
+    }
+
+    /// Returns the appender currently in use.
+    fn current(&self) -> io::Result<Arc<Mutex<RollingFileAppender<RC>>>> {
+        self.inner.read().map(|x| Arc::clone(&x)).map_err(|_| poisoned())
+    }
+
+    /// Replaces the appender in use. Once the new appender is in place, the
+    /// old one is closed, flushing any data it still buffers.
+    pub fn swap(&self, appender: RollingFileAppender<RC>) -> io::Result<()> {
+        let old = {
+            let mut inner = self.inner.write().map_err(|_| poisoned())?;
+            std::mem::replace(&mut *inner, Arc::new(Mutex::new(appender)))
+        };
+        // Writers that grabbed the old appender before the swap finish
+        // before we can lock it; anything they write afterwards is still
+        // flushed when the last reference to it is dropped.
+        let mut old = old.lock().map_err(|_| poisoned())?;
+        old.close()
+    }
+
+    /// Writes data to the current appender, using the given datetime to
+    /// calculate the rolling condition.
+    pub fn write_with_datetime(&self, buf: &[u8], now: &DateTime<Local>) -> io::Result<usize> {
+        let current = self.current()?;
+        let mut appender = current.lock().map_err(|_| poisoned())?;
+        appender.write_with_datetime(buf, now)
+    }
+}
+
+impl<RC> io::Write for &RollingFileAppenderCell<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let current = self.current()?;
+        let mut appender = current.lock().map_err(|_| poisoned())?;
+        appender.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let current = self.current()?;
+        let mut appender = current.lock().map_err(|_| poisoned())?;
+        appender.flush()
+    }
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::{BasicRollingFileAppender, RollingConditionBasic};
+    use std::{fs, io::Write, thread};
+
+    #[test]
+    fn swap_does_not_lose_data() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let first = tempdir.path().join("first.log");
+        let second = tempdir.path().join("second.log");
+        let cell = Arc::new(RollingFileAppenderCell::new(
+            BasicRollingFileAppender::new(&first, RollingConditionBasic::new(), 9).unwrap(),
+        ));
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = Arc::clone(&cell);
+                thread::spawn(move || {
+                    for _ in 0..500 {
+                        (&*cell).write_all(b"line\n").unwrap();
+                    }
+                })
+            })
+            .collect();
+        cell.swap(BasicRollingFileAppender::new(&second, RollingConditionBasic::new(), 9).unwrap())
+            .unwrap();
+        for w in writers {
+            w.join().unwrap();
+        }
+        drop(cell);
+        let total: usize = [first, second]
+            .iter()
+            .map(|p| fs::read_to_string(p).unwrap().lines().count())
+            .sum();
+        assert_eq!(total, 2000);
+    }
+
+    #[test]
+    fn swap_flushes_old_appender() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let first = tempdir.path().join("first.log");
+        let cell = RollingFileAppenderCell::new(
+            BasicRollingFileAppender::new(&first, RollingConditionBasic::new(), 9).unwrap(),
+        );
+        (&cell).write_all(b"before swap\n").unwrap();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "");
+        cell.swap(
+            BasicRollingFileAppender::new(tempdir.path().join("second.log"), RollingConditionBasic::new(), 9).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "before swap\n");
+    }
+}
+// LCOV_EXCL_STOP
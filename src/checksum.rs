@@ -0,0 +1,101 @@
+//! Checksum sidecar files for rotated files.
+use flate2::read::MultiGzDecoder;
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Write as _,
+    fs,
+    fs::File,
+    io,
+    io::{Read, Write},
+    path::Path,
+};
+
+/// The extension of checksum sidecar files
+const SIDECAR_EXTENSION: &str = ".sha256";
+
+/// Determines what the checksum in a rotated file's `.sha256` sidecar covers
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Checksum {
+    /// The checksum of the data as written, before any compression
+    BeforeCompression,
+    /// The checksum of the rotated file as stored, i.e. of the compressed
+    /// bytes if it was compressed
+    AfterCompression,
+}
+
+/// Returns the path of the sidecar holding the checksum of the given file.
+pub(crate) fn sidecar_for(path: &OsStr) -> OsString {
+    let mut f = path.to_os_string();
+    f.push(SIDECAR_EXTENSION);
+    f
+}
+
+/// Computes the SHA-256 checksum of everything the reader returns, as
+/// lowercase hex.
+pub(crate) fn sha256<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    let mut hex = String::with_capacity(64);
+    for b in hasher.finalize() {
+        let _ = write!(hex, "{:02x}", b);
+    }
+    Ok(hex)
+}
+
+/// Writes the sidecar for the given file, via a temporary file that is
+/// renamed into place so readers never see a partial checksum.
+pub(crate) fn write_sidecar(path: &OsStr, checksum: &str) -> io::Result<()> {
+    let sidecar = sidecar_for(path);
+    let mut tmp = sidecar.clone();
+    tmp.push(".tmp");
+    let r = File::create(&tmp).and_then(|mut f| {
+        writeln!(f, "{}", checksum)?;
+        f.sync_all()
+    });
+    match r.and_then(|()| fs::rename(&tmp, &sidecar)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        },
+    }
+}
+
+/// Checks a rotated file against the checksum in its `.sha256` sidecar,
+/// returning whether they match. Since the sidecar doesn't record whether
+/// its checksum was taken before or after compression, a gzip-compressed
+/// file (ending in `.gz`) also matches the checksum of its decompressed
+/// content.
+pub fn verify_checksum<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let path = path.as_ref();
+    let expected = fs::read_to_string(sidecar_for(path.as_os_str()))?;
+    let expected = expected.trim();
+    if sha256(File::open(path)?)? == expected {
+        return Ok(true);
+    }
+    if path.extension() == Some(OsStr::new("gz")) {
+        return match sha256(MultiGzDecoder::new(File::open(path)?)) {
+            Ok(actual) => Ok(actual == expected),
+            // not valid gzip data, so it can't match either way
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                Ok(false)
+            },
+            Err(e) => Err(e),
+        };
+    }
+    Ok(false)
+}
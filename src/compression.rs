@@ -0,0 +1,502 @@
+//! Compression of rotated files.
+use flate2::{
+    read::GzDecoder,
+    write::{DeflateEncoder, GzEncoder},
+    Crc,
+    GzBuilder,
+};
+use std::{
+    convert::TryFrom,
+    fs,
+    fs::{File, OpenOptions},
+    io,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+/// Determines how rotated files are compressed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// Compresses files with gzip, adding a `.gz` extension. The gzip header
+    /// holds the original filename and modification time, which
+    /// `gunzip -N` restores.
+    Gzip,
+    /// Compresses files with gzip in independently compressed blocks of at
+    /// most 64 KiB, adding a `.gz` extension. This is the BGZF format used by
+    /// `bgzip`, which any gzip tool can decompress, and which can be read from
+    /// arbitrary positions with a [`BlockGzipReader`] without decompressing
+    /// the whole file, at the cost of a slightly worse compression ratio.
+    /// Unlike with `Gzip`, the headers don't hold the original filename and
+    /// modification time, as BGZF readers expect a fixed header layout.
+    BlockGzip,
+}
+
+impl Compression {
+    /// The extension added to the names of compressed files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip | Compression::BlockGzip => ".gz",
+        }
+    }
+}
+
+/// Compresses the file at `from` into a new file at `to`, and removes the
+/// original once that succeeded. On failure, the original is left intact.
+pub(crate) fn compress_file(compression: Compression, from: &Path, to: &Path) -> io::Result<()> {
+    let r = match compression {
+        Compression::Gzip => gzip_file(from, to),
+        Compression::BlockGzip => block_gzip_file(from, to),
+    };
+    match r {
+        Ok(()) => fs::remove_file(from),
+        Err(e) => {
+            // don't leave a partially written file behind
+            let _ = fs::remove_file(to);
+            Err(e)
+        },
+    }
+}
+
+fn gzip_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut input = File::open(from)?;
+    let mut builder = GzBuilder::new();
+    if let Some(name) = from.file_name() {
+        builder = builder.filename(name.as_encoded_bytes());
+    }
+    // the header can only hold 32-bit timestamps, so leave it unset otherwise
+    let mtime_opt = input
+        .metadata()?
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .and_then(|d| u32::try_from(d.as_secs()).ok());
+    if let Some(mtime) = mtime_opt {
+        builder = builder.mtime(mtime);
+    }
+    let mut encoder = builder.write(BufWriter::new(File::create(to)?), flate2::Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.flush()
+}
+
+/// Returns whether the file at the given path starts like a gzip file, as
+/// one written by a [`GzipWriter`] does.
+pub(crate) fn is_gzip(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; 2];
+    let n = read_up_to(&mut File::open(path)?, &mut magic)?;
+    Ok(n == magic.len() && magic == [0x1f, 0x8b])
+}
+
+/// Appends to a file through a gzip encoder, counting the compressed bytes.
+/// Every time the file is opened, a new gzip member is started, which gzip
+/// tools decompress as if the members were one.
+#[derive(Debug)]
+pub(crate) struct GzipWriter {
+    encoder: GzEncoder<CountingWriter>,
+}
+
+impl GzipWriter {
+    /// Opens (or creates) the file at the given path for appending.
+    pub(crate) fn open(path: &Path) -> io::Result<GzipWriter> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let len = file.metadata()?.len();
+        let output = CountingWriter {
+            inner: BufWriter::new(file),
+            len,
+        };
+        Ok(GzipWriter {
+            encoder: GzEncoder::new(output, flate2::Compression::default()),
+        })
+    }
+
+    /// The number of compressed bytes in the file, including those still
+    /// buffered, but not the data the encoder hasn't compressed yet.
+    pub(crate) fn len(&self) -> u64 {
+        self.encoder.get_ref().len
+    }
+
+    /// The number of compressed bytes that aren't written yet
+    pub(crate) fn buffered_len(&self) -> usize {
+        self.encoder.get_ref().inner.buffer().len()
+    }
+
+    /// The file being written to
+    pub(crate) fn file(&self) -> &File {
+        self.encoder.get_ref().inner.get_ref()
+    }
+
+    /// Writes the end of the gzip member, after which nothing more can be
+    /// written.
+    pub(crate) fn try_finish(&mut self) -> io::Result<()> {
+        self.encoder.try_finish()?;
+        self.encoder.get_mut().flush()
+    }
+
+    /// Flushes the encoder, and makes sure the data reaches the disk as well.
+    pub(crate) fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.file().sync_data()
+    }
+}
+
+impl Write for GzipWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    /// Compresses all data written so far, so that it can be decompressed
+    /// from the file. This costs some compression, so flush sparingly.
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+/// Counts the bytes written through it
+#[derive(Debug)]
+struct CountingWriter {
+    inner: BufWriter<File>,
+    len: u64,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The maximum amount of uncompressed data per block, as used by `bgzip`
+const BLOCK_DATA_SIZE: usize = 0xff00;
+/// The size of a block's header, including the extra field holding its size
+const BLOCK_HEADER_SIZE: usize = 18;
+/// The size of a block's trailer, holding the CRC and uncompressed size
+const BLOCK_TRAILER_SIZE: usize = 8;
+
+fn block_gzip_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut input = File::open(from)?;
+    let mut output = BufWriter::new(File::create(to)?);
+    let mut data = vec![0; BLOCK_DATA_SIZE];
+    loop {
+        let n = read_up_to(&mut input, &mut data)?;
+        if n == 0 {
+            break;
+        }
+        write_block(&mut output, &data[..n])?;
+    }
+    // an empty block marks the end of the file
+    write_block(&mut output, &[])?;
+    output.flush()
+}
+
+/// Reads until the buffer is full or the end of the input is reached.
+fn read_up_to<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match input.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(x) => n += x,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+fn write_block<W: Write>(output: &mut W, data: &[u8]) -> io::Result<()> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+    let block_size = u16::try_from(BLOCK_HEADER_SIZE + compressed.len() + BLOCK_TRAILER_SIZE - 1)
+        .map_err(|_| io::Error::other("compressed block is too large"))?;
+    let mut crc = Crc::new();
+    crc.update(data);
+    // magic, deflate, FEXTRA flag, no mtime, no extra flags, unknown OS
+    output.write_all(&[0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff])?;
+    // extra field length, and the "BC" subfield holding the block size - 1
+    output.write_all(&6u16.to_le_bytes())?;
+    output.write_all(b"BC")?;
+    output.write_all(&2u16.to_le_bytes())?;
+    output.write_all(&block_size.to_le_bytes())?;
+    output.write_all(&compressed)?;
+    output.write_all(&crc.sum().to_le_bytes())?;
+    output.write_all(&crc.amount().to_le_bytes())
+}
+
+/// The location of a block in both the compressed and uncompressed data
+#[derive(Copy, Clone, Debug)]
+struct Block {
+    offset: u64,
+    size: u64,
+    start: u64,
+    len: u64,
+}
+
+/// Reads a file compressed with [`Compression::BlockGzip`], allowing to seek
+/// to any position in the uncompressed data. On creation, the reader builds
+/// an index from the block headers; afterwards, only the blocks that are
+/// actually read are decompressed.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn docs() -> std::io::Result<()> {
+/// # use rolling_file::*;
+/// use std::io::{Read, Seek, SeekFrom};
+/// let mut reader = BlockGzipReader::new(std::fs::File::open("/var/log/myprogram.1.gz")?)?;
+/// reader.seek(SeekFrom::Start(1_000_000))?;
+/// let mut buf = [0; 100];
+/// reader.read_exact(&mut buf)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BlockGzipReader<R> {
+    inner: R,
+    blocks: Vec<Block>,
+    pos: u64,
+    current_opt: Option<(usize, Vec<u8>)>,
+}
+
+impl<R> BlockGzipReader<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a reader, indexing the blocks of the compressed data.
+    pub fn new(mut inner: R) -> io::Result<BlockGzipReader<R>> {
+        let mut blocks = Vec::new();
+        let mut offset = 0;
+        let mut start = 0;
+        loop {
+            inner.seek(SeekFrom::Start(offset))?;
+            let mut header = [0; BLOCK_HEADER_SIZE];
+            match read_up_to(&mut inner, &mut header)? {
+                0 => break,
+                BLOCK_HEADER_SIZE => {},
+                _ => return Err(invalid_block("truncated block header")),
+            }
+            if header[..4] != [0x1f, 0x8b, 8, 4] || header[10..16] != [6, 0, b'B', b'C', 2, 0] {
+                return Err(invalid_block("missing block size in header"));
+            }
+            let size = u64::from(u16::from_le_bytes([header[16], header[17]])) + 1;
+            if size < (BLOCK_HEADER_SIZE + BLOCK_TRAILER_SIZE) as u64 {
+                return Err(invalid_block("block size too small"));
+            }
+            inner.seek(SeekFrom::Start(offset + size - 4))?;
+            let mut len = [0; 4];
+            inner.read_exact(&mut len)?;
+            let len = u64::from(u32::from_le_bytes(len));
+            if len > 0 {
+                blocks.push(Block {
+                    offset,
+                    size,
+                    start,
+                    len,
+                });
+            }
+            offset += size;
+            start += len;
+        }
+        Ok(BlockGzipReader {
+            inner,
+            blocks,
+            pos: 0,
+            current_opt: None,
+        })
+    }
+
+    /// The length of the uncompressed data
+    pub fn len(&self) -> u64 {
+        self.blocks.last().map_or(0, |b| b.start + b.len)
+    }
+
+    /// Whether the uncompressed data is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets a reference to the underlying reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Decompresses the block with the given index, unless it is the current one.
+    fn load_block(&mut self, index: usize) -> io::Result<&[u8]> {
+        if self.current_opt.as_ref().map(|(i, _)| *i) != Some(index) {
+            let block = self.blocks[index];
+            self.inner.seek(SeekFrom::Start(block.offset))?;
+            let mut compressed = Vec::new();
+            (&mut self.inner).take(block.size).read_to_end(&mut compressed)?;
+            let mut data = Vec::new();
+            GzDecoder::new(&compressed[..]).read_to_end(&mut data)?;
+            self.current_opt = Some((index, data));
+        }
+        Ok(self.current_opt.as_ref().map(|(_, data)| &data[..]).unwrap_or(&[]))
+    }
+}
+
+impl<R> Read for BlockGzipReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pos = self.pos;
+        let index = self.blocks.partition_point(|b| b.start + b.len <= pos);
+        if index >= self.blocks.len() {
+            return Ok(0);
+        }
+        let skip = usize::try_from(pos - self.blocks[index].start).unwrap_or(usize::MAX);
+        let data = self.load_block(index)?;
+        let available = data.get(skip..).unwrap_or(&[]);
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> Seek for BlockGzipReader<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(x) => {
+                self.pos = x;
+                return Ok(x);
+            },
+            SeekFrom::End(x) => (self.len(), x),
+            SeekFrom::Current(x) => (self.pos, x),
+        };
+        match base.checked_add_signed(offset) {
+            Some(x) => {
+                self.pos = x;
+                Ok(x)
+            },
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+fn invalid_block(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid block gzip data: {}", message),
+    )
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+
+    /// Counts the bytes read from the inner reader
+    struct CountingReader<R> {
+        inner: R,
+        count: u64,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.count += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn gzip_header_metadata() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let from = tempdir.path().join("test.log.1");
+        let to = tempdir.path().join("test.log.1.gz");
+        fs::write(&from, "content\n").unwrap();
+        let mtime = UNIX_EPOCH + std::time::Duration::from_secs(1_617_000_000);
+        File::options()
+            .write(true)
+            .open(&from)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        compress_file(Compression::Gzip, &from, &to).unwrap();
+
+        let mut decoder = GzDecoder::new(File::open(&to).unwrap());
+        let mut decompressed = String::new();
+        decoder.read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, "content\n");
+        let header = decoder.header().unwrap();
+        assert_eq!(header.filename(), Some(&b"test.log.1"[..]));
+        assert_eq!(header.mtime(), 1_617_000_000);
+    }
+
+    #[test]
+    fn block_gzip_random_access() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let from = tempdir.path().join("test.log.1");
+        let to = tempdir.path().join("test.log.1.gz");
+        let content: String = (0..30_000).map(|i| format!("Line {}\n", i)).collect();
+        fs::write(&from, &content).unwrap();
+        compress_file(Compression::BlockGzip, &from, &to).unwrap();
+        assert!(!from.exists());
+
+        // any gzip decoder can read the whole file
+        let mut decompressed = String::new();
+        MultiGzDecoder::new(File::open(&to).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, content);
+
+        let mut reader = BlockGzipReader::new(CountingReader {
+            inner: File::open(&to).unwrap(),
+            count: 0,
+        })
+        .unwrap();
+        assert_eq!(reader.len(), content.len() as u64);
+        assert!(reader.blocks.len() > 2);
+        let middle = content.len() / 2;
+        reader.seek(SeekFrom::Start(middle as u64)).unwrap();
+        let mut buf = [0; 100];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &content.as_bytes()[middle..middle + 100]);
+        assert!(reader.get_ref().count < fs::metadata(&to).unwrap().len() / 2);
+
+        // reading across a block boundary and up to the end
+        let boundary = reader.blocks[1].start;
+        reader.seek(SeekFrom::Start(boundary - 10)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..],
+            &content.as_bytes()[boundary as usize - 10..boundary as usize + 90]
+        );
+        reader.seek(SeekFrom::End(-5)).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &content.as_bytes()[content.len() - 5..]);
+    }
+
+    #[test]
+    fn block_gzip_corrupt_size() {
+        for size in [0u16, 2, 24] {
+            let mut data = vec![0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0];
+            data.extend_from_slice(&size.to_le_bytes());
+            data.resize(64, 0);
+            let e = BlockGzipReader::new(io::Cursor::new(data)).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert!(e.to_string().contains("block size too small"), "{}", e);
+        }
+    }
+}
+// LCOV_EXCL_STOP
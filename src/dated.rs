@@ -0,0 +1,89 @@
+//! Names of rotated files grouped by date, see
+//! [`NumberingScheme::DateGrouped`](crate::NumberingScheme::DateGrouped).
+use chrono::NaiveDate;
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+/// The format of the date in the names
+const DATE_FORMAT: &str = "%Y%m%d";
+
+/// Returns the file name of the k-th rotated file of the given date for the
+/// given current file, e.g. `app-20210330.log` for k = 0 and
+/// `app-20210330.1.log` for k = 1 when the current file is `app.log`.
+pub(crate) fn file_name(current: &Path, date: NaiveDate, k: usize) -> OsString {
+    let mut name = current.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{}", date.format(DATE_FORMAT)));
+    if k > 0 {
+        name.push(format!(".{}", k));
+    }
+    if let Some(extension) = current.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    name
+}
+
+/// Parses the file name of a rotated file of the given current file,
+/// returning its date, its index within the date, and whether it ends with
+/// the given compression extension. Names are compared as raw bytes, so the
+/// current file's name needn't be valid UTF-8.
+pub(crate) fn parse_file_name(
+    current: &Path,
+    compression_extension: &str,
+    name: &OsStr,
+) -> Option<(NaiveDate, usize, bool)> {
+    let stem = current.file_stem()?.as_encoded_bytes();
+    let rest = name.as_encoded_bytes().strip_prefix(stem)?.strip_prefix(b"-")?;
+    let (rest, compressed) = match rest.strip_suffix(compression_extension.as_bytes()) {
+        Some(x) => (x, true),
+        None => (rest, false),
+    };
+    let rest = match current.extension() {
+        Some(extension) => rest.strip_suffix(extension.as_encoded_bytes())?.strip_suffix(b".")?,
+        None => rest,
+    };
+    if rest.len() < 8 || !rest[..8].iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let date = NaiveDate::parse_from_str(std::str::from_utf8(&rest[..8]).ok()?, DATE_FORMAT).ok()?;
+    let k = match &rest[8..] {
+        [] => 0,
+        [b'.', digits @ ..] if !digits.is_empty() && digits.iter().all(u8::is_ascii_digit) => {
+            std::str::from_utf8(digits).ok()?.parse().ok().filter(|k| *k > 0)?
+        },
+        _ => return None,
+    };
+    Some((date, k, compressed))
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let date = NaiveDate::from_ymd_opt(2021, 3, 30).unwrap();
+        let current = Path::new("/var/log/app.log");
+        assert_eq!(file_name(current, date, 0), "app-20210330.log");
+        assert_eq!(file_name(current, date, 2), "app-20210330.2.log");
+        assert_eq!(file_name(Path::new("app"), date, 1), "app-20210330.1");
+        let parse = |name: &str| parse_file_name(current, ".gz", OsStr::new(name));
+        assert_eq!(parse("app-20210330.log"), Some((date, 0, false)));
+        assert_eq!(parse("app-20210330.12.log.gz"), Some((date, 12, true)));
+        assert_eq!(parse("app.log"), None);
+        assert_eq!(parse("app.log.1"), None);
+        assert_eq!(parse("app-20210330.0.log"), None);
+        assert_eq!(parse("app-20211330.log"), None);
+        assert_eq!(parse("app-20210330.x.log"), None);
+        assert_eq!(parse("app-2021033.log"), None);
+        assert_eq!(parse("other-20210330.log"), None);
+        assert_eq!(
+            parse_file_name(Path::new("app"), ".gz", OsStr::new("app-20210330.3")),
+            Some((date, 3, false))
+        );
+    }
+}
+// LCOV_EXCL_STOP
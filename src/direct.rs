@@ -0,0 +1,180 @@
+//! An unbuffered writer backend for the active file, bypassing the page
+//! cache with `O_DIRECT`.
+//!
+//! `O_DIRECT` requires the memory buffer, the file offset and the length of
+//! every write to be aligned to the logical block size of the device. Writes
+//! are therefore copied into an aligned staging buffer, which is written out
+//! in whole blocks at aligned offsets. A partial last block is written padded
+//! with zeros, and the file is then truncated back to the data written; the
+//! block is kept in the buffer and written again once more data follows.
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::Path,
+};
+
+/// The alignment of buffers, offsets and lengths, which covers the usual
+/// logical block sizes of 512 and 4096 bytes
+pub(crate) const ALIGNMENT: usize = 4096;
+
+/// The size of the staging buffer
+const CAPACITY: usize = 16 * ALIGNMENT;
+
+/// Appends to a file opened with `O_DIRECT`.
+#[derive(Debug)]
+pub(crate) struct DirectWriter {
+    file: File,
+    /// Holds the staging buffer at an aligned position within it
+    storage: Vec<u8>,
+    /// Where the staging buffer starts within `storage`
+    start: usize,
+    /// The number of bytes in the staging buffer
+    len: usize,
+    /// The number of bytes in the staging buffer that are already written
+    flushed: usize,
+    /// The aligned file offset the staging buffer is written at
+    offset: u64,
+}
+
+impl DirectWriter {
+    /// Opens (or creates) the file at the given path, positioning the write
+    /// cursor at the end of its existing content.
+    pub(crate) fn open(path: &Path) -> io::Result<DirectWriter> {
+        let mut existing = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let file_len = existing.metadata()?.len();
+        let tail = (file_len % ALIGNMENT as u64) as usize;
+        let offset = file_len - tail as u64;
+        let file = OpenOptions::new().write(true).custom_flags(libc::O_DIRECT).open(path)?;
+        let storage = vec![0; CAPACITY + ALIGNMENT];
+        let start = storage.as_ptr().align_offset(ALIGNMENT);
+        let mut writer = DirectWriter {
+            file,
+            storage,
+            start,
+            len: tail,
+            flushed: tail,
+            offset,
+        };
+        // the partial last block is written again along with what follows
+        existing.seek(SeekFrom::Start(offset))?;
+        existing.read_exact(&mut writer.buffer()[..tail])?;
+        Ok(writer)
+    }
+
+    /// The number of bytes written to the file, including those still in the
+    /// staging buffer.
+    pub(crate) fn len(&self) -> u64 {
+        self.offset + self.len as u64
+    }
+
+    /// The number of bytes in the staging buffer that aren't written yet
+    pub(crate) fn buffered_len(&self) -> usize {
+        self.len - self.flushed
+    }
+
+    /// The file being written to
+    pub(crate) fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Forgets the data that isn't written yet.
+    pub(crate) fn discard(&mut self) {
+        self.len = self.flushed;
+    }
+
+    /// Flushes the staging buffer, and makes sure the file's metadata reaches
+    /// the disk as well.
+    pub(crate) fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.file.sync_data()
+    }
+
+    fn buffer(&mut self) -> &mut [u8] {
+        &mut self.storage[self.start..self.start + CAPACITY]
+    }
+
+    /// Writes the staging buffer, padded to whole blocks, and moves the
+    /// whole blocks out of it.
+    fn write_buffer(&mut self) -> io::Result<()> {
+        let len = self.len;
+        let padded = round_up(len);
+        let offset = self.offset;
+        let buffer = &mut self.storage[self.start..self.start + CAPACITY];
+        buffer[len..padded].fill(0);
+        self.file.write_all_at(&buffer[..padded], offset)?;
+        if padded != len {
+            self.file.set_len(offset + len as u64)?;
+        }
+        let whole = len - len % ALIGNMENT;
+        self.buffer().copy_within(whole..len, 0);
+        self.offset += whole as u64;
+        self.len -= whole;
+        self.flushed = self.len;
+        Ok(())
+    }
+}
+
+impl Write for DirectWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.len == CAPACITY {
+            self.write_buffer()?;
+        }
+        let n = buf.len().min(CAPACITY - self.len);
+        let len = self.len;
+        self.buffer()[len..len + n].copy_from_slice(&buf[..n]);
+        self.len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.len > self.flushed {
+            self.write_buffer()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DirectWriter {
+    fn drop(&mut self) {
+        // Errors cannot be reported from here, like with a BufWriter.
+        let _ = self.flush();
+    }
+}
+
+/// Rounds `x` up to the next multiple of the alignment.
+fn round_up(x: usize) -> usize {
+    match x % ALIGNMENT {
+        0 => x,
+        r => x + ALIGNMENT - r,
+    }
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn partial_blocks() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        fs::write(&path, b"existing\n").unwrap();
+        let mut writer = DirectWriter::open(&path).unwrap();
+        assert_eq!(writer.len(), 9);
+        let data: Vec<u8> = (0..CAPACITY + 100).map(|i| (i % 251) as u8).collect();
+        writer.write_all(&data).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.buffered_len(), 0);
+        writer.write_all(b"more").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let mut expected = b"existing\n".to_vec();
+        expected.extend(&data);
+        expected.extend(b"more");
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+}
+// LCOV_EXCL_STOP
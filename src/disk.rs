@@ -0,0 +1,136 @@
+//! Platform-specific queries about the volume holding the log files.
+use std::{fs, fs::OpenOptions, io, path::Path};
+
+/// Renames a file, falling back to copying it and deleting the original if
+/// the destination is on a different volume, where renaming isn't possible.
+pub(crate) fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => copy_then_remove(from, to),
+        r => r,
+    }
+}
+
+/// Copies a file, syncs the copy to disk, and only then removes the original.
+/// On failure, the original is left intact, and any partial copy is removed.
+fn copy_then_remove(from: &Path, to: &Path) -> io::Result<()> {
+    let r = fs::copy(from, to).and_then(|_| OpenOptions::new().write(true).open(to)?.sync_all());
+    match r {
+        Ok(()) => fs::remove_file(from),
+        Err(e) => {
+            // don't leave a partial copy behind (if the original is missing,
+            // nothing was copied)
+            if from.exists() {
+                let _ = fs::remove_file(to);
+            }
+            Err(e)
+        },
+    }
+}
+
+/// Returns the number of bytes available to this process on the volume
+/// holding the given path.
+#[cfg(unix)]
+pub(crate) fn available_space(path: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path =
+        CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+/// Returns the total capacity in bytes of the volume holding the given path.
+#[cfg(unix)]
+pub(crate) fn total_space(path: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path =
+        CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok((stat.f_blocks as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+/// Returns the number of bytes available to this process on the volume
+/// holding the given path.
+#[cfg(windows)]
+pub(crate) fn available_space(path: &Path) -> io::Result<u64> {
+    use std::{iter::once, os::windows::ffi::OsStrExt, ptr};
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+    let mut available = 0u64;
+    if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, ptr::null_mut(), ptr::null_mut()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+/// Returns the total capacity in bytes of the volume holding the given path.
+#[cfg(windows)]
+pub(crate) fn total_space(path: &Path) -> io::Result<u64> {
+    use std::{iter::once, os::windows::ffi::OsStrExt, ptr};
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+    let mut total = 0u64;
+    if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), ptr::null_mut(), &mut total, ptr::null_mut()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(total)
+}
+
+/// Returns the number of bytes available to this process on the volume
+/// holding the given path.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn available_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::other(
+        "querying disk space is not supported on this platform",
+    ))
+}
+
+/// Returns the total capacity in bytes of the volume holding the given path.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn total_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::other(
+        "querying disk space is not supported on this platform",
+    ))
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    #[test]
+    fn copy_fallback() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let from = tempdir.path().join("test.log.1");
+        let to = tempdir.path().join("test.log.2");
+        fs::write(&from, "content").unwrap();
+        copy_then_remove(&from, &to).unwrap();
+        assert!(!from.exists());
+        assert_eq!(fs::read_to_string(&to).unwrap(), "content");
+
+        // the original is kept if copying fails
+        fs::write(&from, "content").unwrap();
+        assert!(copy_then_remove(&from, &tempdir.path().join("missing").join("test.log.2")).is_err());
+        assert_eq!(fs::read_to_string(&from).unwrap(), "content");
+    }
+
+    #[test]
+    fn query_available_space() {
+        let tempdir = tempfile::tempdir().unwrap();
+        assert!(available_space(tempdir.path()).unwrap() > 0);
+        assert!(available_space(&tempdir.path().join("missing")).is_err());
+        assert!(total_space(tempdir.path()).unwrap() >= available_space(tempdir.path()).unwrap());
+    }
+}
+// LCOV_EXCL_STOP
@@ -0,0 +1,164 @@
+//! Transforms applied to the data on its way into the current file.
+use flate2::write::GzEncoder;
+use std::{io, io::Write};
+
+/// Transforms the data written to the appender before it reaches the current
+/// file, e.g. to encrypt or encode it, see
+/// [`RollingFileAppenderBuilder::encoder`](crate::RollingFileAppenderBuilder::encoder).
+/// Each file is encoded on its own: [`finalize`](Encoder::finalize) ends the
+/// encoded data of a file before it is rotated or closed, and the next file
+/// starts afresh.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rolling_file::*;
+/// # use std::{io, io::Write};
+/// /// Writes each record as a line of hex digits
+/// struct Hex;
+///
+/// impl Encoder for Hex {
+///     fn encode(&mut self, input: &[u8], out: &mut dyn Write) -> io::Result<()> {
+///         for b in input {
+///             write!(out, "{:02x}", b)?;
+///         }
+///         out.write_all(b"\n")
+///     }
+/// }
+/// ```
+pub trait Encoder: Send {
+    /// Encodes data written to the appender, writing the result to the
+    /// current file.
+    fn encode(&mut self, input: &[u8], out: &mut dyn Write) -> io::Result<()>;
+
+    /// Writes whatever the encoding of the current file still needs, e.g. a
+    /// trailer, before the file is rotated or closed, and gets ready for the
+    /// next file. Does nothing by default.
+    fn finalize(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compresses each file as a single gzip member, as an [`Encoder`]. The file
+/// can be decompressed once it was finalized, i.e. rotated or closed.
+/// Compressed data is handed to the file as the compressor produces it, so
+/// the file lags behind the writes until it is finalized.
+#[derive(Debug, Default)]
+pub struct GzipEncoder {
+    encoder_opt: Option<GzEncoder<Vec<u8>>>,
+}
+
+impl GzipEncoder {
+    /// Constructs an encoder compressing with the default level.
+    pub fn new() -> GzipEncoder {
+        GzipEncoder::default()
+    }
+}
+
+impl Encoder for GzipEncoder {
+    fn encode(&mut self, input: &[u8], out: &mut dyn Write) -> io::Result<()> {
+        let encoder = self
+            .encoder_opt
+            .get_or_insert_with(|| GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        encoder.write_all(input)?;
+        let compressed = encoder.get_mut();
+        out.write_all(compressed)?;
+        compressed.clear();
+        Ok(())
+    }
+
+    fn finalize(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        match self.encoder_opt.take() {
+            Some(encoder) => out.write_all(&encoder.finish()?),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Counts the bytes written through it
+struct CountingWriter<'a> {
+    inner: &'a mut dyn Write,
+    len: u64,
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes data through the encoder, if there is one, returning the number of
+/// bytes that went to the writer. Sets `taken` to the number of bytes of the
+/// data the writer took, even if writing fails part way. An encoder can't be
+/// resumed part way, so it takes all of the data or, if it fails, none.
+pub(crate) fn write_encoded(
+    encoder_opt: Option<&mut Box<dyn Encoder>>,
+    data: &[u8],
+    out: &mut dyn Write,
+    taken: &mut usize,
+) -> io::Result<u64> {
+    *taken = 0;
+    match encoder_opt {
+        Some(encoder) => {
+            let mut counting = CountingWriter { inner: out, len: 0 };
+            encoder.encode(data, &mut counting)?;
+            *taken = data.len();
+            Ok(counting.len)
+        },
+        None => {
+            while *taken < data.len() {
+                match out.write(&data[*taken..]) {
+                    Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                    Ok(n) => *taken += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(data.len() as u64)
+        },
+    }
+}
+
+/// Finalizes the encoder for the current file, returning the number of bytes
+/// that went to the writer.
+pub(crate) fn finalize(encoder: &mut dyn Encoder, out: &mut dyn Write) -> io::Result<u64> {
+    let mut counting = CountingWriter { inner: out, len: 0 };
+    encoder.finalize(&mut counting)?;
+    Ok(counting.len)
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn gzip_encoder() {
+        let mut encoder = GzipEncoder::new();
+        let mut out = Vec::new();
+        for _ in 0..2 {
+            for line in &["Line 1\n", "Line 2\n"] {
+                encoder.encode(line.as_bytes(), &mut out).unwrap();
+            }
+            encoder.finalize(&mut out).unwrap();
+        }
+        // two members, one per file
+        let mut s = String::new();
+        flate2::read::MultiGzDecoder::new(&out[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Line 1\nLine 2\nLine 1\nLine 2\n");
+        let mut s = String::new();
+        GzDecoder::new(&out[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Line 1\nLine 2\n");
+    }
+}
+// LCOV_EXCL_STOP
@@ -0,0 +1,238 @@
+//! Errors the appender recovers from by itself.
+use std::{error, fmt, io, path::PathBuf};
+
+/// A non-fatal error the appender ran into and carried on from, see
+/// [`RollingFileAppenderBuilder::internal_error_handler`](crate::RollingFileAppenderBuilder::internal_error_handler).
+/// Unless a handler is set, these are printed to stderr. The exception is
+/// [`WriterMissing`](RollingFileError::WriterMissing), which fails the write.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RollingFileError {
+    /// Rotating the current file failed, so writes continue to it
+    Rotate {
+        /// The path of the current file
+        path: PathBuf,
+        /// Why rotating failed
+        source: io::Error,
+    },
+    /// Rotating the current file failed, so writes are held until a retry
+    /// succeeds, see
+    /// [`defer_on_rollover_failure`](crate::RollingFileAppenderBuilder::defer_on_rollover_failure)
+    RotateDeferred {
+        /// The path of the current file
+        path: PathBuf,
+        /// Why rotating failed
+        source: io::Error,
+    },
+    /// Opening the current file failed when building the appender, so it
+    /// discards all writes, see
+    /// [`allow_null_fallback`](crate::RollingFileAppenderBuilder::allow_null_fallback)
+    Unwritable {
+        /// The path of the current file
+        path: PathBuf,
+        /// Why opening failed
+        source: io::Error,
+    },
+    /// Compressing a rotated file failed even after retrying, see
+    /// [`compress_retry`](crate::RollingFileAppenderBuilder::compress_retry),
+    /// so it is left uncompressed until the next rollover tries again
+    Compress {
+        /// The path of the file that failed to be compressed
+        path: PathBuf,
+        /// Why compressing failed
+        source: io::Error,
+    },
+    /// Finishing a rotated file failed after it was rotated, i.e. writing its
+    /// checksum sidecar, making it read-only or creating its marker. The
+    /// rollover itself succeeded, so it isn't failed.
+    FinishRotated {
+        /// The path of the rotated file
+        path: PathBuf,
+        /// Why finishing failed
+        source: io::Error,
+    },
+    /// The current file is smaller on disk than the appender tracked, because
+    /// someone else truncated it, see
+    /// [`size_check_interval`](crate::RollingFileAppenderBuilder::size_check_interval).
+    /// The tracked size was resynced to the size on disk.
+    Truncated {
+        /// The path of the current file
+        path: PathBuf,
+        /// The size the appender tracked, including buffered data
+        tracked: u64,
+        /// The size on disk, without buffered data
+        on_disk: u64,
+    },
+    /// Flushing the current file after a write failed. The write itself
+    /// succeeded, as its data made it to the appender, so it isn't failed.
+    Flush {
+        /// The path of the current file
+        path: PathBuf,
+        /// Why flushing failed
+        source: io::Error,
+    },
+    /// Syncing the current file to disk after a write failed, see
+    /// [`sync_interval`](crate::RollingFileAppenderBuilder::sync_interval).
+    /// The write itself succeeded, so it isn't failed, and the next write
+    /// tries again.
+    Sync {
+        /// The path of the current file
+        path: PathBuf,
+        /// Why syncing failed
+        source: io::Error,
+    },
+    /// Copying a write to the error sink failed
+    ErrorSink {
+        /// The path of the error sink's current file
+        path: PathBuf,
+        /// Why writing failed
+        source: io::Error,
+    },
+    /// Writing a record queued on a [`NonBlocking`](crate::NonBlocking)
+    /// writer failed on its writer thread, so the record was lost
+    Write {
+        /// The path of the current file
+        path: PathBuf,
+        /// Why writing failed
+        source: io::Error,
+    },
+    /// Closing the current file failed when the writer thread of a
+    /// [`NonBlocking`](crate::NonBlocking) writer was stopped
+    Close {
+        /// The path of the current file
+        path: PathBuf,
+        /// Why closing failed
+        source: io::Error,
+    },
+    /// The writer thread of a [`NonBlocking`](crate::NonBlocking) writer
+    /// panicked, so the writes still queued were lost. If the handler panics
+    /// as well, the panic is printed to stderr instead.
+    WriterPanicked {
+        /// The path of the current file
+        path: PathBuf,
+    },
+    /// Notifying the socket of a rollover failed, which is only reported the
+    /// first time
+    NotifySocket {
+        /// The path of the socket
+        socket: PathBuf,
+        /// The path of the current file
+        path: PathBuf,
+        /// Why notifying failed
+        source: io::Error,
+    },
+    /// There was no current file to write to even though opening it
+    /// succeeded, which should never happen. Rather than being reported,
+    /// this fails the write, as the inner error of the returned
+    /// [`io::Error`], along with the state of the appender at the time.
+    WriterMissing {
+        /// The base filename of the appender
+        path: PathBuf,
+        /// Whether the appender discarded writes, see
+        /// [`allow_null_fallback`](crate::RollingFileAppenderBuilder::allow_null_fallback)
+        null: bool,
+        /// Whether the appender was degraded, see
+        /// [`strict_full_disk`](crate::RollingFileAppenderBuilder::strict_full_disk)
+        degraded: bool,
+        /// Whether the appender only rotated files, see
+        /// [`rotate_only`](crate::RollingFileAppenderBuilder::rotate_only)
+        rotate_only: bool,
+    },
+}
+
+impl fmt::Display for RollingFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RollingFileError::Rotate { path, source } => {
+                write!(f, "Failed to rotate logfile {}: {}", path.display(), source)
+            },
+            RollingFileError::RotateDeferred { path, source } => write!(
+                f,
+                "Failed to rotate logfile {}, deferring writes until it succeeds: {}",
+                path.display(),
+                source
+            ),
+            RollingFileError::Unwritable { path, source } => write!(
+                f,
+                "Failed to open logfile {}, discarding writes until it is reopened: {}",
+                path.display(),
+                source
+            ),
+            RollingFileError::Compress { path, source } => write!(
+                f,
+                "Failed to compress {}, leaving it uncompressed until the next rollover: {}",
+                path.display(),
+                source
+            ),
+            RollingFileError::FinishRotated { path, source } => {
+                write!(f, "Failed to finish rotated file {}: {}", path.display(), source)
+            },
+            RollingFileError::Truncated { path, tracked, on_disk } => write!(
+                f,
+                "Logfile {} was truncated to {} bytes on disk, resyncing its tracked size of {} bytes",
+                path.display(),
+                on_disk,
+                tracked
+            ),
+            RollingFileError::Flush { path, source } => {
+                write!(f, "Failed to flush logfile {}: {}", path.display(), source)
+            },
+            RollingFileError::Sync { path, source } => {
+                write!(f, "Failed to sync logfile {}: {}", path.display(), source)
+            },
+            RollingFileError::ErrorSink { path, source } => {
+                write!(f, "Failed to write to error sink {}: {}", path.display(), source)
+            },
+            RollingFileError::Write { path, source } => {
+                write!(f, "Failed to write to logfile {}: {}", path.display(), source)
+            },
+            RollingFileError::Close { path, source } => {
+                write!(f, "Failed to close logfile {}: {}", path.display(), source)
+            },
+            RollingFileError::WriterPanicked { path } => {
+                write!(f, "Rolling file writer thread for logfile {} panicked", path.display())
+            },
+            RollingFileError::NotifySocket { socket, path, source } => write!(
+                f,
+                "Failed to notify {} of the rotation of logfile {}: {}",
+                socket.display(),
+                path.display(),
+                source
+            ),
+            RollingFileError::WriterMissing {
+                path,
+                null,
+                degraded,
+                rotate_only,
+            } => write!(
+                f,
+                "No writer for logfile {} after opening it (null: {}, degraded: {}, rotate only: {})",
+                path.display(),
+                null,
+                degraded,
+                rotate_only
+            ),
+        }
+    }
+}
+
+impl error::Error for RollingFileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RollingFileError::Rotate { source, .. }
+            | RollingFileError::RotateDeferred { source, .. }
+            | RollingFileError::Unwritable { source, .. }
+            | RollingFileError::Compress { source, .. }
+            | RollingFileError::FinishRotated { source, .. }
+            | RollingFileError::Flush { source, .. }
+            | RollingFileError::Sync { source, .. }
+            | RollingFileError::ErrorSink { source, .. }
+            | RollingFileError::Write { source, .. }
+            | RollingFileError::Close { source, .. }
+            | RollingFileError::NotifySocket { source, .. } => Some(source),
+            RollingFileError::Truncated { .. }
+            | RollingFileError::WriterPanicked { .. }
+            | RollingFileError::WriterMissing { .. } => None,
+        }
+    }
+}
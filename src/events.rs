@@ -0,0 +1,110 @@
+//! Rollover events sent over a channel.
+use chrono::prelude::*;
+#[cfg(unix)]
+use std::{fmt::Write as _, io, io::Write, os::unix::net::UnixStream, path::Path, time};
+use std::{
+    path::PathBuf,
+    sync::mpsc::{Sender, SyncSender, TrySendError},
+};
+
+/// Why a rollover happened
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum RolloverReason {
+    /// The rolling condition asked for it because a new period started
+    Time,
+    /// The rolling condition asked for it because the file got too large
+    Size,
+    /// The rolling condition asked for it without telling why, see
+    /// [`RollingCondition::rollover_reason`](crate::RollingCondition::rollover_reason)
+    Condition,
+    /// Free disk space fell below `min_free_bytes`
+    LowDiskSpace,
+    /// The trigger file set with
+    /// [`roll_on_trigger_file`](crate::RollingFileAppenderBuilder::roll_on_trigger_file)
+    /// appeared
+    TriggerFile,
+    /// [`RollingFileAppender::rollover`](crate::RollingFileAppender::rollover)
+    /// was called
+    Manual,
+}
+
+/// Describes a rollover that happened, see
+/// [`RollingFileAppenderBuilder::rollover_events`](crate::RollingFileAppenderBuilder::rollover_events).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RolloverEvent {
+    /// The path the previous current file was archived to, ending in `.gz`
+    /// if it was compressed right away
+    pub path: PathBuf,
+    /// When the rollover happened, i.e. the datetime of the write that
+    /// triggered it, or the current time for a manual rollover
+    pub timestamp: DateTime<Local>,
+    /// Why the rollover happened
+    pub reason: RolloverReason,
+}
+
+/// The sending side of a channel of rollover events.
+#[derive(Debug)]
+pub(crate) enum EventSender {
+    Unbounded(Sender<RolloverEvent>),
+    Bounded(SyncSender<RolloverEvent>),
+}
+
+impl EventSender {
+    /// Sends the event without blocking, dropping it if the channel is full.
+    /// Returns false if the receiver is gone.
+    pub(crate) fn send(&self, event: RolloverEvent) -> bool {
+        match self {
+            EventSender::Unbounded(tx) => tx.send(event).is_ok(),
+            EventSender::Bounded(tx) => !matches!(tx.try_send(event), Err(TrySendError::Disconnected(_))),
+        }
+    }
+}
+
+/// Sends a one-line JSON notification of a rollover to the Unix domain socket
+/// at the given path, e.g. `{"rolled":"/var/log/app.log.1","ts":"2021-03-30T01:02:03+00:00"}`.
+#[cfg(unix)]
+pub(crate) fn notify_socket(socket: &Path, archived: &Path, now: &DateTime<Local>) -> io::Result<()> {
+    let mut stream = UnixStream::connect(socket)?;
+    // a stuck listener must not hold up writing the logs
+    stream.set_write_timeout(Some(time::Duration::from_millis(100)))?;
+    let line = format!(
+        "{{\"rolled\":{},\"ts\":{}}}\n",
+        json_string(&archived.to_string_lossy()),
+        json_string(&now.to_rfc3339())
+    );
+    stream.write_all(line.as_bytes())
+}
+
+/// Quotes and escapes a string for JSON.
+#[cfg(unix)]
+fn json_string(x: &str) -> String {
+    let mut quoted = String::with_capacity(x.len() + 2);
+    quoted.push('"');
+    for c in x.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            },
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// LCOV_EXCL_START
+#[cfg(all(test, unix))]
+mod t {
+    use super::*;
+
+    #[test]
+    fn json_strings() {
+        assert_eq!(json_string("app.log.1"), "\"app.log.1\"");
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+}
+// LCOV_EXCL_STOP
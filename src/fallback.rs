@@ -0,0 +1,71 @@
+//! A bounded in-memory buffer for writes while the file is unavailable.
+use std::collections::VecDeque;
+
+/// Holds the most recent bytes that couldn't be written, up to a capacity,
+/// dropping the oldest ones beyond it.
+#[derive(Debug)]
+pub(crate) struct FallbackBuffer {
+    data: VecDeque<u8>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl FallbackBuffer {
+    /// Creates an empty buffer holding up to the given number of bytes.
+    pub(crate) fn new(capacity: usize) -> FallbackBuffer {
+        FallbackBuffer {
+            data: VecDeque::new(),
+            capacity,
+            dropped: 0,
+        }
+    }
+
+    /// Appends the data, dropping the oldest bytes if it overflows.
+    pub(crate) fn push(&mut self, buf: &[u8]) {
+        let keep = &buf[buf.len().saturating_sub(self.capacity)..];
+        let overflow = (self.data.len() + keep.len()).saturating_sub(self.capacity);
+        self.data.drain(..overflow);
+        self.data.extend(keep);
+        self.dropped += (overflow + buf.len() - keep.len()) as u64;
+    }
+
+    /// Returns the maximum number of bytes held.
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns whether there is nothing buffered.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Takes all buffered bytes, oldest first.
+    pub(crate) fn take(&mut self) -> Vec<u8> {
+        self.data.drain(..).collect()
+    }
+
+    /// Returns the number of bytes dropped so far because the buffer was
+    /// full.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    #[test]
+    fn overflow() {
+        let mut buffer = FallbackBuffer::new(8);
+        buffer.push(b"12345");
+        buffer.push(b"6789");
+        assert_eq!(buffer.dropped(), 1);
+        buffer.push(b"abcdefghij");
+        assert_eq!(buffer.dropped(), 11);
+        assert_eq!(buffer.take(), b"cdefghij");
+        assert!(buffer.is_empty());
+    }
+}
+// LCOV_EXCL_STOP
@@ -0,0 +1,201 @@
+//! The file operations the appender rotates files with, which can be replaced
+//! e.g. to test rotation without touching the disk.
+use crate::disk;
+use std::{ffi::OsString, fmt, fs, fs::OpenOptions, io, io::Write, path::Path, time::SystemTime};
+
+/// The file operations used to write and rotate the files, see
+/// [`RollingFileAppenderBuilder::file_system`](crate::RollingFileAppenderBuilder::file_system).
+/// [`RealFileSystem`] implements them on the disk.
+pub trait FileSystem: fmt::Debug + Send + Sync {
+    /// Opens the file at the given path for appending, creating it if needed.
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+
+    /// Renames a file, replacing the destination if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Removes a file.
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Returns the size and modification time of a file, or a `NotFound`
+    /// error if it doesn't exist.
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+
+    /// Returns the names of the entries of a directory.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>>;
+}
+
+/// What the appender needs to know about a file
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FileMetadata {
+    /// The size of the file in bytes
+    pub len: u64,
+    /// When the file was last modified
+    pub modified: SystemTime,
+}
+
+/// The file system on disk, used by default
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(OpenOptions::new().append(true).create(true).open(path)?))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        disk::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let m = fs::metadata(path)?;
+        Ok(FileMetadata {
+            len: m.len(),
+            modified: m.modified()?,
+        })
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        fs::read_dir(path)?.map(|entry| Ok(entry?.file_name())).collect()
+    }
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::{BasicRollingFileAppender, RollingConditionBasic};
+    use chrono::Local;
+    use std::{
+        collections::HashMap,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    /// Files kept in memory
+    #[derive(Debug, Default)]
+    struct MemoryFileSystem {
+        files: Mutex<HashMap<PathBuf, Arc<Mutex<Vec<u8>>>>>,
+    }
+
+    impl MemoryFileSystem {
+        fn contents(&self, path: &Path) -> Option<Vec<u8>> {
+            self.files.lock().unwrap().get(path).map(|f| f.lock().unwrap().clone())
+        }
+    }
+
+    #[derive(Debug)]
+    struct MemoryFile(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for MemoryFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl FileSystem for MemoryFileSystem {
+        fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+            let mut files = self.files.lock().unwrap();
+            let file = files.entry(path.to_path_buf()).or_default();
+            Ok(Box::new(MemoryFile(Arc::clone(file))))
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            let file = files.remove(from).ok_or(io::ErrorKind::NotFound)?;
+            files.insert(to.to_path_buf(), file);
+            Ok(())
+        }
+
+        fn remove_file(&self, path: &Path) -> io::Result<()> {
+            self.files
+                .lock()
+                .unwrap()
+                .remove(path)
+                .map(|_| ())
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+            let files = self.files.lock().unwrap();
+            let file = files.get(path).ok_or(io::ErrorKind::NotFound)?;
+            let len = file.lock().unwrap().len() as u64;
+            Ok(FileMetadata {
+                len,
+                modified: SystemTime::now(),
+            })
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+            let files = self.files.lock().unwrap();
+            Ok(files
+                .keys()
+                .filter(|p| p.parent() == Some(path))
+                .filter_map(|p| p.file_name().map(|n| n.to_os_string()))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn in_memory() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let memory = Arc::new(MemoryFileSystem::default());
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().max_size(5), 2)
+            .file_system(Arc::clone(&memory) as Arc<dyn FileSystem>)
+            .build()
+            .unwrap();
+        let now = Local::now();
+        for line in &["Line 1\n", "Line 2\n", "Line 3\n", "Line 4\n"] {
+            rolling.write_with_datetime(line.as_bytes(), &now).unwrap();
+        }
+        rolling.flush().unwrap();
+        assert_eq!(memory.contents(&path).unwrap(), b"Line 4\n");
+        assert_eq!(
+            memory.contents(&tempdir.path().join("test.log.1")).unwrap(),
+            b"Line 3\n"
+        );
+        assert_eq!(
+            memory.contents(&tempdir.path().join("test.log.2")).unwrap(),
+            b"Line 2\n"
+        );
+        assert_eq!(memory.contents(&tempdir.path().join("test.log.3")), None);
+        // nothing reached the disk
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn trigger_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let trigger = tempdir.path().join("test.roll");
+        let memory = Arc::new(MemoryFileSystem::default());
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 2)
+            .file_system(Arc::clone(&memory) as Arc<dyn FileSystem>)
+            .roll_on_trigger_file(trigger.clone())
+            .build()
+            .unwrap();
+        let now = Local::now();
+        rolling.write_with_datetime(b"Line 1\n", &now).unwrap();
+        memory.open_append(&trigger).unwrap();
+        rolling.write_with_datetime(b"Line 2\n", &now).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(memory.contents(&path).unwrap(), b"Line 2\n");
+        assert_eq!(
+            memory.contents(&tempdir.path().join("test.log.1")).unwrap(),
+            b"Line 1\n"
+        );
+        // the trigger file was taken from the file system too
+        assert_eq!(memory.contents(&trigger), None);
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+}
+// LCOV_EXCL_STOP
@@ -0,0 +1,81 @@
+//! An adapter to write formatted text to an appender.
+use std::{fmt, io};
+
+/// Implements [`std::fmt::Write`] on top of an appender (or anything else
+/// implementing [`std::io::Write`], e.g. `&mut` an appender or a
+/// [`SharedRollingFileAppender`](crate::SharedRollingFileAppender)), so that
+/// text can be written with `write!` without going through bytes manually.
+/// Each `&str` is written as UTF-8.
+///
+/// Since `fmt::Error` carries no information, the detail of any I/O error is
+/// lost in the conversion; write to the appender directly where it matters.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn docs() {
+/// # use rolling_file::*;
+/// use std::fmt::Write;
+/// let mut file_appender = BasicRollingFileAppender::new(
+///     "/var/log/myprogram",
+///     RollingConditionBasic::new().daily(),
+///     9
+/// ).unwrap();
+/// let mut writer = FmtRollingWriter::new(&mut file_appender);
+/// writeln!(writer, "{} + {} = {}", 1, 2, 1 + 2).unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FmtRollingWriter<W> {
+    inner: W,
+}
+
+impl<W> FmtRollingWriter<W>
+where
+    W: io::Write,
+{
+    /// Creates a new adapter writing to the given writer.
+    pub fn new(inner: W) -> FmtRollingWriter<W> {
+        FmtRollingWriter { inner }
+    }
+
+    /// Gets a mutable reference to the underlying writer
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W> fmt::Write for FmtRollingWriter<W>
+where
+    W: io::Write,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::{BasicRollingFileAppender, RollingConditionBasic};
+    use std::{fmt::Write, fs, io::Write as _};
+
+    #[test]
+    fn write_formatted() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::new(&path, RollingConditionBasic::new(), 9).unwrap();
+        let mut writer = FmtRollingWriter::new(&mut rolling);
+        write!(writer, "{} + {}", 1, 2).unwrap();
+        writeln!(writer, " = {} ✓", 1 + 2).unwrap();
+        writer.into_inner().flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1 + 2 = 3 ✓\n");
+    }
+}
+// LCOV_EXCL_STOP
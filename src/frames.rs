@@ -0,0 +1,112 @@
+//! Length-prefixed framing of binary records.
+use std::{convert::TryFrom, io, io::Read};
+
+/// How the length of each record is written before it, see
+/// [`RollingFileAppenderBuilder::length_prefixed`](crate::RollingFileAppenderBuilder::length_prefixed)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum LengthPrefix {
+    /// A 32-bit little-endian unsigned integer
+    U32LE,
+}
+
+impl LengthPrefix {
+    /// Returns the record prefixed with its length, or an `InvalidInput`
+    /// error if it is too long for the prefix.
+    pub(crate) fn frame(self, record: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            LengthPrefix::U32LE => {
+                let len = u32::try_from(record.len()).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "record of {} bytes is too long for a 32-bit length prefix",
+                            record.len()
+                        ),
+                    )
+                })?;
+                let mut framed = Vec::with_capacity(4 + record.len());
+                framed.extend_from_slice(&len.to_le_bytes());
+                framed.extend_from_slice(record);
+                Ok(framed)
+            },
+        }
+    }
+}
+
+/// An iterator over the length-prefixed records of a stream, e.g. of a
+/// [`RollingFileReader`](crate::RollingFileReader), created by
+/// [`RollingFileReader::frames`](crate::RollingFileReader::frames). A stream
+/// ending in the middle of a record yields an `UnexpectedEof` error.
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    inner: R,
+    prefix: LengthPrefix,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Creates a reader of the records of the given stream.
+    pub fn new(inner: R, prefix: LengthPrefix) -> FrameReader<R> {
+        FrameReader { inner, prefix }
+    }
+
+    /// Reads the next record, returning None at the end of the stream.
+    fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let len = match self.prefix {
+            LengthPrefix::U32LE => {
+                let mut header = [0; 4];
+                if !read_exact_or_eof(&mut self.inner, &mut header)? {
+                    return Ok(None);
+                }
+                u32::from_le_bytes(header) as usize
+            },
+        };
+        let mut record = vec![0; len];
+        self.inner.read_exact(&mut record)?;
+        Ok(Some(record))
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Fills the buffer, returning false if the stream ends before any byte is
+/// read, and an `UnexpectedEof` error if it ends after some were.
+fn read_exact_or_eof<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    #[test]
+    fn truncated() {
+        let mut data = LengthPrefix::U32LE.frame(b"abc").unwrap();
+        data.extend(LengthPrefix::U32LE.frame(b"defg").unwrap());
+        assert_eq!(&data[..4], &[3, 0, 0, 0]);
+        for cut in [data.len() - 1, 9] {
+            let frames: Vec<_> = FrameReader::new(&data[..cut], LengthPrefix::U32LE).collect();
+            assert_eq!(frames.len(), 2);
+            assert_eq!(frames[0].as_ref().unwrap(), b"abc");
+            assert_eq!(frames[1].as_ref().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+}
+// LCOV_EXCL_STOP
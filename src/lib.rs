@@ -6,6 +6,9 @@
 //! using basename, basename.1, ..., basename.N where N is
 //! the maximum number of allowed historical logfiles.
 //!
+//! Rotated files can optionally be compressed with gzip,
+//! in which case they are named basename.N.gz.
+//!
 //! This is useful to combine with the tracing crate and
 //! tracing_appender::non_blocking::NonBlocking -- use it
 //! as an alternative to tracing_appender::rolling::RollingFileAppender.
@@ -22,49 +25,250 @@
 //! ).unwrap();
 //! # }
 //! ```
+//!
+//! # Features
+//!
+//! - `mmap`: enables [`RollingFileAppender::new_mmap`], which writes the active file through a memory-mapped
+//!   region.
+//! - `checksum`: enables writing a `.sha256` sidecar for each rotated file, see
+//!   [`RollingFileAppenderBuilder::checksum`].
 #![deny(warnings)]
 
-use chrono::prelude::*;
+use chrono::{prelude::*, Duration};
 use std::{
     convert::TryFrom,
-    ffi::OsString,
+    ffi::{OsStr, OsString},
+    fmt,
     fs,
     fs::{File, OpenOptions},
     io,
     io::{BufWriter, Write},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Sender, SyncSender},
+        Arc,
+    },
 };
 
+mod cell;
+#[cfg(feature = "checksum")]
+mod checksum;
+mod compression;
+mod dated;
+#[cfg(target_os = "linux")]
+mod direct;
+mod disk;
+mod encoder;
+mod error;
+mod events;
+mod fallback;
+mod filesystem;
+mod fmt_writer;
+mod frames;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod non_blocking;
+mod reader;
+mod sharded;
+mod shared;
+mod spec;
+
+pub use cell::RollingFileAppenderCell;
+#[cfg(feature = "checksum")]
+pub use checksum::{verify_checksum, Checksum};
+pub use compression::{BlockGzipReader, Compression};
+pub use encoder::{Encoder, GzipEncoder};
+pub use error::RollingFileError;
+pub use events::{RolloverEvent, RolloverReason};
+pub use filesystem::{FileMetadata, FileSystem, RealFileSystem};
+pub use fmt_writer::FmtRollingWriter;
+pub use frames::{FrameReader, LengthPrefix};
+pub use non_blocking::{NonBlocking, Priority, WhenFull, WorkerGuard};
+pub use reader::RollingFileReader;
+pub use sharded::{shard_path, ShardedRollingAppender};
+#[cfg(unix)]
+pub use shared::register_flush_at_exit;
+pub use shared::{PanicHook, SharedRollingFileAppender};
+pub use spec::SpecError;
+
 /// Determines when a file should be "rolled over".
 pub trait RollingCondition {
-    /// Determine and return whether or not the file should be rolled over.
+    /// Determine and return whether or not the file should be rolled over
+    /// before writing data.
     fn should_rollover(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool;
+
+    /// Determine and return whether or not the file should be rolled over
+    /// right after writing data, given the file size including that data.
+    /// Never rolls over by default.
+    fn should_rollover_after_write(&mut self, _now: &DateTime<Local>, _current_filesize: u64) -> bool {
+        false
+    }
+
+    /// Informs the condition of when an existing file was last written to,
+    /// e.g. by a previous run of the program. Does nothing by default.
+    fn seed_last_write(&mut self, _last_write: &DateTime<Local>) {}
+
+    /// Informs the condition that the given number of bytes was written to
+    /// the current file. Does nothing by default.
+    fn on_write(&mut self, _len: u64) {}
+
+    /// Informs the condition that the current file was rotated, whatever the
+    /// reason. Does nothing by default.
+    fn on_rollover(&mut self) {}
+
+    /// Returns whether the condition looks at the datetime of writes. If not,
+    /// writes through [`io::Write`] may skip getting the current time, and
+    /// pass the condition the one last taken instead, e.g. for a rollover.
+    /// Returns true by default.
+    fn needs_time(&self) -> bool {
+        true
+    }
+
+    /// Returns why the condition last asked for a rollover, if it can tell.
+    /// Returns `None` by default, in which case the rollover is reported
+    /// with [`RolloverReason::Condition`].
+    fn rollover_reason(&self) -> Option<RolloverReason> {
+        None
+    }
+
+    /// Returns the file size the condition rolls over at, if it rolls over
+    /// by size, so that the appender can check its configuration. Returns
+    /// `None` by default.
+    fn max_size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the starts of the periods that passed without any write
+    /// between the last write and the given datetime, oldest first, so that
+    /// an appender with
+    /// [`fill_gaps`](RollingFileAppenderBuilder::fill_gaps) set creates an
+    /// empty file for each. Returns none by default.
+    fn skipped_periods(&self, _now: &DateTime<Local>) -> Vec<DateTime<Local>> {
+        Vec::new()
+    }
+
+    /// Returns the current time, to evaluate the condition at when there is
+    /// no write to take the datetime of, e.g. on a flush with
+    /// [`roll_on_flush`](RollingFileAppenderBuilder::roll_on_flush) set.
+    /// Returns the system time by default.
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// Provides the current date and time, and can be replaced to fake the time
+/// in tests, see [`RollingFileAppenderBuilder::clock`].
+pub trait Clock {
+    /// Returns the current date and time.
+    fn now(&self) -> DateTime<Local>;
+}
+
+/// A clock returning the current system time
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
 }
 
 /// Determines how often a file should be rolled over
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum RollingFrequency {
     EveryDay,
     EveryHour,
     EveryMinute,
+    /// Rolls over when the date changes in UTC, regardless of the local
+    /// timezone, e.g. to match the day boundaries of centralized logging.
+    EveryUtcDay,
 }
 
 impl RollingFrequency {
     /// Calculates a datetime that will be different if data should be in
-    /// different files.
-    pub fn equivalent_datetime(&self, dt: &DateTime<Local>) -> DateTime<Local> {
-        match self {
-            RollingFrequency::EveryDay => Local.ymd(dt.year(), dt.month(), dt.day()).and_hms(0, 0, 0),
-            RollingFrequency::EveryHour => Local.ymd(dt.year(), dt.month(), dt.day()).and_hms(dt.hour(), 0, 0),
-            RollingFrequency::EveryMinute => {
-                Local
-                    .ymd(dt.year(), dt.month(), dt.day())
-                    .and_hms(dt.hour(), dt.minute(), 0)
+    /// different files. Works in the timezone of the given datetime, e.g.
+    /// [`Local`] or a [`FixedOffset`], and never panics: if the start of the
+    /// period doesn't exist in that timezone (e.g. it was skipped by a DST
+    /// change), the period starts at the end of the skipped time instead.
+    pub fn equivalent_datetime<Tz: TimeZone>(&self, dt: &DateTime<Tz>) -> DateTime<Tz> {
+        let (hour, minute) = match self {
+            RollingFrequency::EveryDay => (0, 0),
+            RollingFrequency::EveryHour => (dt.hour(), 0),
+            RollingFrequency::EveryMinute => (dt.hour(), dt.minute()),
+            RollingFrequency::EveryUtcDay => {
+                let start = dt.naive_utc().date().and_time(NaiveTime::default());
+                return dt.timezone().from_utc_datetime(&start);
+            },
+        };
+        let start = dt
+            .date_naive()
+            .and_time(NaiveTime::from_hms_opt(hour, minute, 0).unwrap_or_default());
+        let tz = dt.timezone();
+        match tz.from_local_datetime(&start).earliest() {
+            Some(x) => x,
+            // The start of the period was skipped, e.g. by a DST change, so
+            // the period starts when the skipped time ends, i.e. at the start
+            // taken at the offset from before the change.
+            None => {
+                let seconds = |x: FixedOffset| Duration::seconds(i64::from(x.local_minus_utc()));
+                let skipped_at_opt = start
+                    .checked_sub_signed(seconds(dt.offset().fix()))
+                    .map(|x| tz.offset_from_utc_datetime(&x).fix())
+                    .and_then(|before| start.checked_sub_signed(seconds(before)));
+                match skipped_at_opt {
+                    Some(x) => tz.from_utc_datetime(&x),
+                    None => dt.clone(),
+                }
             },
         }
     }
 }
 
+impl fmt::Display for RollingFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RollingFrequency::EveryDay => "daily",
+            RollingFrequency::EveryHour => "hourly",
+            RollingFrequency::EveryMinute => "every minute",
+            RollingFrequency::EveryUtcDay => "daily (UTC)",
+        })
+    }
+}
+
+impl RollingFrequency {
+    /// Calculates the start of the period following the one starting at the
+    /// given datetime.
+    fn next_period_start(&self, start: &DateTime<Local>) -> DateTime<Local> {
+        let length = match self {
+            RollingFrequency::EveryDay | RollingFrequency::EveryUtcDay => Duration::days(1),
+            RollingFrequency::EveryHour => Duration::hours(1),
+            RollingFrequency::EveryMinute => Duration::minutes(1),
+        };
+        // Landing halfway into the next period is safe from DST changes
+        // shortening or lengthening a period, unless the hour repeats.
+        let next = self.equivalent_datetime(&(*start + length + length / 2));
+        if next > *start {
+            next
+        } else {
+            *start + length
+        }
+    }
+}
+
+/// Determines whether a rollover happens before or after the write that
+/// triggers it
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RolloverTiming {
+    /// Roll over before writing, so the triggering write starts the new file.
+    /// This is the default.
+    #[default]
+    BeforeWrite,
+    /// Roll over right after writing, so the triggering write ends the old
+    /// file.
+    AfterWrite,
+}
+
 /// Implements a rolling condition based on a certain frequency
 /// and/or a size limit. The default condition is to rotate daily.
 ///
@@ -78,8 +282,15 @@ impl RollingFrequency {
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct RollingConditionBasic {
     last_write_opt: Option<DateTime<Local>>,
+    file_start_opt: Option<DateTime<Local>>,
     frequency_opt: Option<RollingFrequency>,
     max_size_opt: Option<u64>,
+    min_duration_opt: Option<Duration>,
+    min_size_before_time_roll_opt: Option<u64>,
+    time_timing: RolloverTiming,
+    size_timing: RolloverTiming,
+    time_rollover_pending: bool,
+    last_reason_opt: Option<RolloverReason>,
 }
 
 impl RollingConditionBasic {
@@ -87,8 +298,15 @@ impl RollingConditionBasic {
     pub fn new() -> RollingConditionBasic {
         RollingConditionBasic {
             last_write_opt: None,
+            file_start_opt: None,
             frequency_opt: None,
             max_size_opt: None,
+            min_duration_opt: None,
+            min_size_before_time_roll_opt: None,
+            time_timing: RolloverTiming::default(),
+            size_timing: RolloverTiming::default(),
+            time_rollover_pending: false,
+            last_reason_opt: None,
         }
     }
 
@@ -104,17 +322,78 @@ impl RollingConditionBasic {
         self
     }
 
+    /// Sets a condition to rollover when the date changes in UTC
+    pub fn daily_utc(mut self) -> RollingConditionBasic {
+        self.frequency_opt = Some(RollingFrequency::EveryUtcDay);
+        self
+    }
+
     /// Sets a condition to rollover when the date or hour changes
     pub fn hourly(mut self) -> RollingConditionBasic {
         self.frequency_opt = Some(RollingFrequency::EveryHour);
         self
     }
 
-    /// Sets a condition to rollover when a certain size is reached
+    /// Sets a condition to rollover when a certain size is reached, which
+    /// must be greater than zero. An appender with a `max_size` of zero fails
+    /// to build.
     pub fn max_size(mut self, x: u64) -> RollingConditionBasic {
         self.max_size_opt = Some(x);
         self
     }
+
+    /// Sets whether a frequency-based rollover happens before the first write
+    /// belonging to a new period (the default), so that it starts the new
+    /// file, or right after it, so that it ends the old file.
+    pub fn time_timing(mut self, x: RolloverTiming) -> RollingConditionBasic {
+        self.time_timing = x;
+        self
+    }
+
+    /// Sets whether a size-based rollover happens before the next write once
+    /// the file reached the size limit (the default), or right after the
+    /// write reaching the limit, so that the current file never stays at or
+    /// above the limit. Either way, the write reaching the limit ends up in
+    /// the old file.
+    pub fn size_timing(mut self, x: RolloverTiming) -> RollingConditionBasic {
+        self.size_timing = x;
+        self
+    }
+
+    /// Sets the time of the last write, e.g. to the modification time of an
+    /// existing file on startup, so that the first write can roll over if it
+    /// belongs to a different period.
+    pub fn last_write(mut self, x: DateTime<Local>) -> RollingConditionBasic {
+        self.last_write_opt = Some(x);
+        self
+    }
+
+    /// Sets a minimum duration that a file must cover before a
+    /// frequency-based rollover may happen, to avoid tiny files (e.g. when
+    /// starting up just before midnight). The duration is measured from the
+    /// first write seen after the previous rollover. Size-based rollovers are
+    /// not affected.
+    pub fn min_duration(mut self, x: Duration) -> RollingConditionBasic {
+        self.min_duration_opt = Some(x);
+        self
+    }
+
+    /// Sets a minimum size that a file must reach before a frequency-based
+    /// rollover may happen, so that quiet periods keep appending to the same
+    /// file instead of producing many tiny ones. Once the file reaches the
+    /// minimum, it rolls over on the first write in a period other than the
+    /// one it was started in. A `max_size` still forces a rollover.
+    pub fn min_size_before_time_roll(mut self, x: u64) -> RollingConditionBasic {
+        self.min_size_before_time_roll_opt = Some(x);
+        self
+    }
+
+    /// Wraps the condition in a [`MaintenanceWindow`], deferring its
+    /// frequency-based rollovers until a write between `start` and `end`.
+    /// This has to be the last setting.
+    pub fn maintenance_window(self, start: NaiveTime, end: NaiveTime) -> MaintenanceWindow<RollingConditionBasic> {
+        MaintenanceWindow::new(self, start, end)
+    }
 }
 
 impl Default for RollingConditionBasic {
@@ -123,373 +402,7762 @@ impl Default for RollingConditionBasic {
     }
 }
 
+impl fmt::Display for RollingConditionBasic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(frequency) = self.frequency_opt {
+            parts.push(frequency.to_string());
+        }
+        if let Some(max_size) = self.max_size_opt {
+            parts.push(format!("max size {} bytes", max_size));
+        }
+        if let Some(min_duration) = self.min_duration_opt {
+            parts.push(format!("min duration {}s", min_duration.num_seconds()));
+        }
+        if let Some(min_size) = self.min_size_before_time_roll_opt {
+            parts.push(format!("min size {} bytes", min_size));
+        }
+        if parts.is_empty() {
+            f.write_str("never")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
 impl RollingCondition for RollingConditionBasic {
     fn should_rollover(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool {
-        let mut rollover = false;
+        let mut time_rollover = false;
         if let Some(frequency) = self.frequency_opt.as_ref() {
-            if let Some(last_write) = self.last_write_opt.as_ref() {
-                if frequency.equivalent_datetime(now) != frequency.equivalent_datetime(last_write) {
-                    rollover = true;
+            // With a minimum duration or size, a suppressed rollover must
+            // still happen later on, so compare against the start of the file
+            // rather than the last write.
+            let since_opt = if self.min_duration_opt.is_some() || self.min_size_before_time_roll_opt.is_some() {
+                self.file_start_opt.as_ref()
+            } else {
+                self.last_write_opt.as_ref()
+            };
+            if let Some(since) = since_opt {
+                if frequency.equivalent_datetime(now) != frequency.equivalent_datetime(since) {
+                    time_rollover = match self.min_duration_opt {
+                        Some(min_duration) => now.signed_duration_since(*since) >= min_duration,
+                        None => true,
+                    };
+                    if let Some(min_size) = self.min_size_before_time_roll_opt {
+                        time_rollover &= current_filesize >= min_size;
+                    }
                 }
             }
         }
+        let mut rollover = time_rollover && self.time_timing == RolloverTiming::BeforeWrite;
+        if rollover {
+            self.last_reason_opt = Some(RolloverReason::Time);
+        }
         if let Some(max_size) = self.max_size_opt.as_ref() {
-            if current_filesize >= *max_size {
+            if !rollover && current_filesize >= *max_size && self.size_timing == RolloverTiming::BeforeWrite {
                 rollover = true;
+                self.last_reason_opt = Some(RolloverReason::Size);
             }
         }
+        self.time_rollover_pending = time_rollover && !rollover && self.time_timing == RolloverTiming::AfterWrite;
+        if rollover || self.file_start_opt.is_none() {
+            self.file_start_opt = Some(*now);
+        }
         self.last_write_opt = Some(*now);
         rollover
     }
+
+    fn should_rollover_after_write(&mut self, _now: &DateTime<Local>, current_filesize: u64) -> bool {
+        let mut rollover = std::mem::take(&mut self.time_rollover_pending);
+        if rollover {
+            self.last_reason_opt = Some(RolloverReason::Time);
+        }
+        if let Some(max_size) = self.max_size_opt.as_ref() {
+            if !rollover && current_filesize >= *max_size && self.size_timing == RolloverTiming::AfterWrite {
+                rollover = true;
+                self.last_reason_opt = Some(RolloverReason::Size);
+            }
+        }
+        if rollover {
+            // the new file starts with the next write
+            self.file_start_opt = None;
+        }
+        rollover
+    }
+
+    fn seed_last_write(&mut self, last_write: &DateTime<Local>) {
+        if self.last_write_opt.is_none() {
+            self.last_write_opt = Some(*last_write);
+        }
+        if self.file_start_opt.is_none() {
+            self.file_start_opt = Some(*last_write);
+        }
+    }
+
+    fn needs_time(&self) -> bool {
+        self.frequency_opt.is_some()
+    }
+
+    fn rollover_reason(&self) -> Option<RolloverReason> {
+        self.last_reason_opt
+    }
+
+    fn max_size(&self) -> Option<u64> {
+        self.max_size_opt
+    }
+
+    fn skipped_periods(&self, now: &DateTime<Local>) -> Vec<DateTime<Local>> {
+        let mut skipped = Vec::new();
+        if let (Some(frequency), Some(last_write)) = (self.frequency_opt.as_ref(), self.last_write_opt.as_ref()) {
+            let end = frequency.equivalent_datetime(now);
+            let mut start = frequency.next_period_start(&frequency.equivalent_datetime(last_write));
+            while start < end {
+                skipped.push(start);
+                start = frequency.next_period_start(&start);
+            }
+        }
+        skipped
+    }
 }
 
-/// Writes data to a file, and "rolls over" to preserve older data in
-/// a separate set of files. Old files have a Debian-style naming scheme
-/// where we have base_filename, base_filename.1, ..., base_filename.N
-/// where N is the maximum number of rollover files to keep.
-#[derive(Debug)]
-pub struct RollingFileAppender<RC>
+/// Wraps any rolling condition, and suppresses further rollovers for a while
+/// after the inner condition triggered one, based on the datetime of the
+/// writes. This lets the new file grow for a bit when bursts of writes keep
+/// crossing a size limit, instead of producing a cluster of tiny files. The
+/// inner condition is not consulted while rollovers are suppressed.
+///
+/// # Examples
+///
+/// ```rust
+/// use chrono::Duration;
+/// use rolling_file::*;
+/// let c = Debounced::new(RollingConditionBasic::new().max_size(1024 * 1024), Duration::seconds(10));
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Debounced<RC> {
+    inner: RC,
+    period: Duration,
+    last_rollover_opt: Option<DateTime<Local>>,
+}
+
+impl<RC> Debounced<RC>
 where
     RC: RollingCondition,
 {
-    condition: RC,
-    base_filename: OsString,
-    max_files: usize,
-    current_filesize: u64,
-    writer_opt: Option<BufWriter<File>>,
+    /// Wraps the given condition, suppressing rollovers for the given period
+    /// after each rollover.
+    pub fn new(inner: RC, period: Duration) -> Debounced<RC> {
+        Debounced {
+            inner,
+            period,
+            last_rollover_opt: None,
+        }
+    }
+
+    /// Returns the wrapped condition
+    pub fn inner(&self) -> &RC {
+        &self.inner
+    }
 }
 
-impl<RC> RollingFileAppender<RC>
+impl<RC> fmt::Display for Debounced<RC>
 where
-    RC: RollingCondition,
+    RC: fmt::Display,
 {
-    /// Creates a new rolling file appender with the given condition.
-    /// The parent directory of the base path must already exist.
-    pub fn new<P>(path: P, condition: RC, max_files: usize) -> io::Result<RollingFileAppender<RC>>
-    where
-        P: AsRef<Path>,
-    {
-        let mut rfa = RollingFileAppender {
-            condition,
-            base_filename: path.as_ref().as_os_str().to_os_string(),
-            max_files,
-            current_filesize: 0,
-            writer_opt: None,
-        };
-        // Fail if we can't open the file initially...
-        rfa.open_writer_if_needed()?;
-        Ok(rfa)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, debounced {}s", self.inner, self.period.num_seconds())
     }
+}
 
-    /// Determines the final filename, where n==0 indicates the current file
-    fn filename_for(&self, n: usize) -> OsString {
-        let mut f = self.base_filename.clone();
-        if n > 0 {
-            f.push(OsString::from(format!(".{}", n)))
+impl<RC> RollingCondition for Debounced<RC>
+where
+    RC: RollingCondition,
+{
+    fn should_rollover(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool {
+        if let Some(last_rollover) = self.last_rollover_opt {
+            if now.signed_duration_since(last_rollover) < self.period {
+                return false;
+            }
         }
-        f
+        let rollover = self.inner.should_rollover(now, current_filesize);
+        if rollover {
+            self.last_rollover_opt = Some(*now);
+        }
+        rollover
     }
 
-    /// Rotates old files to make room for a new one.
-    /// This may result in the deletion of the oldest file
-    fn rotate_files(&mut self) -> io::Result<()> {
-        // ignore any failure removing the oldest file (may not exist)
-        let _ = fs::remove_file(self.filename_for(self.max_files.max(1)));
-        let mut r = Ok(());
-        for i in (0..self.max_files.max(1)).rev() {
-            let rotate_from = self.filename_for(i);
-            let rotate_to = self.filename_for(i + 1);
-            if let Err(e) = fs::rename(&rotate_from, &rotate_to).or_else(|e| match e.kind() {
-                io::ErrorKind::NotFound => Ok(()),
-                _ => Err(e),
-            }) {
-                // capture the error, but continue the loop,
-                // to maximize ability to rename everything
-                r = Err(e);
+    fn should_rollover_after_write(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool {
+        if let Some(last_rollover) = self.last_rollover_opt {
+            if now.signed_duration_since(last_rollover) < self.period {
+                return false;
             }
         }
-        r
+        let rollover = self.inner.should_rollover_after_write(now, current_filesize);
+        if rollover {
+            self.last_rollover_opt = Some(*now);
+        }
+        rollover
     }
 
-    /// Forces a rollover to happen immediately.
-    pub fn rollover(&mut self) -> io::Result<()> {
-        // Before closing, make sure all data is flushed successfully.
-        self.flush()?;
-        // We must close the current file before rotating files
-        self.writer_opt.take();
-        self.current_filesize = 0;
-        self.rotate_files()?;
-        self.open_writer_if_needed()
+    fn seed_last_write(&mut self, last_write: &DateTime<Local>) {
+        self.inner.seed_last_write(last_write);
     }
 
-    /// Opens a writer for the current file.
-    fn open_writer_if_needed(&mut self) -> io::Result<()> {
-        if self.writer_opt.is_none() {
-            let p = self.filename_for(0);
-            self.writer_opt = Some(BufWriter::new(OpenOptions::new().append(true).create(true).open(&p)?));
-            self.current_filesize = fs::metadata(&p).map_or(0, |m| m.len());
+    fn on_write(&mut self, len: u64) {
+        self.inner.on_write(len);
+    }
+
+    fn on_rollover(&mut self) {
+        self.inner.on_rollover();
+    }
+
+    fn rollover_reason(&self) -> Option<RolloverReason> {
+        self.inner.rollover_reason()
+    }
+
+    fn max_size(&self) -> Option<u64> {
+        self.inner.max_size()
+    }
+
+    fn skipped_periods(&self, now: &DateTime<Local>) -> Vec<DateTime<Local>> {
+        self.inner.skipped_periods(now)
+    }
+
+    fn now(&self) -> DateTime<Local> {
+        self.inner.now()
+    }
+}
+
+/// Wraps any rolling condition, and defers the rollovers it asks for because
+/// a new period started until the time of a write falls within a daily
+/// maintenance window, e.g. to keep rotation IO out of peak hours. Rollovers
+/// for any other reason, e.g. a `max_size`, still happen at any time. Only
+/// rollovers the inner condition reports as [`RolloverReason::Time`] are
+/// deferred.
+///
+/// # Examples
+///
+/// ```rust
+/// use chrono::NaiveTime;
+/// use rolling_file::*;
+/// // roll over daily between 02:00 and 04:00, or whenever the file reaches 1 GiB
+/// let c = MaintenanceWindow::new(
+///     RollingConditionBasic::new().daily().max_size(1 << 30),
+///     NaiveTime::from_hms_opt(2, 0, 0).unwrap(),
+///     NaiveTime::from_hms_opt(4, 0, 0).unwrap(),
+/// );
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MaintenanceWindow<RC> {
+    inner: RC,
+    start: NaiveTime,
+    end: NaiveTime,
+    deferred: bool,
+    last_reason_opt: Option<RolloverReason>,
+}
+
+impl<RC> MaintenanceWindow<RC>
+where
+    RC: RollingCondition,
+{
+    /// Wraps the given condition, deferring its time-based rollovers until a
+    /// write at or after `start` and before `end`, local time. If `end` is
+    /// before `start`, the window crosses midnight. If they are equal, the
+    /// window covers the whole day.
+    pub fn new(inner: RC, start: NaiveTime, end: NaiveTime) -> MaintenanceWindow<RC> {
+        MaintenanceWindow {
+            inner,
+            start,
+            end,
+            deferred: false,
+            last_reason_opt: None,
         }
-        Ok(())
     }
 
-    /// Writes data using the given datetime to calculate the rolling condition
-    pub fn write_with_datetime(&mut self, buf: &[u8], now: &DateTime<Local>) -> io::Result<usize> {
-        if self.condition.should_rollover(&now, self.current_filesize) {
-            if let Err(e) = self.rollover() {
-                // If we can't rollover, just try to continue writing anyway
-                // (better than missing data).
-                // This will likely used to implement logging, so
-                // avoid using log::warn and log to stderr directly
-                eprintln!(
-                    "WARNING: Failed to rotate logfile {}: {}",
-                    self.base_filename.to_string_lossy(),
-                    e
-                );
+    /// Returns the wrapped condition
+    pub fn inner(&self) -> &RC {
+        &self.inner
+    }
+
+    /// Returns whether a time-based rollover is waiting for the window to open
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+
+    /// Returns whether the given datetime falls within the window.
+    pub fn in_window(&self, now: &DateTime<Local>) -> bool {
+        let time = now.time();
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+
+    /// Passes on or defers a rollover the inner condition asked for.
+    fn admit(&mut self, now: &DateTime<Local>, rollover: bool) -> bool {
+        if rollover {
+            self.last_reason_opt = self.inner.rollover_reason();
+            if self.last_reason_opt != Some(RolloverReason::Time) || self.in_window(now) {
+                return true;
             }
+            self.deferred = true;
         }
-        self.open_writer_if_needed()?;
-        if let Some(writer) = self.writer_opt.as_mut() {
-            let buf_len = buf.len();
-            writer.write_all(buf).map(|_| {
-                self.current_filesize += u64::try_from(buf_len).unwrap_or(u64::MAX);
-                buf_len
-            })
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "unexpected condition: writer is missing",
-            ))
+        if self.deferred && self.in_window(now) {
+            self.last_reason_opt = Some(RolloverReason::Time);
+            return true;
         }
+        false
     }
 }
 
-impl<RC> io::Write for RollingFileAppender<RC>
+impl<RC> fmt::Display for MaintenanceWindow<RC>
+where
+    RC: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, time-based rollovers between {} and {}",
+            self.inner,
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl<RC> RollingCondition for MaintenanceWindow<RC>
 where
     RC: RollingCondition,
 {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let now = Local::now();
-        self.write_with_datetime(buf, &now)
+    fn should_rollover(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool {
+        let rollover = self.inner.should_rollover(now, current_filesize);
+        self.admit(now, rollover)
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        if let Some(writer) = self.writer_opt.as_mut() {
-            writer.flush()?;
-        }
-        Ok(())
+    fn should_rollover_after_write(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool {
+        let rollover = self.inner.should_rollover_after_write(now, current_filesize);
+        self.admit(now, rollover)
     }
-}
 
-/// A rolling file appender with a rolling condition based on date/time or size.
-pub type BasicRollingFileAppender = RollingFileAppender<RollingConditionBasic>;
+    fn seed_last_write(&mut self, last_write: &DateTime<Local>) {
+        self.inner.seed_last_write(last_write);
+    }
 
-// LCOV_EXCL_START
-#[cfg(test)]
-mod t {
-    use super::*;
+    fn on_write(&mut self, len: u64) {
+        self.inner.on_write(len);
+    }
 
-    struct Context {
-        _tempdir: tempfile::TempDir,
-        rolling: BasicRollingFileAppender,
+    fn on_rollover(&mut self) {
+        // any rollover starts a new file, so nothing is left to defer
+        self.deferred = false;
+        self.inner.on_rollover();
     }
 
-    impl Context {
-        fn verify_contains(&mut self, needle: &str, n: usize) {
-            self.rolling.flush().unwrap();
-            let p = self.rolling.filename_for(n);
-            let haystack = fs::read_to_string(&p).unwrap();
-            if !haystack.contains(needle) {
-                panic!("file {:?} did not contain expected contents {}", p, needle);
-            }
-        }
+    fn needs_time(&self) -> bool {
+        self.inner.needs_time()
     }
 
-    fn build_context(condition: RollingConditionBasic, max_files: usize) -> Context {
-        let tempdir = tempfile::tempdir().unwrap();
-        let rolling = BasicRollingFileAppender::new(tempdir.path().join("test.log"), condition, max_files).unwrap();
-        Context {
-            _tempdir: tempdir,
-            rolling,
-        }
+    fn rollover_reason(&self) -> Option<RolloverReason> {
+        self.last_reason_opt
     }
 
-    #[test]
-    fn frequency_every_day() {
-        let mut c = build_context(RollingConditionBasic::new().daily(), 9);
-        c.rolling
-            .write_with_datetime(b"Line 1\n", &Local.ymd(2021, 3, 30).and_hms(1, 2, 3))
-            .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 2\n", &Local.ymd(2021, 3, 30).and_hms(1, 3, 0))
-            .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 3\n", &Local.ymd(2021, 3, 31).and_hms(1, 4, 0))
-            .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 4\n", &Local.ymd(2021, 5, 31).and_hms(1, 4, 0))
-            .unwrap();
+    fn max_size(&self) -> Option<u64> {
+        self.inner.max_size()
+    }
+
+    fn skipped_periods(&self, now: &DateTime<Local>) -> Vec<DateTime<Local>> {
+        self.inner.skipped_periods(now)
+    }
+
+    fn now(&self) -> DateTime<Local> {
+        self.inner.now()
+    }
+}
+
+/// Implements a rolling condition based on the number of bytes written
+/// since the last rollover (or since the condition was created or reset),
+/// regardless of the size the file already had when it was opened, e.g.
+/// from a previous run of the program.
+///
+/// # Examples
+///
+/// ```rust
+/// use rolling_file::*;
+/// let c = RollingConditionSessionBytes::new(1024 * 1024);
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RollingConditionSessionBytes {
+    max_bytes: u64,
+    bytes_written: u64,
+}
+
+impl RollingConditionSessionBytes {
+    /// Constructs a condition to rollover once the given number of bytes was
+    /// written to the current file.
+    pub fn new(max_bytes: u64) -> RollingConditionSessionBytes {
+        RollingConditionSessionBytes {
+            max_bytes,
+            bytes_written: 0,
+        }
+    }
+
+    /// Returns the number of bytes written since the last rollover or reset
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Starts counting the bytes written from zero again
+    pub fn reset(&mut self) {
+        self.bytes_written = 0;
+    }
+}
+
+impl fmt::Display for RollingConditionSessionBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "after {} bytes written", self.max_bytes)
+    }
+}
+
+impl RollingCondition for RollingConditionSessionBytes {
+    fn should_rollover(&mut self, _now: &DateTime<Local>, _current_filesize: u64) -> bool {
+        self.bytes_written >= self.max_bytes
+    }
+
+    fn on_write(&mut self, len: u64) {
+        self.bytes_written = self.bytes_written.saturating_add(len);
+    }
+
+    fn on_rollover(&mut self) {
+        self.reset();
+    }
+
+    fn needs_time(&self) -> bool {
+        false
+    }
+
+    fn rollover_reason(&self) -> Option<RolloverReason> {
+        Some(RolloverReason::Size)
+    }
+}
+
+/// Implements a rolling condition based on the age of the oldest data in the
+/// current file, rolling over before a write if the first write to the file
+/// happened at least the given duration earlier. Unlike a
+/// [`RollingFrequency`], the age is measured from the first record rather
+/// than from fixed periods, so no file ever holds records further apart than
+/// the duration. A file reopened on restart counts as starting with the first
+/// write after the restart.
+///
+/// # Examples
+///
+/// ```rust
+/// use chrono::Duration;
+/// use rolling_file::*;
+/// let c = RollingConditionDataAge::new(Duration::minutes(15));
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RollingConditionDataAge {
+    max_age: Duration,
+    first_write_opt: Option<DateTime<Local>>,
+    last_now_opt: Option<DateTime<Local>>,
+}
+
+impl RollingConditionDataAge {
+    /// Constructs a condition to rollover once the first write to the current
+    /// file is at least the given duration old.
+    pub fn new(max_age: Duration) -> RollingConditionDataAge {
+        RollingConditionDataAge {
+            max_age,
+            first_write_opt: None,
+            last_now_opt: None,
+        }
+    }
+
+    /// Returns the datetime of the first write to the current file
+    pub fn first_write(&self) -> Option<DateTime<Local>> {
+        self.first_write_opt
+    }
+}
+
+impl fmt::Display for RollingConditionDataAge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "when data is {}s old", self.max_age.num_seconds())
+    }
+}
+
+impl RollingCondition for RollingConditionDataAge {
+    fn should_rollover(&mut self, now: &DateTime<Local>, _current_filesize: u64) -> bool {
+        // the write only counts as the first of the file once it happened
+        self.last_now_opt = Some(*now);
+        self.first_write_opt
+            .is_some_and(|first| now.signed_duration_since(first) >= self.max_age)
+    }
+
+    fn on_write(&mut self, _len: u64) {
+        if self.first_write_opt.is_none() {
+            self.first_write_opt = self.last_now_opt;
+        }
+    }
+
+    fn on_rollover(&mut self) {
+        self.first_write_opt = None;
+    }
+
+    fn rollover_reason(&self) -> Option<RolloverReason> {
+        Some(RolloverReason::Time)
+    }
+}
+
+/// Implements a rolling condition based on a key derived from the datetime
+/// of each write, rolling over whenever the key differs from the one of the
+/// previous write. This generalizes [`RollingFrequency`] to any period, e.g.
+/// per ISO week.
+///
+/// # Examples
+///
+/// ```rust
+/// use chrono::Datelike;
+/// use rolling_file::*;
+/// let c = RollingConditionKeyed::new(|now| now.iso_week());
+/// ```
+pub struct RollingConditionKeyed<K> {
+    key_fn: KeyFn<K>,
+    last_key_opt: Option<K>,
+}
+
+/// Derives the key of a write from its datetime.
+type KeyFn<K> = Box<dyn FnMut(&DateTime<Local>) -> K + Send>;
+
+impl<K> RollingConditionKeyed<K>
+where
+    K: PartialEq,
+{
+    /// Constructs a condition to rollover whenever the given function
+    /// returns a different key than for the previous write.
+    pub fn new<F>(key_fn: F) -> RollingConditionKeyed<K>
+    where
+        F: FnMut(&DateTime<Local>) -> K + Send + 'static,
+    {
+        RollingConditionKeyed {
+            key_fn: Box::new(key_fn),
+            last_key_opt: None,
+        }
+    }
+}
+
+impl<K> fmt::Debug for RollingConditionKeyed<K>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RollingConditionKeyed")
+            .field("last_key_opt", &self.last_key_opt)
+            .finish()
+    }
+}
+
+impl<K> RollingCondition for RollingConditionKeyed<K>
+where
+    K: PartialEq,
+{
+    fn should_rollover(&mut self, now: &DateTime<Local>, _current_filesize: u64) -> bool {
+        let key = (self.key_fn)(now);
+        let rollover = self.last_key_opt.as_ref().is_some_and(|last| *last != key);
+        self.last_key_opt = Some(key);
+        rollover
+    }
+
+    fn seed_last_write(&mut self, last_write: &DateTime<Local>) {
+        if self.last_key_opt.is_none() {
+            self.last_key_opt = Some((self.key_fn)(last_write));
+        }
+    }
+
+    fn rollover_reason(&self) -> Option<RolloverReason> {
+        Some(RolloverReason::Time)
+    }
+}
+
+/// Determines when data buffered by the appender is flushed to the file.
+/// Regardless of the policy, data is always flushed on rollover, on an
+/// explicit `flush()`, and whenever the write buffer fills up.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum FlushPolicy {
+    /// Flush after every write.
+    OnEveryWrite,
+    /// Flush on the first write after the given interval has elapsed since
+    /// the last flush, based on the datetime of the writes.
+    Interval(Duration),
+    /// Flush after every N writes.
+    EveryN(usize),
+    /// Never flush automatically. This is the default, and gives the best
+    /// throughput at the cost of losing buffered data on a crash.
+    #[default]
+    OnlyOnRollover,
+}
+
+/// Determines how rotated files are numbered
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum NumberingScheme {
+    /// The newest rotated file is always basename.1, and older files are
+    /// renamed to the next higher index on each rollover. This is the
+    /// default, and follows the Debian-style naming convention.
+    #[default]
+    ShiftUp,
+    /// Each rollover moves the current file to the next index after the
+    /// highest existing one, so the newest rotated file has the highest
+    /// index, and existing files are never renamed. The files with the lowest
+    /// indices are deleted to keep the maximum number of files.
+    MonotonicIncrement,
+    /// Rotated files are named after the local date of the last write to
+    /// them, with an index for further files of the same date, e.g.
+    /// `app-20210330.log`, `app-20210330.1.log`, `app-20210331.log` for the
+    /// current file `app.log`, so that size rollovers within a day stay
+    /// grouped under its date. Existing files are never renamed, and the
+    /// oldest dates are deleted to keep the maximum number of files. This
+    /// doesn't support `keep_recent_uncompressed` or checksums.
+    DateGrouped,
+}
+
+/// Identifies a rotated file: by its index, or with
+/// [`NumberingScheme::DateGrouped`] by the date of its last write and its
+/// index among the files of that date.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Archive {
+    Indexed(usize),
+    Dated(NaiveDate, usize),
+}
+
+/// Determines what happens when a file is rotated to a name that is already
+/// taken, see
+/// [`RollingFileAppenderBuilder::collision_policy`]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum CollisionPolicy {
+    /// Replace the existing file. This is the default.
+    #[default]
+    Overwrite,
+    /// Fail the rollover with `AlreadyExists`, leaving both files in place.
+    FailFast,
+    /// Move the existing file aside first, to its name followed by `.bak`,
+    /// or `.bak.1`, `.bak.2` and so on if that is taken as well. Such backups
+    /// are never deleted by the appender.
+    Backup,
+}
+
+/// Determines how often the rolling condition is evaluated by
+/// [`RollingFileAppender::write_batch`]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum BatchRollover {
+    /// Evaluate the condition before each record, as if each was written
+    /// separately. This is the default.
+    #[default]
+    PerRecord,
+    /// Evaluate the condition only before the first record and after the
+    /// last one, as if the batch was a single write, so that the whole batch
+    /// lands in the same file. The flush policy counts the batch as a single
+    /// write too.
+    PerBatch,
+}
+
+/// How many times in a row flushing the file for a rollover may fail before
+/// writes fail as well, by default
+pub const DEFAULT_MAX_FLUSH_FAILURES: usize = 3;
+
+/// Counters of what an appender did, as returned by
+/// [`RollingFileAppender::metrics`] and
+/// [`RollingFileAppender::take_metrics`]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct AppenderMetrics {
+    /// The number of successful writes
+    pub writes: u64,
+    /// The number of bytes accepted by successful writes
+    pub bytes_written: u64,
+    /// The number of failed writes
+    pub write_errors: u64,
+    /// The number of times the current file was rotated
+    pub rollovers: u64,
+    /// The number of rollovers that failed
+    pub rollover_failures: u64,
+}
+
+/// The files as they are planned to be after the next rollover, as returned
+/// by [`RollingFileAppender::next_rollover_layout`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct RolloverLayout {
+    /// The path of the current file the rollover starts
+    pub current: PathBuf,
+    /// The paths of the rotated files, from newest to oldest, ending in the
+    /// compression extension where they are compressed
+    pub archives: Vec<PathBuf>,
+}
+
+/// A snapshot of an appender's effective configuration, as returned by
+/// [`RollingFileAppender::config`], e.g. to compare configurations on reload.
+/// The rolling condition is not included.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct AppenderConfig {
+    /// The maximum number of rotated files to keep, where 0 means unlimited
+    pub max_files: usize,
+    /// How rotated files are numbered
+    pub numbering: NumberingScheme,
+    /// How rotated files are compressed, if at all
+    pub compression: Option<Compression>,
+    /// The minimum size for rotated files to be compressed
+    pub compress_min_size: u64,
+    /// How many times compressing a rotated file is attempted, and the delay
+    /// between attempts
+    pub compress_retry: Option<(usize, std::time::Duration)>,
+    /// When buffered data is flushed to the file
+    pub flush_policy: FlushPolicy,
+    /// The interval at which the current file is synced to disk, if any
+    pub sync_interval: Option<Duration>,
+    /// The minimum amount of free space to keep on the volume, if any
+    pub min_free_bytes: Option<u64>,
+    /// The size to roll over at, as computed from `max_size_fraction`
+    pub max_size: Option<u64>,
+    /// The maximum number of entries in the directory when building, if any
+    pub max_dir_entries: Option<usize>,
+    /// How many times in a row flushing for a rollover may fail
+    pub max_flush_failures: usize,
+    /// Whether the condition is informed of an existing file's modification time
+    pub seed_last_write: bool,
+    /// Whether empty writes are skipped
+    pub skip_empty_writes: bool,
+    /// Whether writes are copied to an error sink
+    pub has_error_sink: bool,
+    /// Whether internal errors go to a handler rather than stderr
+    pub has_internal_error_handler: bool,
+    /// Whether rollover events are sent to a channel
+    pub has_rollover_events: bool,
+    /// Whether running out of disk space is an error
+    pub strict_full_disk: bool,
+    /// How often `write_batch()` evaluates the rolling condition
+    pub batch_rollover: BatchRollover,
+    /// Whether new files begin with a sequence number header
+    pub sequence_header: bool,
+    /// The bytes new files begin with
+    pub preamble: Option<Vec<u8>>,
+    /// Whether the appender only rotates files written by someone else
+    pub rotate_only: bool,
+    /// The size of the chunks larger writes are split into, if any
+    pub max_write_size: Option<usize>,
+    /// Whether flushing also rolls over if the condition says so
+    pub roll_on_flush: bool,
+    /// Whether data goes through a custom [`Encoder`]
+    pub encoder: bool,
+    /// What happens when a file is rotated to a name that is already taken
+    pub collision_policy: CollisionPolicy,
+    /// Whether bytes added by the appender count towards the size limits
+    pub size_counts_framing: bool,
+    /// The suffix of the marker file created for each rotated file, if any
+    pub rollover_marker_suffix: Option<String>,
+    /// The maximum length of the names of rotated files, if limited
+    pub max_filename_len: Option<usize>,
+    /// Whether writes are discarded if the current file can't ever be opened
+    pub allow_null_fallback: bool,
+    /// Whether the current file is written through a gzip encoder
+    pub stream_compression: bool,
+    /// Whether an empty file is created for each period without writes
+    pub fill_gaps: bool,
+    /// Every how many writes the tracked size of the current file is checked
+    /// against its size on disk, if at all
+    pub size_check_interval: Option<usize>,
+    /// How many of the most recent rotated files stay uncompressed
+    pub keep_recent_uncompressed: usize,
+    /// The number of bytes a batch may leave buffered before it is flushed
+    pub max_buffered_bytes: Option<usize>,
+    /// The directories the current file moves through in turn
+    pub rotate_dirs: Vec<PathBuf>,
+    /// The format of the timestamp prefixed to each line
+    pub line_prefix_timestamp: Option<String>,
+    /// How the length prefixed to each record is written, if it is
+    pub length_prefixed: Option<LengthPrefix>,
+    /// The capacity of the buffer holding writes while the file is
+    /// unavailable
+    pub fallback_buffer: Option<usize>,
+    /// The capacity of the buffer holding writes while a failed rollover is
+    /// retried, if they are deferred
+    pub defer_on_rollover_failure: Option<usize>,
+    /// The file whose appearance triggers a rollover
+    pub roll_on_trigger_file: Option<PathBuf>,
+    /// Whether rollovers are serialized with a lock file
+    pub lock_file: bool,
+    /// Whether files are handled by a custom [`FileSystem`]
+    pub custom_file_system: bool,
+    /// Whether the current time is taken from a custom [`Clock`]
+    pub custom_clock: bool,
+    /// The Unix domain socket notified of rollovers
+    #[cfg(unix)]
+    pub notify_socket: Option<PathBuf>,
+    /// Whether the current file is written with `O_DIRECT`
+    #[cfg(target_os = "linux")]
+    pub direct_io: bool,
+    /// Whether rotated files are made read-only
+    #[cfg(unix)]
+    pub read_only_archives: bool,
+    /// The stable path hard-linked to the current file
+    #[cfg(unix)]
+    pub stable_hardlink: Option<PathBuf>,
+    /// What checksum sidecars of rotated files cover, if they are written
+    #[cfg(feature = "checksum")]
+    pub checksum: Option<Checksum>,
+    /// The chunk size of the memory-mapped current file, if it is mapped
+    #[cfg(feature = "mmap")]
+    pub mmap_chunk_size: Option<u64>,
+}
+
+/// Writes data to a file, and "rolls over" to preserve older data in
+/// a separate set of files. Old files have a Debian-style naming scheme
+/// where we have base_filename, base_filename.1, ..., base_filename.N
+/// where N is the maximum number of rollover files to keep.
+pub struct RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    condition: RC,
+    base_filename: OsString,
+    max_files: usize,
+    current_filesize: u64,
+    writer_opt: Option<ActiveWriter>,
+    #[cfg(feature = "mmap")]
+    mmap_chunk_size_opt: Option<u64>,
+    flush_policy: FlushPolicy,
+    writes_since_flush: usize,
+    last_flush_opt: Option<DateTime<Local>>,
+    min_free_bytes_opt: Option<u64>,
+    max_size_fraction_opt: Option<f64>,
+    max_size_opt: Option<u64>,
+    low_space_rolled: bool,
+    compression_opt: Option<Compression>,
+    compress_min_size: u64,
+    compress_retry_opt: Option<(usize, std::time::Duration)>,
+    compress_pending: bool,
+    seed_last_write: bool,
+    rollover_flush_failures: usize,
+    max_flush_failures: usize,
+    numbering: NumberingScheme,
+    error_sink_opt: Option<ErrorSink>,
+    error_handler_opt: Option<ErrorHandler>,
+    metrics: AppenderMetrics,
+    #[cfg(unix)]
+    read_only_archives: bool,
+    #[cfg(unix)]
+    stable_hardlink_opt: Option<PathBuf>,
+    #[cfg(feature = "checksum")]
+    checksum_opt: Option<Checksum>,
+    rolling_paused: bool,
+    batch_records_opt: Option<usize>,
+    skip_empty_writes: bool,
+    sync_interval_opt: Option<Duration>,
+    last_sync_opt: Option<DateTime<Local>>,
+    max_dir_entries_opt: Option<usize>,
+    strict_full_disk: bool,
+    degraded: bool,
+    batch_rollover: BatchRollover,
+    sequence_header: bool,
+    preamble_opt: Option<Vec<u8>>,
+    rotate_only: bool,
+    max_write_size_opt: Option<usize>,
+    roll_on_flush: bool,
+    encoder_opt: Option<Box<dyn Encoder>>,
+    collision_policy: CollisionPolicy,
+    size_counts_framing: bool,
+    framing_bytes: u64,
+    marker_suffix_opt: Option<String>,
+    max_filename_len_opt: Option<usize>,
+    allow_null_fallback: bool,
+    null: bool,
+    null_dropped_bytes: u64,
+    stream_compression: bool,
+    fill_gaps: bool,
+    size_check_interval_opt: Option<usize>,
+    writes_since_size_check: usize,
+    keep_recent_uncompressed: usize,
+    event_sender_opt: Option<events::EventSender>,
+    max_buffered_bytes_opt: Option<usize>,
+    rotate_dirs: Vec<PathBuf>,
+    rotate_dir_index: usize,
+    untimed_now: DateTime<Local>,
+    line_timestamp_format_opt: Option<String>,
+    length_prefix_opt: Option<LengthPrefix>,
+    at_line_start: bool,
+    fallback_opt: Option<fallback::FallbackBuffer>,
+    deferred_opt: Option<fallback::FallbackBuffer>,
+    deferred_rollover_opt: Option<RolloverReason>,
+    file_system_opt: Option<Arc<dyn FileSystem>>,
+    clock_opt: Option<Arc<dyn Clock + Send + Sync>>,
+    trigger_file_opt: Option<PathBuf>,
+    lock_file: bool,
+    #[cfg(unix)]
+    current_file_id_opt: Option<(u64, u64)>,
+    #[cfg(unix)]
+    notify_socket_opt: Option<PathBuf>,
+    #[cfg(unix)]
+    notify_failure_reported: bool,
+    #[cfg(target_os = "linux")]
+    direct_io: bool,
+    current_file_date_opt: Option<NaiveDate>,
+}
+
+/// A secondary appender receiving a copy of the writes that match a predicate.
+struct ErrorSink {
+    appender: Box<BasicRollingFileAppender>,
+    predicate: WritePredicate,
+}
+
+/// Decides whether a write is copied to an error sink.
+type WritePredicate = Box<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// Receives the errors the appender carries on from.
+type ErrorHandler = Box<dyn FnMut(&RollingFileError) + Send>;
+
+impl<RC> fmt::Debug for RollingFileAppender<RC>
+where
+    RC: RollingCondition + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RollingFileAppender")
+            .field("condition", &self.condition)
+            .field("base_filename", &self.base_filename)
+            .field("max_files", &self.max_files)
+            .field("current_filesize", &self.current_filesize)
+            .field("is_open", &self.writer_opt.is_some())
+            .field("flush_policy", &self.flush_policy)
+            .field("compression", &self.compression_opt)
+            .finish()
+    }
+}
+
+/// Summarizes the appender's configuration and state, e.g.
+/// `/var/log/myprogram (rolling daily, max files: 9, current size: 1024 bytes)`
+impl<RC> fmt::Display for RollingFileAppender<RC>
+where
+    RC: RollingCondition + fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (rolling {}, max files: {}, current size: {} bytes",
+            self.base_filename.to_string_lossy(),
+            self.condition,
+            self.max_files,
+            self.current_filesize
+        )?;
+        if let Some(compression) = self.compression_opt {
+            write!(f, ", compression: {:?}", compression)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// The writer used for the active file.
+enum ActiveWriter {
+    Buffered(BufWriter<File>),
+    Custom(BufWriter<Box<dyn Write + Send>>),
+    #[cfg(feature = "mmap")]
+    Mapped(mmap::MmapWriter),
+    #[cfg(target_os = "linux")]
+    Direct(direct::DirectWriter),
+    Gzip(compression::GzipWriter),
+}
+
+impl ActiveWriter {
+    /// The file being written to, unless it belongs to a custom file system
+    #[cfg(unix)]
+    fn file(&self) -> Option<&File> {
+        match self {
+            ActiveWriter::Buffered(w) => Some(w.get_ref()),
+            ActiveWriter::Custom(_) => None,
+            #[cfg(feature = "mmap")]
+            ActiveWriter::Mapped(w) => Some(w.file()),
+            #[cfg(target_os = "linux")]
+            ActiveWriter::Direct(w) => Some(w.file()),
+            ActiveWriter::Gzip(w) => Some(w.file()),
+        }
+    }
+
+    /// The number of bytes buffered but not yet handed to the OS. A mapped
+    /// file has no such buffer.
+    fn buffered_len(&self) -> usize {
+        match self {
+            ActiveWriter::Buffered(w) => w.buffer().len(),
+            ActiveWriter::Custom(w) => w.buffer().len(),
+            #[cfg(feature = "mmap")]
+            ActiveWriter::Mapped(_) => 0,
+            #[cfg(target_os = "linux")]
+            ActiveWriter::Direct(w) => w.buffered_len(),
+            ActiveWriter::Gzip(w) => w.buffered_len(),
+        }
+    }
+
+    /// Flushes the writer, and makes sure the data reaches the disk rather
+    /// than just the OS page cache.
+    fn sync_data(&mut self) -> io::Result<()> {
+        match self {
+            ActiveWriter::Buffered(w) => {
+                w.flush()?;
+                w.get_ref().sync_data()
+            },
+            // the file system can't be asked for more than flushing
+            ActiveWriter::Custom(w) => w.flush(),
+            #[cfg(feature = "mmap")]
+            ActiveWriter::Mapped(w) => w.sync_data(),
+            #[cfg(target_os = "linux")]
+            ActiveWriter::Direct(w) => w.sync_data(),
+            ActiveWriter::Gzip(w) => w.sync_data(),
+        }
+    }
+}
+
+impl Write for ActiveWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ActiveWriter::Buffered(w) => w.write(buf),
+            ActiveWriter::Custom(w) => w.write(buf),
+            #[cfg(feature = "mmap")]
+            ActiveWriter::Mapped(w) => w.write(buf),
+            #[cfg(target_os = "linux")]
+            ActiveWriter::Direct(w) => w.write(buf),
+            ActiveWriter::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            ActiveWriter::Buffered(w) => w.write_all(buf),
+            ActiveWriter::Custom(w) => w.write_all(buf),
+            #[cfg(feature = "mmap")]
+            ActiveWriter::Mapped(w) => w.write_all(buf),
+            #[cfg(target_os = "linux")]
+            ActiveWriter::Direct(w) => w.write_all(buf),
+            ActiveWriter::Gzip(w) => w.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ActiveWriter::Buffered(w) => w.flush(),
+            ActiveWriter::Custom(w) => w.flush(),
+            #[cfg(feature = "mmap")]
+            ActiveWriter::Mapped(w) => w.flush(),
+            #[cfg(target_os = "linux")]
+            ActiveWriter::Direct(w) => w.flush(),
+            ActiveWriter::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+impl<RC> RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Creates a new rolling file appender with the given condition.
+    /// The parent directory of the base path must already exist.
+    /// A `max_files` of 0 keeps an unlimited number of rotated files.
+    pub fn new<P>(path: P, condition: RC, max_files: usize) -> io::Result<RollingFileAppender<RC>>
+    where
+        P: AsRef<Path>,
+    {
+        RollingFileAppender::builder(path, condition, max_files).build()
+    }
+
+    /// Creates a new rolling file appender whose active file is written
+    /// through a memory-mapped region rather than a `BufWriter`.
+    ///
+    /// The active file is preallocated and grown in multiples of `chunk_size`
+    /// bytes, and writes are plain memory copies into the mapping. Be aware of
+    /// the following caveats:
+    ///
+    /// - Data is only guaranteed to reach the disk on `flush()` and on rollover, which both synchronously
+    ///   msync the mapping.
+    /// - While a file is active, its size on disk is rounded up to the next chunk with a zero-filled tail,
+    ///   which readers tailing the file will see. The file is trimmed to the written length when it is
+    ///   closed.
+    /// - If the process dies before the file is closed, the zero-filled tail remains, and is treated as
+    ///   existing content if the file is reopened.
+    #[cfg(feature = "mmap")]
+    pub fn new_mmap<P>(path: P, condition: RC, max_files: usize, chunk_size: u64) -> io::Result<RollingFileAppender<RC>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut rfa = RollingFileAppender::unopened(path, condition, max_files);
+        rfa.mmap_chunk_size_opt = Some(chunk_size);
+        rfa.open_writer_if_needed()?;
+        Ok(rfa)
+    }
+
+    /// Creates a new rolling file appender that adopts an already open file
+    /// as its current file, e.g. one opened in a more privileged context.
+    /// The file should be opened for appending, and is assumed to be the
+    /// file at the given base path, which is still used to rotate files on
+    /// rollover.
+    pub fn from_file<P>(file: File, path: P, condition: RC, max_files: usize) -> io::Result<RollingFileAppender<RC>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut rfa = RollingFileAppender::unopened(path, condition, max_files);
+        rfa.current_filesize = file.metadata()?.len();
+        rfa.writer_opt = Some(ActiveWriter::Buffered(BufWriter::new(file)));
+        Ok(rfa)
+    }
+
+    /// Creates the appender without opening the current file.
+    fn unopened<P>(path: P, condition: RC, max_files: usize) -> RollingFileAppender<RC>
+    where
+        P: AsRef<Path>,
+    {
+        RollingFileAppender {
+            condition,
+            base_filename: path.as_ref().as_os_str().to_os_string(),
+            max_files,
+            current_filesize: 0,
+            writer_opt: None,
+            #[cfg(feature = "mmap")]
+            mmap_chunk_size_opt: None,
+            flush_policy: FlushPolicy::default(),
+            writes_since_flush: 0,
+            last_flush_opt: None,
+            min_free_bytes_opt: None,
+            max_size_fraction_opt: None,
+            max_size_opt: None,
+            low_space_rolled: false,
+            compression_opt: None,
+            compress_min_size: 0,
+            compress_retry_opt: None,
+            compress_pending: false,
+            seed_last_write: false,
+            rollover_flush_failures: 0,
+            max_flush_failures: DEFAULT_MAX_FLUSH_FAILURES,
+            numbering: NumberingScheme::default(),
+            error_sink_opt: None,
+            error_handler_opt: None,
+            metrics: AppenderMetrics::default(),
+            #[cfg(unix)]
+            read_only_archives: false,
+            #[cfg(unix)]
+            stable_hardlink_opt: None,
+            #[cfg(feature = "checksum")]
+            checksum_opt: None,
+            rolling_paused: false,
+            batch_records_opt: None,
+            skip_empty_writes: true,
+            sync_interval_opt: None,
+            last_sync_opt: None,
+            max_dir_entries_opt: None,
+            strict_full_disk: false,
+            degraded: false,
+            batch_rollover: BatchRollover::default(),
+            sequence_header: false,
+            preamble_opt: None,
+            rotate_only: false,
+            max_write_size_opt: None,
+            roll_on_flush: false,
+            encoder_opt: None,
+            collision_policy: CollisionPolicy::default(),
+            size_counts_framing: true,
+            framing_bytes: 0,
+            marker_suffix_opt: None,
+            max_filename_len_opt: None,
+            allow_null_fallback: false,
+            null: false,
+            null_dropped_bytes: 0,
+            stream_compression: false,
+            fill_gaps: false,
+            size_check_interval_opt: None,
+            writes_since_size_check: 0,
+            keep_recent_uncompressed: 0,
+            event_sender_opt: None,
+            max_buffered_bytes_opt: None,
+            rotate_dirs: Vec::new(),
+            rotate_dir_index: 0,
+            untimed_now: Local::now(),
+            line_timestamp_format_opt: None,
+            length_prefix_opt: None,
+            at_line_start: true,
+            fallback_opt: None,
+            deferred_opt: None,
+            deferred_rollover_opt: None,
+            file_system_opt: None,
+            clock_opt: None,
+            trigger_file_opt: None,
+            lock_file: false,
+            #[cfg(unix)]
+            current_file_id_opt: None,
+            #[cfg(unix)]
+            notify_socket_opt: None,
+            #[cfg(unix)]
+            notify_failure_reported: false,
+            #[cfg(target_os = "linux")]
+            direct_io: false,
+            current_file_date_opt: None,
+        }
+    }
+
+    /// Creates a builder for a rolling file appender with the given
+    /// condition, which allows setting further options.
+    pub fn builder<P>(path: P, condition: RC, max_files: usize) -> RollingFileAppenderBuilder<RC>
+    where
+        P: AsRef<Path>,
+    {
+        RollingFileAppenderBuilder {
+            appender: RollingFileAppender::unopened(path, condition, max_files),
+        }
+    }
+
+    /// Returns the counters of what the appender did since it was created,
+    /// or since they were last taken.
+    pub fn metrics(&self) -> AppenderMetrics {
+        self.metrics
+    }
+
+    /// Returns the counters of what the appender did, like
+    /// [`metrics`](RollingFileAppender::metrics), and resets them to zero,
+    /// e.g. for a monitoring system scraping them periodically. No write can
+    /// happen in between, also when called through
+    /// [`SharedRollingFileAppender::with_appender`].
+    pub fn take_metrics(&mut self) -> AppenderMetrics {
+        std::mem::take(&mut self.metrics)
+    }
+
+    /// Returns a snapshot of the appender's effective configuration
+    pub fn config(&self) -> AppenderConfig {
+        AppenderConfig {
+            max_files: self.max_files,
+            numbering: self.numbering,
+            compression: self.compression_opt,
+            compress_min_size: self.compress_min_size,
+            compress_retry: self.compress_retry_opt,
+            flush_policy: self.flush_policy,
+            sync_interval: self.sync_interval_opt,
+            min_free_bytes: self.min_free_bytes_opt,
+            max_size: self.max_size_opt,
+            max_dir_entries: self.max_dir_entries_opt,
+            max_flush_failures: self.max_flush_failures,
+            seed_last_write: self.seed_last_write,
+            skip_empty_writes: self.skip_empty_writes,
+            has_error_sink: self.error_sink_opt.is_some(),
+            has_internal_error_handler: self.error_handler_opt.is_some(),
+            has_rollover_events: self.event_sender_opt.is_some(),
+            strict_full_disk: self.strict_full_disk,
+            batch_rollover: self.batch_rollover,
+            sequence_header: self.sequence_header,
+            preamble: self.preamble_opt.clone(),
+            rotate_only: self.rotate_only,
+            max_write_size: self.max_write_size_opt,
+            roll_on_flush: self.roll_on_flush,
+            encoder: self.encoder_opt.is_some(),
+            collision_policy: self.collision_policy,
+            size_counts_framing: self.size_counts_framing,
+            rollover_marker_suffix: self.marker_suffix_opt.clone(),
+            max_filename_len: self.max_filename_len_opt,
+            allow_null_fallback: self.allow_null_fallback,
+            stream_compression: self.stream_compression,
+            fill_gaps: self.fill_gaps,
+            size_check_interval: self.size_check_interval_opt,
+            keep_recent_uncompressed: self.keep_recent_uncompressed,
+            max_buffered_bytes: self.max_buffered_bytes_opt,
+            rotate_dirs: self.rotate_dirs.clone(),
+            line_prefix_timestamp: self.line_timestamp_format_opt.clone(),
+            length_prefixed: self.length_prefix_opt,
+            fallback_buffer: self.fallback_opt.as_ref().map(fallback::FallbackBuffer::capacity),
+            defer_on_rollover_failure: self.deferred_opt.as_ref().map(fallback::FallbackBuffer::capacity),
+            roll_on_trigger_file: self.trigger_file_opt.clone(),
+            lock_file: self.lock_file,
+            custom_file_system: self.file_system_opt.is_some(),
+            custom_clock: self.clock_opt.is_some(),
+            #[cfg(unix)]
+            notify_socket: self.notify_socket_opt.clone(),
+            #[cfg(target_os = "linux")]
+            direct_io: self.direct_io,
+            #[cfg(unix)]
+            read_only_archives: self.read_only_archives,
+            #[cfg(unix)]
+            stable_hardlink: self.stable_hardlink_opt.clone(),
+            #[cfg(feature = "checksum")]
+            checksum: self.checksum_opt,
+            #[cfg(feature = "mmap")]
+            mmap_chunk_size: self.mmap_chunk_size_opt,
+        }
+    }
+
+    /// Changes the maximum number of rotated files to keep, where 0 means
+    /// unlimited, as when constructing the appender. Lowering it takes effect
+    /// on the next rollover; no files are deleted right away.
+    pub fn set_max_files(&mut self, x: usize) {
+        self.max_files = x;
+    }
+
+    /// Returns the size of the current file, as tracked by the appender.
+    pub fn current_file_size(&self) -> u64 {
+        self.current_filesize
+    }
+
+    /// Sets the size of the current file as tracked by the appender, e.g.
+    /// after recovering it by other means, without reopening it. The size
+    /// should include any data not flushed yet, and all of it counts as
+    /// payload for [`size_counts_framing`](RollingFileAppenderBuilder::size_counts_framing).
+    /// A wrong size makes size-based rollovers happen too early or too late.
+    pub fn set_current_file_size(&mut self, x: u64) {
+        self.current_filesize = x;
+        self.framing_bytes = 0;
+    }
+
+    /// Determines the final filename, where n==0 indicates the current file,
+    /// and n>0 the n-th rotated file. This is part of the stable API, so that
+    /// other tools can find the files exactly as the appender names them.
+    ///
+    /// The name never includes a compression extension: when compression is
+    /// enabled, a rotated file is named as returned here plus the
+    /// compression's [`extension`](Compression::extension) once it has been
+    /// compressed, while files below `compress_min_size` keep this name. With
+    /// [`NumberingScheme::ShiftUp`] the newest rotated file is n==1; with
+    /// [`NumberingScheme::MonotonicIncrement`] it has the highest n.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rolling_file::*;
+    /// let dir = std::env::temp_dir().join("rolling_file_filename_for");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let file_appender = BasicRollingFileAppender::new(dir.join("myprogram"), RollingConditionBasic::new(), 9).unwrap();
+    /// assert_eq!(file_appender.filename_for(0), dir.join("myprogram").into_os_string());
+    /// assert_eq!(file_appender.filename_for(1), dir.join("myprogram.1").into_os_string());
+    /// # std::fs::remove_dir_all(dir).ok();
+    /// ```
+    pub fn filename_for(&self, n: usize) -> OsString {
+        if n == 0 {
+            return self.base_filename.clone();
+        }
+        let mut f = self.rotated_base();
+        f.push(OsString::from(format!(".{}", n)));
+        f
+    }
+
+    /// Determines the path that the names of rotated files are derived from,
+    /// which is the current file's unless `max_filename_len` requires a
+    /// shorter file name.
+    fn rotated_base(&self) -> OsString {
+        let current = Path::new(&self.base_filename);
+        match (self.max_filename_len_opt, current.file_name()) {
+            (Some(max_len), Some(name)) if name.len() + ROTATED_SUFFIX_RESERVE > max_len => current
+                .with_file_name(shorten_file_name(name, max_len - ROTATED_SUFFIX_RESERVE))
+                .into_os_string(),
+            _ => self.base_filename.clone(),
+        }
+    }
+
+    /// Determines the filename of a compressed file, where n>0.
+    /// Compressed and uncompressed files may be mixed in the same series,
+    /// e.g. if a file was too small to be compressed.
+    fn compressed_filename_for(&self, n: usize) -> OsString {
+        let mut f = self.filename_for(n);
+        f.push(self.compression_opt.unwrap_or(Compression::Gzip).extension());
+        f
+    }
+
+    /// Deletes a rotated file, whether or not it is compressed.
+    fn remove_archive(&self, archive: Archive) {
+        // ignore any failure removing the file (may not exist)
+        let fs = self.file_system();
+        let [plain, compressed] = self.archive_paths(archive);
+        if let Some(marker) = self.marker_for(plain.clone().into_os_string()) {
+            let _ = fs.remove_file(marker.as_ref());
+        }
+        #[cfg(feature = "checksum")]
+        {
+            let _ = fs.remove_file(checksum::sidecar_for(plain.as_os_str()).as_ref());
+            let _ = fs.remove_file(checksum::sidecar_for(compressed.as_os_str()).as_ref());
+        }
+        let _ = fs.remove_file(&plain);
+        let _ = fs.remove_file(&compressed);
+    }
+
+    /// Finds the indices of all existing rotated files in ascending order,
+    /// by looking for files named after the current file in its directory.
+    /// Names are compared as raw bytes, so the current file's name needn't be
+    /// valid UTF-8.
+    fn archive_indices(&self) -> io::Result<Vec<usize>> {
+        let base = self.rotated_base();
+        let prefix = match Path::new(&base).file_name() {
+            Some(x) => x.as_encoded_bytes(),
+            None => return Ok(Vec::new()),
+        };
+        let extension = self.compression_opt.unwrap_or(Compression::Gzip).extension();
+        let mut indices = Vec::new();
+        for name in self.file_system().read_dir(self.directory())? {
+            let index_opt = name
+                .as_encoded_bytes()
+                .strip_prefix(prefix)
+                .and_then(|x| x.strip_prefix(b"."))
+                .map(|x| x.strip_suffix(extension.as_bytes()).unwrap_or(x))
+                .filter(|x| !x.is_empty() && x.iter().all(u8::is_ascii_digit))
+                .and_then(|x| std::str::from_utf8(x).ok())
+                .and_then(|x| x.parse::<usize>().ok())
+                .filter(|n| *n > 0);
+            if let Some(n) = index_opt {
+                indices.push(n);
+            }
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        Ok(indices)
+    }
+
+    /// Finds all existing rotated files, from oldest to newest. Only this and
+    /// the methods placing rotated files look at the numbering scheme, the
+    /// rest of the rotation deals with [`Archive`]s whatever it is.
+    fn archives(&self) -> io::Result<Vec<Archive>> {
+        let archives = match self.numbering {
+            NumberingScheme::ShiftUp => self
+                .archive_indices()?
+                .into_iter()
+                .rev()
+                .map(Archive::Indexed)
+                .collect(),
+            NumberingScheme::MonotonicIncrement => self.archive_indices()?.into_iter().map(Archive::Indexed).collect(),
+            NumberingScheme::DateGrouped => self
+                .dated_archives()?
+                .into_iter()
+                .map(|(date, k)| Archive::Dated(date, k))
+                .collect(),
+        };
+        Ok(archives)
+    }
+
+    /// Returns the existing rotated files from newest to oldest.
+    fn archives_newest_first(&self) -> io::Result<Vec<Archive>> {
+        let mut archives = self.archives()?;
+        archives.reverse();
+        Ok(archives)
+    }
+
+    /// Determines the rotated file the current file becomes on the next
+    /// rotation, given the existing ones from oldest to newest. Without a
+    /// write since the appender was created, the current file was last
+    /// written when it was modified, or else on the given day.
+    fn next_archive(&self, archives: &[Archive], today: NaiveDate) -> Archive {
+        match self.numbering {
+            NumberingScheme::ShiftUp => Archive::Indexed(1),
+            NumberingScheme::MonotonicIncrement => match archives.last() {
+                Some(Archive::Indexed(n)) => Archive::Indexed(n + 1),
+                _ => Archive::Indexed(1),
+            },
+            NumberingScheme::DateGrouped => {
+                let date = self.current_file_date_opt.unwrap_or_else(|| {
+                    self.file_system()
+                        .metadata(self.filename_for(0).as_ref())
+                        .map_or(today, |m| DateTime::<Local>::from(m.modified).date_naive())
+                });
+                next_of_date(archives, date)
+            },
+        }
+    }
+
+    /// Rotates old files to make room for a new one, returning the newest
+    /// rotated file.
+    /// This may result in the deletion of the oldest file
+    fn rotate_files(&mut self, now: &DateTime<Local>) -> io::Result<Archive> {
+        match self.numbering {
+            NumberingScheme::ShiftUp => self.shift_files().map(|_| Archive::Indexed(1)),
+            NumberingScheme::MonotonicIncrement | NumberingScheme::DateGrouped => self.increment_files(now),
+        }
+    }
+
+    /// Moves the current file to the rotated file after the newest existing
+    /// one, then deletes the oldest files beyond the maximum number of files.
+    fn increment_files(&mut self, now: &DateTime<Local>) -> io::Result<Archive> {
+        let mut archives = self.archives()?;
+        let next = self.next_archive(&archives, now.date_naive());
+        self.current_file_date_opt = None;
+        let [to, _] = self.archive_paths(next);
+        match self.rename_rotated(self.filename_for(0).as_ref(), &to) {
+            Ok(()) => {
+                archives.push(next);
+                archives.sort_unstable();
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        for archive in self.archives_to_prune(&archives, false) {
+            self.remove_archive(archive);
+        }
+        Ok(next)
+    }
+
+    /// Renames a file as part of a rotation, dealing with an existing file
+    /// at the destination according to the collision policy.
+    fn rename_rotated(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.collision_policy != CollisionPolicy::Overwrite && self.exists(to) && self.exists(from) {
+            if self.collision_policy == CollisionPolicy::FailFast {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("can't rotate {} to {}, which exists", from.display(), to.display()),
+                ));
+            }
+            let mut backup = to.as_os_str().to_os_string();
+            backup.push(".bak");
+            let mut n = 0;
+            while self.exists(&backup) {
+                n += 1;
+                backup = to.as_os_str().to_os_string();
+                backup.push(format!(".bak.{}", n));
+            }
+            self.rename_with_retry(to, backup.as_ref())?;
+        }
+        self.rename_with_retry(from, to)
+    }
+
+    /// Renames a file, retrying with a growing delay while it fails in a way
+    /// that may be transient, as on Windows when another process has either
+    /// file open without allowing it to be deleted, e.g. a log viewer.
+    fn rename_with_retry(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut delay = RENAME_RETRY_DELAY;
+        for _ in 0..RENAME_RETRIES {
+            match self.file_system().rename(from, to) {
+                Err(e) if is_sharing_violation(&e) => std::thread::sleep(delay),
+                r => return r,
+            }
+            delay *= 2;
+        }
+        self.file_system().rename(from, to)
+    }
+
+    /// Determines which rotated files the next rotation deletes to stay
+    /// within `max_files`, as they are named before the rotation, given the
+    /// existing ones from oldest to newest and whether the current file will
+    /// be added to them.
+    fn archives_to_prune(&self, archives: &[Archive], adding_current: bool) -> Vec<Archive> {
+        match (self.max_files, self.numbering) {
+            (0, _) => Vec::new(),
+            (n, NumberingScheme::ShiftUp) => vec![Archive::Indexed(n)],
+            (_, NumberingScheme::MonotonicIncrement | NumberingScheme::DateGrouped) => {
+                self.excess_archives(archives, adding_current)
+            },
+        }
+    }
+
+    /// Returns the oldest of the given rotated files, from oldest to newest,
+    /// that are beyond `max_files`, counting the current file if it will be
+    /// added to them.
+    fn excess_archives(&self, archives: &[Archive], adding_current: bool) -> Vec<Archive> {
+        let excess = match self.max_files {
+            0 => 0,
+            n => (archives.len() + usize::from(adding_current)).saturating_sub(n),
+        };
+        archives[..excess].to_vec()
+    }
+
+    /// Returns the path of the k-th rotated file of the given date, and the
+    /// path it has once compressed.
+    fn dated_filenames_for(&self, date: NaiveDate, k: usize) -> [PathBuf; 2] {
+        let base = self.rotated_base();
+        let current = Path::new(&base);
+        let plain = current.with_file_name(dated::file_name(current, date, k));
+        let mut compressed = plain.clone().into_os_string();
+        compressed.push(self.compression_opt.unwrap_or(Compression::Gzip).extension());
+        [plain, PathBuf::from(compressed)]
+    }
+
+    /// Returns the path of a rotated file, and the path it has once
+    /// compressed.
+    fn archive_paths(&self, archive: Archive) -> [PathBuf; 2] {
+        match archive {
+            Archive::Indexed(n) => self.archive_filenames_for(n),
+            Archive::Dated(date, k) => self.dated_filenames_for(date, k),
+        }
+    }
+
+    /// Finds the dates and indices of all existing rotated files named by
+    /// date, from oldest to newest.
+    fn dated_archives(&self) -> io::Result<Vec<(NaiveDate, usize)>> {
+        let base = self.rotated_base();
+        let current = Path::new(&base);
+        let extension = self.compression_opt.unwrap_or(Compression::Gzip).extension();
+        let mut archives = Vec::new();
+        for name in self.file_system().read_dir(self.directory())? {
+            if let Some((date, k, _)) = dated::parse_file_name(current, extension, &name) {
+                archives.push((date, k));
+            }
+        }
+        archives.sort_unstable();
+        archives.dedup();
+        Ok(archives)
+    }
+
+    /// Lists the existing current and rotated files, compressed or not.
+    fn managed_files(&self) -> io::Result<Vec<PathBuf>> {
+        let current = PathBuf::from(self.filename_for(0));
+        let candidates: Vec<_> = self
+            .archives()?
+            .into_iter()
+            .flat_map(|archive| self.archive_paths(archive))
+            .collect();
+        Ok(std::iter::once(current)
+            .chain(candidates)
+            .filter(|p| self.exists(p))
+            .collect())
+    }
+
+    /// Returns the total size on disk of the current file and all rotated
+    /// files, as stored, i.e. compressed if they were. Data still buffered by
+    /// the appender is not included.
+    pub fn total_managed_size(&self) -> io::Result<u64> {
+        let mut total = 0;
+        for p in self.managed_files()? {
+            match self.file_system().metadata(&p) {
+                Ok(m) => total += m.len,
+                // deleted meanwhile, e.g. by another rollover
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(total)
+    }
+
+    /// Returns the rotated files the next rollover would delete to stay
+    /// within `max_files`, without rolling over. Files deleted to keep
+    /// `min_free_bytes` free are not included, since that depends on the
+    /// free space at the time.
+    pub fn files_to_be_pruned(&self) -> io::Result<Vec<PathBuf>> {
+        let adding_current = self.exists(self.filename_for(0));
+        let mut files = Vec::new();
+        for archive in self.archives_to_prune(&self.archives()?, adding_current) {
+            files.extend(self.archive_paths(archive).iter().filter(|p| self.exists(p)).cloned());
+        }
+        Ok(files)
+    }
+
+    /// Determines the names the current and rotated files will have after
+    /// the next rollover, e.g. to check the naming configuration, without
+    /// changing anything. This assumes the rollover succeeds, and that the
+    /// files aren't changed by anyone else in between.
+    pub fn next_rollover_layout(&self) -> io::Result<RolloverLayout> {
+        // the rotated files after the rollover from newest to oldest, as
+        // their path now, their paths after the rollover, and whether they
+        // are compressed
+        let mut planned: Vec<(PathBuf, [PathBuf; 2], bool)> = Vec::new();
+        let current = PathBuf::from(self.filename_for(0));
+        let adding_current = self.exists(&current);
+        match self.numbering {
+            NumberingScheme::ShiftUp => {
+                let indices = self.archive_indices()?;
+                if adding_current {
+                    planned.push((current.clone(), self.archive_filenames_for(1), false));
+                }
+                for n in indices {
+                    let [plain, compressed] = self.archive_filenames_for(n);
+                    let is_compressed = self.exists(&compressed);
+                    let now = if is_compressed { compressed } else { plain };
+                    match self.max_files {
+                        // deleted to make room
+                        max_files if n == max_files => {},
+                        // left alone
+                        max_files if n > max_files && max_files > 0 => {
+                            planned.push((now, self.archive_filenames_for(n), is_compressed))
+                        },
+                        _ => planned.push((now, self.archive_filenames_for(n + 1), is_compressed)),
+                    }
+                }
+            },
+            NumberingScheme::MonotonicIncrement | NumberingScheme::DateGrouped => {
+                let mut archives = self.archives()?;
+                let next = self.next_archive(&archives, Local::now().date_naive());
+                if adding_current {
+                    archives.push(next);
+                    archives.sort_unstable();
+                }
+                let pruned = self.archives_to_prune(&archives, false);
+                for archive in archives.into_iter().rev().filter(|a| !pruned.contains(a)) {
+                    let [plain, compressed] = self.archive_paths(archive);
+                    if archive == next && adding_current {
+                        planned.push((current.clone(), [plain, compressed], false));
+                    } else {
+                        let is_compressed = self.exists(&compressed);
+                        let now = if is_compressed {
+                            compressed.clone()
+                        } else {
+                            plain.clone()
+                        };
+                        planned.push((now, [plain, compressed], is_compressed));
+                    }
+                }
+            },
+        }
+        // the rollover compresses one file, if it is large enough
+        if adding_current && self.compression_opt.is_some() {
+            if let Some((now, _, is_compressed)) = planned.get_mut(self.keep_recent_uncompressed) {
+                let len = if *now == current {
+                    self.current_filesize
+                } else {
+                    self.file_system().metadata(now).map_or(0, |m| m.len)
+                };
+                *is_compressed |= len >= self.compress_min_size;
+            }
+        }
+        let current = match self.rotate_dirs.len() {
+            0 => current,
+            n => match current.file_name() {
+                Some(name) => self.rotate_dirs[(self.rotate_dir_index + 1) % n].join(name),
+                None => current,
+            },
+        };
+        Ok(RolloverLayout {
+            current,
+            archives: planned
+                .into_iter()
+                .map(|(_, [plain, compressed], is_compressed)| if is_compressed { compressed } else { plain })
+                .collect(),
+        })
+    }
+
+    /// Renames each file to the next higher index, deleting the oldest file.
+    fn shift_files(&mut self) -> io::Result<()> {
+        let max_files = match self.max_files {
+            // unlimited, so shift every existing file and delete none
+            0 => self.archive_indices()?.last().map_or(1, |n| n + 1),
+            n => {
+                for archive in self.archives_to_prune(&[], true) {
+                    self.remove_archive(archive);
+                }
+                n
+            },
+        };
+        let mut r = Ok(());
+        for i in (0..max_files).rev() {
+            let mut renames = vec![(self.filename_for(i), self.filename_for(i + 1))];
+            if i > 0 {
+                renames.push((self.compressed_filename_for(i), self.compressed_filename_for(i + 1)));
+                if let (Some(from), Some(to)) = (
+                    self.marker_for(self.filename_for(i)),
+                    self.marker_for(self.filename_for(i + 1)),
+                ) {
+                    renames.push((from, to));
+                }
+                #[cfg(feature = "checksum")]
+                renames.extend(
+                    renames
+                        .clone()
+                        .into_iter()
+                        .map(|(from, to)| (checksum::sidecar_for(&from), checksum::sidecar_for(&to))),
+                );
+            }
+            for (rotate_from, rotate_to) in renames {
+                if let Err(e) = self
+                    .rename_rotated(rotate_from.as_ref(), rotate_to.as_ref())
+                    .or_else(|e| match e.kind() {
+                        io::ErrorKind::NotFound => Ok(()),
+                        _ => Err(e),
+                    })
+                {
+                    // capture the error, but continue the loop,
+                    // to maximize ability to rename everything
+                    r = Err(e);
+                }
+            }
+        }
+        r
+    }
+
+    /// Compresses a rotated file, if compression is enabled and the file is
+    /// large enough to be worth it.
+    fn compress_file(&self, archive: Archive) -> io::Result<bool> {
+        if let Some(compression) = self.compression_opt {
+            let [p, compressed] = self.archive_paths(archive);
+            let len = match self.file_system().metadata(&p) {
+                Ok(m) => m.len,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+                Err(e) => return Err(e),
+            };
+            if len >= self.compress_min_size {
+                if self.stream_compression && compression::is_gzip(&p)? {
+                    self.file_system().rename(&p, &compressed)?;
+                    return Ok(true);
+                }
+                self.with_compress_retry(|| compression::compress_file(compression, &p, &compressed))?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Runs a compression, attempting it again after a delay while it fails,
+    /// as configured with `compress_retry`. A failed attempt leaves the
+    /// uncompressed file intact.
+    fn with_compress_retry<F>(&self, mut compress: F) -> io::Result<()>
+    where
+        F: FnMut() -> io::Result<()>,
+    {
+        let (attempts, delay) = self.compress_retry_opt.unwrap_or((1, std::time::Duration::ZERO));
+        let mut attempt = 1;
+        loop {
+            match compress() {
+                Err(_) if attempt < attempts => {
+                    attempt += 1;
+                    std::thread::sleep(delay);
+                },
+                r => return r,
+            }
+        }
+    }
+
+    /// A compression of the given file that failed is reported and left for
+    /// the next rollover to retry, instead of failing the rollover, which
+    /// already rotated the file.
+    fn defer_compression_failure(&mut self, r: io::Result<()>, path: PathBuf) {
+        if let Err(e) = r {
+            self.compress_pending = true;
+            self.report(RollingFileError::Compress { path, source: e });
+        }
+    }
+
+    /// A failure to finish a rotated file, e.g. to write its checksum sidecar,
+    /// is reported instead of failing the rollover, which already rotated the
+    /// file.
+    fn report_unfinished_archive(&mut self, r: io::Result<()>, archive: Archive) {
+        if let Err(e) = r {
+            let [path, _] = self.archive_paths(archive);
+            self.report(RollingFileError::FinishRotated { path, source: e });
+        }
+    }
+
+    /// Retries the compressions that failed during earlier rollovers.
+    fn retry_failed_compressions(&mut self) {
+        let r = self.compress_existing().map(|_| ());
+        self.defer_compression_failure(r, PathBuf::from(self.directory()));
+    }
+
+    /// Compresses all existing rotated files that aren't compressed yet, e.g.
+    /// to migrate old files after enabling compression, and returns how many
+    /// were compressed. The current file is left alone, as are files below
+    /// `compress_min_size` and the most recent files kept uncompressed. Does
+    /// nothing if compression isn't enabled.
+    pub fn compress_existing(&self) -> io::Result<usize> {
+        let mut count = 0;
+        for archive in self
+            .archives_newest_first()?
+            .into_iter()
+            .skip(self.keep_recent_uncompressed)
+        {
+            // never overwrite a compressed file of the same name
+            let [_, compressed] = self.archive_paths(archive);
+            if self.exists(&compressed) {
+                continue;
+            }
+            if self.compress_archive(archive)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Moves files written by another tool, e.g. `app.log.2024-01-01`, into
+    /// the appender's naming scheme as its oldest rotated files, in the order
+    /// they were last modified, so that they are read, compressed and pruned
+    /// like the appender's own files. With [`NumberingScheme::MonotonicIncrement`]
+    /// the existing rotated files are renumbered to make room for them. Files
+    /// ending in the compression's extension are taken to be compressed
+    /// already. Files beyond `max_files` are deleted right away, oldest
+    /// first, as a rollover would. Returns the new paths of the files that
+    /// are kept, from oldest to newest.
+    pub fn import_existing(&mut self, paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+        let current = PathBuf::from(self.filename_for(0));
+        let extension = self.compression_opt.unwrap_or(Compression::Gzip).extension();
+        let mut files = Vec::with_capacity(paths.len());
+        for p in paths {
+            if *p == current {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("can't import the current file {}", p.display()),
+                ));
+            }
+            files.push((self.file_system().metadata(p)?.modified, p));
+        }
+        // oldest first
+        files.sort();
+        let count = files.len();
+        let mut targets = Vec::with_capacity(count);
+        let base = match self.numbering {
+            NumberingScheme::ShiftUp => self.archive_indices()?.last().copied().unwrap_or(0),
+            NumberingScheme::MonotonicIncrement => {
+                for n in self.archive_indices()?.into_iter().rev() {
+                    self.move_archive(n, n + count)?;
+                }
+                0
+            },
+            NumberingScheme::DateGrouped => 0,
+        };
+        for (i, (modified, p)) in files.into_iter().enumerate() {
+            let compressed = p.as_os_str().as_encoded_bytes().ends_with(extension.as_bytes());
+            let [plain, compressed_path] = match self.numbering {
+                NumberingScheme::ShiftUp => self.archive_filenames_for(base + count - i),
+                NumberingScheme::MonotonicIncrement => self.archive_filenames_for(i + 1),
+                NumberingScheme::DateGrouped => {
+                    let date = DateTime::<Local>::from(modified).date_naive();
+                    self.archive_paths(next_of_date(&self.archives()?, date))
+                },
+            };
+            let target = if compressed { compressed_path } else { plain };
+            if self.exists(&target) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("can't import {} as {}, which exists", p.display(), target.display()),
+                ));
+            }
+            self.file_system().rename(p, &target)?;
+            targets.push(target);
+        }
+        for archive in self.excess_archives(&self.archives()?, false) {
+            self.remove_archive(archive);
+        }
+        targets.retain(|p| self.exists(p));
+        Ok(targets)
+    }
+
+    /// Returns the path of the n-th file, and the path it has once
+    /// compressed.
+    fn archive_filenames_for(&self, n: usize) -> [PathBuf; 2] {
+        [
+            PathBuf::from(self.filename_for(n)),
+            PathBuf::from(self.compressed_filename_for(n)),
+        ]
+    }
+
+    /// Renames the n-th file to the given index, whether or not it is
+    /// compressed, along with its checksum sidecar and marker.
+    fn move_archive(&self, from: usize, to: usize) -> io::Result<()> {
+        let mut renames = vec![
+            (self.filename_for(from), self.filename_for(to)),
+            (self.compressed_filename_for(from), self.compressed_filename_for(to)),
+        ];
+        if let (Some(marker_from), Some(marker_to)) = (
+            self.marker_for(self.filename_for(from)),
+            self.marker_for(self.filename_for(to)),
+        ) {
+            renames.push((marker_from, marker_to));
+        }
+        #[cfg(feature = "checksum")]
+        let renames: Vec<_> = renames
+            .iter()
+            .cloned()
+            .chain(
+                renames
+                    .iter()
+                    .map(|(from, to)| (checksum::sidecar_for(from), checksum::sidecar_for(to))),
+            )
+            .collect();
+        for (rename_from, rename_to) in renames {
+            match self.file_system().rename(rename_from.as_ref(), rename_to.as_ref()) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    /// Forces a rollover to happen immediately.
+    ///
+    /// When the appender is shared between threads, use a
+    /// [`SharedRollingFileAppender`] rather than locking it separately for
+    /// writes and rollovers, so that the two can't interleave.
+    pub fn rollover(&mut self) -> io::Result<()> {
+        self.rollover_with_reason(&Local::now(), RolloverReason::Manual)
+    }
+
+    /// Rolls over, reporting the given datetime and reason to the event
+    /// channel if there is one, and counting failures.
+    fn rollover_with_reason(&mut self, now: &DateTime<Local>, reason: RolloverReason) -> io::Result<()> {
+        let r = self.try_rollover_with_reason(now, reason);
+        if r.is_err() {
+            self.metrics.rollover_failures += 1;
+        }
+        r
+    }
+
+    fn try_rollover_with_reason(&mut self, now: &DateTime<Local>, reason: RolloverReason) -> io::Result<()> {
+        // held until the rollover is complete
+        let _lock_opt = self.lock_rotation()?;
+        if self.null || (self.rotate_only && !self.exists(self.filename_for(0))) {
+            return Ok(());
+        }
+        // Before closing, make sure all data is flushed successfully.
+        if let Err(e) = self.flush_buffers().and_then(|()| self.finish_writer()) {
+            self.rollover_flush_failures += 1;
+            return Err(e);
+        }
+        self.rollover_flush_failures = 0;
+        let rotated_elsewhere = self.lock_file && self.rotated_elsewhere();
+        // We must close the current file before rotating files
+        self.writer_opt.take();
+        self.current_filesize = 0;
+        self.framing_bytes = 0;
+        if rotated_elsewhere {
+            self.metrics.rollovers += 1;
+            self.current_file_date_opt = None;
+            self.condition.on_rollover();
+            return self.open_writer_if_needed();
+        }
+        let newest = self.rotate_files(now)?;
+        self.metrics.rollovers += 1;
+        self.condition.on_rollover();
+        self.prune_for_free_space();
+        // with several directories, the next file is only opened once the
+        // rotated file is dealt with, which needs the current directory
+        if self.rotate_dirs.is_empty() {
+            self.open_writer_if_needed()?;
+        }
+        let target_opt = match self.keep_recent_uncompressed {
+            0 => Some(newest),
+            n => self.archives_newest_first()?.into_iter().nth(n),
+        };
+        if target_opt != Some(newest) {
+            #[cfg(feature = "checksum")]
+            {
+                let r = self.write_checksum(newest, None);
+                self.report_unfinished_archive(r, newest);
+            }
+            #[cfg(unix)]
+            {
+                let r = self.make_read_only(newest);
+                self.report_unfinished_archive(r, newest);
+            }
+        }
+        if let Some(target) = target_opt {
+            let retry = std::mem::take(&mut self.compress_pending);
+            let r = self.compress_archive(target).map(|_| ());
+            let [plain, _] = self.archive_paths(target);
+            self.defer_compression_failure(r, plain);
+            if retry {
+                self.retry_failed_compressions();
+            }
+        }
+        let [plain, _] = self.archive_paths(newest);
+        let r = self.create_marker(plain.into_os_string());
+        self.report_unfinished_archive(r, newest);
+        let archived = self.archived_path(newest);
+        self.send_rollover_event(&archived, now, reason);
+        #[cfg(unix)]
+        self.notify_socket(&archived, now);
+        self.next_rotate_dir()
+    }
+
+    /// Determines the path of the marker of the rotated file with the given
+    /// uncompressed path, if markers are enabled.
+    fn marker_for(&self, mut plain: OsString) -> Option<OsString> {
+        let suffix = self.marker_suffix_opt.as_ref()?;
+        plain.push(suffix);
+        Some(plain)
+    }
+
+    /// Creates the empty marker of the rotated file with the given
+    /// uncompressed path, if markers are enabled, anew if it exists.
+    fn create_marker(&self, plain: OsString) -> io::Result<()> {
+        if let Some(marker) = self.marker_for(plain) {
+            let _ = self.file_system().remove_file(marker.as_ref());
+            self.file_system().open_append(marker.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Moves on to the next directory of `rotate_dirs`, if set, and opens the
+    /// current file there.
+    fn next_rotate_dir(&mut self) -> io::Result<()> {
+        if !self.rotate_dirs.is_empty() {
+            self.rotate_dir_index = (self.rotate_dir_index + 1) % self.rotate_dirs.len();
+            self.enter_rotate_dir();
+            self.open_writer_if_needed()?;
+        }
+        Ok(())
+    }
+
+    /// Moves the current file to the current directory of `rotate_dirs`,
+    /// keeping its file name.
+    fn enter_rotate_dir(&mut self) {
+        if let Some(name) = Path::new(&self.base_filename).file_name() {
+            self.base_filename = self.rotate_dirs[self.rotate_dir_index].join(name).into_os_string();
+        }
+    }
+
+    /// Sends an event for a rollover that archived the current file at the
+    /// given path, if there is an event channel. A full channel drops the
+    /// event; a closed one is detached.
+    fn send_rollover_event(&mut self, archived: &Path, now: &DateTime<Local>, reason: RolloverReason) {
+        if let Some(sender) = self.event_sender_opt.as_ref() {
+            let event = RolloverEvent {
+                path: archived.to_path_buf(),
+                timestamp: *now,
+                reason,
+            };
+            if !sender.send(event) {
+                self.event_sender_opt = None;
+            }
+        }
+    }
+
+    /// Returns the path of a rotated file as stored, compressed or not.
+    fn archived_path(&self, archive: Archive) -> PathBuf {
+        let [plain, compressed] = self.archive_paths(archive);
+        if self.exists(&compressed) {
+            compressed
+        } else {
+            plain
+        }
+    }
+
+    /// Notifies the socket of a rollover that archived the current file at
+    /// the given path, if there is one. A failure is only reported, the
+    /// first time.
+    #[cfg(unix)]
+    fn notify_socket(&mut self, archived: &Path, now: &DateTime<Local>) {
+        if let Some(socket) = self.notify_socket_opt.as_ref() {
+            if let Err(e) = events::notify_socket(socket, archived, now) {
+                if !self.notify_failure_reported {
+                    self.notify_failure_reported = true;
+                    self.report(RollingFileError::NotifySocket {
+                        socket: socket.clone(),
+                        path: PathBuf::from(&self.base_filename),
+                        source: e,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Determines whether rollovers notify a socket.
+    #[cfg(unix)]
+    fn notifies_socket(&self) -> bool {
+        self.notify_socket_opt.is_some()
+    }
+
+    #[cfg(not(unix))]
+    fn notifies_socket(&self) -> bool {
+        false
+    }
+
+    /// Reports an error the appender carries on from to the handler, or on
+    /// stderr if there is none. This will likely be used to implement
+    /// logging, so stderr is used directly rather than e.g. `log::warn`.
+    fn report(&mut self, e: RollingFileError) {
+        match self.error_handler_opt.as_mut() {
+            Some(handler) => handler(&e),
+            None => eprintln!("WARNING: {}", e),
+        }
+    }
+
+    /// Reports a failure after the data of a write made it to the appender,
+    /// which doesn't fail the write, as the caller would write the data
+    /// again. With `strict_full_disk`, a full disk still leaves the appender
+    /// degraded.
+    fn report_after_write(&mut self, e: io::Error, error: fn(PathBuf, io::Error) -> RollingFileError) {
+        if self.strict_full_disk && e.kind() == io::ErrorKind::StorageFull {
+            self.degraded = true;
+        }
+        let path = PathBuf::from(&self.base_filename);
+        self.report(error(path, e));
+    }
+
+    /// Takes the lock serializing rollovers between processes, if enabled,
+    /// waiting for it if needed. The lock is released when the returned
+    /// file is dropped.
+    fn lock_rotation(&self) -> io::Result<Option<File>> {
+        if !self.lock_file {
+            return Ok(None);
+        }
+        let mut p = self.base_filename.clone();
+        p.push(".lock");
+        let file = OpenOptions::new().write(true).create(true).truncate(false).open(p)?;
+        file.lock()?;
+        Ok(Some(file))
+    }
+
+    /// Remembers which file the current file was when it was opened, if a
+    /// lock file is used, so that a rotation by another process can be told
+    /// even once the file is closed.
+    #[cfg(unix)]
+    fn remember_current_file(&mut self) {
+        use std::os::unix::fs::MetadataExt;
+
+        if self.lock_file {
+            self.current_file_id_opt = self
+                .writer_opt
+                .as_ref()
+                .and_then(ActiveWriter::file)
+                .and_then(|f| f.metadata().ok())
+                .map(|m| (m.dev(), m.ino()));
+        }
+    }
+
+    /// Determines whether the current file this appender last opened was
+    /// already rotated, open or not, by another process sharing the lock
+    /// file, in which case the current file is a different one by now. An
+    /// appender that never opened the current file rotates whatever it is.
+    #[cfg(unix)]
+    fn rotated_elsewhere(&self) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        let opened = match self.current_file_id_opt {
+            Some(id) => id,
+            None => return false,
+        };
+        match fs::metadata(self.filename_for(0)) {
+            Ok(m) => (m.dev(), m.ino()) != opened,
+            Err(e) => e.kind() == io::ErrorKind::NotFound,
+        }
+    }
+
+    /// Determines whether the open current file was already rotated by
+    /// another process. Open files can't be renamed on this platform, so it
+    /// can't have been.
+    #[cfg(not(unix))]
+    fn rotated_elsewhere(&self) -> bool {
+        false
+    }
+
+    /// Compresses a rotated file if it should be, and updates its checksum
+    /// sidecar and permissions. Returns whether it was compressed.
+    fn compress_archive(&self, archive: Archive) -> io::Result<bool> {
+        #[cfg(feature = "checksum")]
+        let checksum_before_opt = self.checksum_before_compression(archive)?;
+        let compressed = self.compress_file(archive)?;
+        #[cfg(feature = "checksum")]
+        self.write_checksum(archive, checksum_before_opt)?;
+        #[cfg(unix)]
+        self.make_read_only(archive)?;
+        Ok(compressed)
+    }
+
+    /// Computes the checksum of a rotated file before it is compressed, if
+    /// its sidecar should hold that.
+    #[cfg(feature = "checksum")]
+    fn checksum_before_compression(&self, archive: Archive) -> io::Result<Option<String>> {
+        if self.checksum_opt == Some(Checksum::BeforeCompression) {
+            let [plain, _] = self.archive_paths(archive);
+            match File::open(plain) {
+                Ok(f) => return checksum::sha256(f).map(Some),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes the checksum sidecar of a rotated file as stored, if enabled,
+    /// using the checksum taken before compression if there is one. Any
+    /// sidecar left from before the file was compressed is removed.
+    #[cfg(feature = "checksum")]
+    fn write_checksum(&self, archive: Archive, checksum_before_opt: Option<String>) -> io::Result<()> {
+        if self.checksum_opt.is_some() {
+            let [plain, compressed] = self.archive_paths(archive);
+            if compressed.exists() {
+                let _ = fs::remove_file(checksum::sidecar_for(plain.as_os_str()));
+            }
+            for p in &[compressed, plain] {
+                let f = match File::open(p) {
+                    Ok(f) => f,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+                let sum = match checksum_before_opt {
+                    Some(sum) => sum,
+                    None => checksum::sha256(f)?,
+                };
+                return checksum::write_sidecar(p.as_os_str(), &sum);
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes a rotated file read-only (mode 0444) if the appender is set up
+    /// to do so, whether or not it is compressed.
+    #[cfg(unix)]
+    fn make_read_only(&self, archive: Archive) -> io::Result<()> {
+        self.set_read_only(&self.archive_paths(archive))
+    }
+
+    /// Makes the given files read-only (mode 0444) if the appender is set up
+    /// to do so, skipping those that don't exist.
+    #[cfg(unix)]
+    fn set_read_only<P: AsRef<Path>>(&self, paths: &[P]) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if self.read_only_archives {
+            for p in paths {
+                match fs::set_permissions(p, fs::Permissions::from_mode(0o444)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {},
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the file system the files are handled with.
+    fn file_system(&self) -> &dyn FileSystem {
+        match self.file_system_opt.as_ref() {
+            Some(fs) => fs.as_ref(),
+            None => &RealFileSystem,
+        }
+    }
+
+    /// Determines whether the file exists on the file system.
+    fn exists<P: AsRef<Path>>(&self, p: P) -> bool {
+        self.file_system().metadata(p.as_ref()).is_ok()
+    }
+
+    /// Returns the directory holding the current file. All features that
+    /// look at the directory go through this, so that a bare filename without
+    /// a parent (e.g. "app.log") consistently refers to the current directory.
+    fn directory(&self) -> &Path {
+        match Path::new(&self.base_filename).parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        }
+    }
+
+    /// Determines whether the current file reached the size computed from
+    /// `max_size_fraction`. An empty file never did, even if the computed
+    /// size is zero on a nearly full disk.
+    fn over_max_size(&self) -> bool {
+        self.max_size_opt
+            .is_some_and(|max_size| self.rolling_size() >= max_size.max(1))
+    }
+
+    /// Determines whether free space has dropped below the configured
+    /// minimum. This only returns true once until space is available again,
+    /// so that a shortage doesn't cause a rollover on every write.
+    fn low_on_space(&mut self) -> bool {
+        if let Some(min_free_bytes) = self.min_free_bytes_opt {
+            match disk::available_space(self.directory()) {
+                Ok(available) if available < min_free_bytes => {
+                    let first = !self.low_space_rolled;
+                    self.low_space_rolled = true;
+                    return first;
+                },
+                Ok(_) => self.low_space_rolled = false,
+                // if we can't tell, carry on as usual
+                Err(_) => {},
+            }
+        }
+        false
+    }
+
+    /// Deletes the oldest files until free space is back above the
+    /// configured minimum. The current file is never deleted.
+    fn prune_for_free_space(&self) {
+        if let Some(min_free_bytes) = self.min_free_bytes_opt {
+            for archive in self.archives().unwrap_or_default() {
+                match disk::available_space(self.directory()) {
+                    Ok(available) if available < min_free_bytes => self.remove_archive(archive),
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    /// Flushes any buffered data and closes the current file.
+    /// The file will be reopened by the next write.
+    pub fn close(&mut self) -> io::Result<()> {
+        self.drain_deferred()?;
+        self.flush_buffers()?;
+        self.finish_writer()?;
+        self.writer_opt.take();
+        if let Some(sink) = self.error_sink_opt.as_mut() {
+            sink.appender.close()?;
+        }
+        Ok(())
+    }
+
+    /// Returns a reader over the rotated files followed by the current file,
+    /// from oldest to newest, decompressing compressed files. Flush the
+    /// appender first to include all data written so far.
+    pub fn reader(&self) -> io::Result<RollingFileReader> {
+        let mut files: Vec<_> = self
+            .archives()?
+            .into_iter()
+            .map(|archive| {
+                let [plain, compressed] = self.archive_paths(archive);
+                if self.exists(&compressed) {
+                    (compressed, true)
+                } else {
+                    (plain, false)
+                }
+            })
+            .collect();
+        files.push((PathBuf::from(self.filename_for(0)), self.stream_compression));
+        Ok(RollingFileReader::new(files))
+    }
+
+    /// Suppresses all automatic rollovers, e.g. so that a bulk import lands in
+    /// a single file, until `resume_rolling()` is called. The rolling
+    /// condition isn't even evaluated while paused. Explicit calls to
+    /// `rollover()` still take effect.
+    pub fn pause_rolling(&mut self) {
+        self.rolling_paused = true;
+    }
+
+    /// Resumes automatic rollovers after `pause_rolling()`. The next write
+    /// evaluates the rolling condition again, and may roll over right away.
+    pub fn resume_rolling(&mut self) {
+        self.rolling_paused = false;
+    }
+
+    /// Returns whether automatic rollovers are paused
+    pub fn is_rolling_paused(&self) -> bool {
+        self.rolling_paused
+    }
+
+    /// Flushes the current file like `flush()`, and returns how many buffered
+    /// bytes were written out by it, e.g. to report them at shutdown. This is
+    /// always 0 for a memory-mapped file, whose data isn't buffered.
+    pub fn flush_reporting(&mut self) -> io::Result<usize> {
+        let pending = self.writer_opt.as_ref().map_or(0, |w| w.buffered_len());
+        self.flush()?;
+        Ok(pending)
+    }
+
+    /// Returns whether the appender is degraded after running out of disk
+    /// space in strict full-disk mode, failing all writes until `reopen()`.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Discards the current writer, including any data it still buffers, and
+    /// opens the current file again. This ends the degraded state of strict
+    /// full-disk mode, and the null state of `allow_null_fallback`, once
+    /// opening succeeds.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        // don't try to flush what failed to be written before
+        match self.writer_opt.take() {
+            Some(ActiveWriter::Buffered(w)) => {
+                let _ = w.into_parts();
+            },
+            Some(ActiveWriter::Custom(w)) => {
+                let _ = w.into_parts();
+            },
+            #[cfg(target_os = "linux")]
+            Some(ActiveWriter::Direct(mut w)) => w.discard(),
+            _ => {},
+        }
+        self.open_writer_if_needed()?;
+        self.degraded = false;
+        self.null = false;
+        Ok(())
+    }
+
+    /// Returns whether the appender currently holds an open file, which it
+    /// doesn't e.g. after `close()` or a failed reopen.
+    pub fn is_open(&self) -> bool {
+        self.writer_opt.is_some()
+    }
+
+    /// Opens the current file unless it is already open. Otherwise, the file
+    /// is reopened by the next write.
+    pub fn ensure_open(&mut self) -> io::Result<()> {
+        self.open_writer_if_needed()?;
+        self.null = false;
+        Ok(())
+    }
+
+    /// Returns whether the appender discards all writes, because the current
+    /// file couldn't be opened when building it with
+    /// [`allow_null_fallback`](RollingFileAppenderBuilder::allow_null_fallback).
+    /// Reopening the file ends this.
+    pub fn is_null(&self) -> bool {
+        self.null
+    }
+
+    /// Returns the number of bytes discarded while
+    /// [`is_null`](RollingFileAppender::is_null).
+    pub fn null_dropped_bytes(&self) -> u64 {
+        self.null_dropped_bytes
+    }
+
+    /// Opens a writer for the current file, unless it is open already.
+    fn open_writer_if_needed(&mut self) -> io::Result<()> {
+        if self.writer_opt.is_none() && !self.rotate_only {
+            self.open_writer()?;
+            #[cfg(unix)]
+            self.remember_current_file();
+            #[cfg(unix)]
+            self.link_stable()?;
+            // A reopened file already has its preamble and header, only a
+            // file opened empty gets them.
+            if self.current_filesize == 0 {
+                self.write_preamble()?;
+                if self.sequence_header {
+                    self.write_sequence_header()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Points the stable hard link, if set, to the freshly opened current
+    /// file. The link is replaced atomically, so a reader opening it always
+    /// finds a file.
+    #[cfg(unix)]
+    fn link_stable(&self) -> io::Result<()> {
+        if let Some(stable) = self.stable_hardlink_opt.as_ref() {
+            let mut tmp = stable.clone().into_os_string();
+            tmp.push(".tmp");
+            let _ = fs::remove_file(&tmp);
+            fs::hard_link(self.filename_for(0), &tmp)?;
+            let r = fs::rename(&tmp, stable);
+            // renaming onto a link to the same file does nothing, e.g. when
+            // reopening the current file, and leaves the temporary link
+            let _ = fs::remove_file(&tmp);
+            r?;
+        }
+        Ok(())
+    }
+
+    /// Ends the data written to the current file before it is closed, which
+    /// only an encoder or a gzip stream needs.
+    fn finish_writer(&mut self) -> io::Result<()> {
+        if let (Some(encoder), Some(writer)) = (self.encoder_opt.as_mut(), self.writer_opt.as_mut()) {
+            self.current_filesize += encoder::finalize(encoder.as_mut(), writer)?;
+            writer.flush()?;
+        }
+        match self.writer_opt.as_mut() {
+            Some(ActiveWriter::Gzip(w)) => w.try_finish(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Opens a writer for the current file.
+    fn open_writer(&mut self) -> io::Result<()> {
+        let p = self.filename_for(0);
+        // what an existing file holds counts as payload
+        self.framing_bytes = 0;
+        #[cfg(feature = "mmap")]
+        {
+            if let Some(chunk_size) = self.mmap_chunk_size_opt {
+                let writer = mmap::MmapWriter::open(p.as_ref(), chunk_size)?;
+                self.current_filesize = writer.len();
+                self.writer_opt = Some(ActiveWriter::Mapped(writer));
+                return Ok(());
+            }
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if self.direct_io {
+                let writer = direct::DirectWriter::open(p.as_ref())?;
+                self.current_filesize = writer.len();
+                self.writer_opt = Some(ActiveWriter::Direct(writer));
+                return Ok(());
+            }
+        }
+        if self.stream_compression {
+            let writer = compression::GzipWriter::open(p.as_ref())?;
+            self.current_filesize = writer.len();
+            self.writer_opt = Some(ActiveWriter::Gzip(writer));
+            return Ok(());
+        }
+        if let Some(fs) = self.file_system_opt.as_ref() {
+            self.writer_opt = Some(ActiveWriter::Custom(BufWriter::new(fs.open_append(p.as_ref())?)));
+        } else {
+            self.writer_opt = Some(ActiveWriter::Buffered(BufWriter::new(
+                OpenOptions::new().append(true).create(true).open(&p)?,
+            )));
+        }
+        self.current_filesize = self.file_system().metadata(p.as_ref()).map_or(0, |m| m.len);
+        Ok(())
+    }
+
+    /// Writes the preamble, if there is one, to the freshly opened current
+    /// file.
+    fn write_preamble(&mut self) -> io::Result<()> {
+        if let (Some(preamble), Some(writer)) = (self.preamble_opt.as_ref(), self.writer_opt.as_mut()) {
+            writer.write_all(preamble)?;
+            self.current_filesize += preamble.len() as u64;
+            self.framing_bytes += preamble.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Determines the filename of the sidecar holding the last sequence
+    /// number written to a file header.
+    fn sequence_filename(&self) -> OsString {
+        let mut f = self.base_filename.clone();
+        f.push(".seq");
+        f
+    }
+
+    /// Writes a header with the next sequence number to the freshly opened
+    /// current file, after atomically updating the sidecar with it.
+    fn write_sequence_header(&mut self) -> io::Result<()> {
+        let p = self.sequence_filename();
+        let last = match fs::read_to_string(&p) {
+            Ok(x) => x.trim().parse::<u64>().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid sequence file {}: {}", p.to_string_lossy(), e),
+                )
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        let seq = last + 1;
+        let mut tmp = p.clone();
+        tmp.push(".tmp");
+        fs::write(&tmp, format!("{}\n", seq))?;
+        fs::rename(&tmp, &p)?;
+        let header = format!("#seq:{}\n", seq);
+        if let Some(writer) = self.writer_opt.as_mut() {
+            writer.write_all(header.as_bytes())?;
+            self.current_filesize += header.len() as u64;
+            self.framing_bytes += header.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Flushes the current file if the flush policy calls for it after a write.
+    fn flush_per_policy(&mut self, now_opt: &mut Option<DateTime<Local>>) -> io::Result<()> {
+        self.writes_since_flush += 1;
+        let flush = match self.flush_policy {
+            FlushPolicy::OnEveryWrite => true,
+            FlushPolicy::Interval(interval) => {
+                let now = self.time_of_write(now_opt);
+                match self.last_flush_opt {
+                    Some(last_flush) => now.signed_duration_since(last_flush) >= interval,
+                    None => {
+                        self.last_flush_opt = Some(now);
+                        false
+                    },
+                }
+            },
+            FlushPolicy::EveryN(n) => self.writes_since_flush >= n,
+            FlushPolicy::OnlyOnRollover => false,
+        };
+        if flush {
+            self.flush_buffers()?;
+            // only the interval policy needs it, which took the time
+            if let Some(now) = now_opt {
+                self.last_flush_opt = Some(*now);
+            }
+        }
+        Ok(())
+    }
+
+    /// Syncs the current file to disk on the first write after the sync
+    /// interval has elapsed since the last sync, if there is one.
+    fn sync_per_interval(&mut self, now_opt: &mut Option<DateTime<Local>>) -> io::Result<()> {
+        if let Some(interval) = self.sync_interval_opt {
+            let now = self.time_of_write(now_opt);
+            match self.last_sync_opt {
+                Some(last_sync) if now.signed_duration_since(last_sync) < interval => {},
+                Some(_) => {
+                    if let Some(writer) = self.writer_opt.as_mut() {
+                        writer.sync_data()?;
+                    }
+                    self.last_sync_opt = Some(now);
+                },
+                None => self.last_sync_opt = Some(now),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies data to the error sink if there is one and the data matches its
+    /// predicate. The data already made it to the current file, so failures
+    /// are only reported.
+    fn write_to_error_sink(&mut self, buf: &[u8], now_opt: &mut Option<DateTime<Local>>) {
+        if self.error_sink_opt.is_none() {
+            return;
+        }
+        let now = self.time_of_write(now_opt);
+        let mut error_opt = None;
+        if let Some(sink) = self.error_sink_opt.as_mut() {
+            if (sink.predicate)(buf) {
+                if let Err(e) = sink.appender.write_with_datetime(buf, &now) {
+                    error_opt = Some(RollingFileError::ErrorSink {
+                        path: PathBuf::from(&sink.appender.base_filename),
+                        source: e,
+                    });
+                }
+            }
+        }
+        if let Some(e) = error_opt {
+            self.report(e);
+        }
+    }
+
+    /// Writes data using the given datetime to calculate the rolling condition.
+    /// Unless configured otherwise, writing an empty buffer does nothing at
+    /// all: neither is the condition evaluated, nor does it count as a write.
+    pub fn write_with_datetime(&mut self, buf: &[u8], now: &DateTime<Local>) -> io::Result<usize> {
+        self.write_at(buf, Some(*now))
+    }
+
+    /// Writes data at the given datetime, or if there is none, because
+    /// nothing needs it, without getting the current time unless something
+    /// does after all.
+    fn write_at(&mut self, buf: &[u8], mut now_opt: Option<DateTime<Local>>) -> io::Result<usize> {
+        // an empty record is still a record
+        if buf.is_empty() && self.skip_empty_writes && self.length_prefix_opt.is_none() {
+            return Ok(0);
+        }
+        if self.null {
+            self.null_dropped_bytes += buf.len() as u64;
+            return Ok(buf.len());
+        }
+        let r = if self.degraded {
+            Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "logfile {} is degraded after running out of disk space, call reopen() once space is available",
+                    self.base_filename.to_string_lossy()
+                ),
+            ))
+        } else if self.rotate_only {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "logfile {} is only rotated, not written to",
+                    self.base_filename.to_string_lossy()
+                ),
+            ))
+        } else {
+            self.write_in_chunks(buf, &mut now_opt)
+        };
+        match &r {
+            Ok(n) => {
+                self.metrics.writes += 1;
+                self.metrics.bytes_written += *n as u64;
+            },
+            Err(e) => {
+                self.metrics.write_errors += 1;
+                if self.strict_full_disk && e.kind() == io::ErrorKind::StorageFull {
+                    self.degraded = true;
+                }
+            },
+        }
+        r
+    }
+
+    /// Writes several records at once using the given datetime to calculate
+    /// the rolling condition, which is evaluated per record or per batch
+    /// depending on the configured [`BatchRollover`]. Returns the total
+    /// number of bytes written. If writing a record fails, the error is
+    /// returned, and the remaining records are not written. With
+    /// `max_buffered_bytes` set, the batch is flushed along the way whenever
+    /// that many bytes are buffered, and a failed flush is reported rather
+    /// than failing the batch.
+    pub fn write_batch(&mut self, records: &[&[u8]], now: &DateTime<Local>) -> io::Result<usize> {
+        self.write_all_from(records, now)
+    }
+
+    /// Like [`write_batch`](Self::write_batch), for records taken from an
+    /// iterator as they are written, e.g. lines formatted lazily, so they
+    /// needn't be collected first. The iterator isn't advanced any further
+    /// once writing a record fails.
+    pub fn write_all_from<I>(&mut self, records: I, now: &DateTime<Local>) -> io::Result<usize>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        if self.batch_rollover == BatchRollover::PerBatch {
+            self.batch_records_opt = Some(0);
+        }
+        let mut r = Ok(0);
+        for record in records {
+            match self.write_with_datetime(record.as_ref(), now) {
+                Ok(n) => {
+                    r = r.map(|total| total + n);
+                    if n > 0 {
+                        self.batch_records_opt = self.batch_records_opt.map(|records| records + 1);
+                    }
+                    self.flush_if_over_buffer_cap();
+                },
+                Err(e) => {
+                    r = Err(e);
+                    break;
+                },
+            }
+        }
+        if self.batch_records_opt.take().is_some_and(|records| records > 0) {
+            self.end_batch(&mut Some(*now));
+        }
+        r
+    }
+
+    /// Ends a batch written with [`BatchRollover::PerBatch`], applying the
+    /// flush policy and evaluating the condition once for all of it, as
+    /// after a single write. The records were already written, so failures
+    /// are only reported.
+    fn end_batch(&mut self, now_opt: &mut Option<DateTime<Local>>) {
+        if self.writer_opt.is_none() || self.deferred_rollover_opt.is_some() {
+            return;
+        }
+        if let Err(e) = self.flush_per_policy(now_opt) {
+            self.report_after_write(e, |path, source| RollingFileError::Flush { path, source });
+        }
+        self.rollover_after_write(now_opt);
+    }
+
+    /// Flushes if at least `max_buffered_bytes` are buffered. The record was
+    /// already written, so failures are only reported.
+    fn flush_if_over_buffer_cap(&mut self) {
+        let buffered = self.writer_opt.as_ref().map_or(0, ActiveWriter::buffered_len);
+        match self.max_buffered_bytes_opt {
+            Some(cap) if buffered >= cap => {
+                if let Err(e) = self.flush_buffers() {
+                    self.report_after_write(e, |path, source| RollingFileError::Flush { path, source });
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Determines whether anything looks at the datetime of writes. If not,
+    /// e.g. when only rolling over by size, writes skip getting the current
+    /// time.
+    fn needs_time(&self) -> bool {
+        self.condition.needs_time()
+            || matches!(self.flush_policy, FlushPolicy::Interval(_))
+            || self.sync_interval_opt.is_some()
+            || self.error_sink_opt.is_some()
+            || self.event_sender_opt.is_some()
+            || self.notifies_socket()
+            || self.line_timestamp_format_opt.is_some()
+            || self.numbering == NumberingScheme::DateGrouped
+    }
+
+    /// Returns the current time according to the clock, if one is set, or to
+    /// the condition otherwise.
+    fn now(&self) -> DateTime<Local> {
+        match self.clock_opt.as_ref() {
+            Some(clock) => clock.now(),
+            None => self.condition.now(),
+        }
+    }
+
+    /// Returns the datetime of the current write, taking the current time
+    /// from the clock if the write skipped getting it.
+    fn time_of_write(&mut self, now_opt: &mut Option<DateTime<Local>>) -> DateTime<Local> {
+        let now = *now_opt.get_or_insert_with(|| self.now());
+        self.untimed_now = now;
+        now
+    }
+
+    /// Returns the datetime the condition is evaluated with for the current
+    /// write, which is the last one taken if the write skipped getting it, as
+    /// the condition doesn't look at it then.
+    fn condition_time(&self, now_opt: &Option<DateTime<Local>>) -> DateTime<Local> {
+        now_opt.unwrap_or(self.untimed_now)
+    }
+
+    /// Returns why the condition asked for the rollover.
+    fn condition_reason(&self) -> RolloverReason {
+        self.condition.rollover_reason().unwrap_or(RolloverReason::Condition)
+    }
+
+    /// Rolls over as part of a write. Failures are only reported, unless they
+    /// are bound to repeat.
+    fn rollover_for_write(&mut self, now_opt: &mut Option<DateTime<Local>>, reason: RolloverReason) -> io::Result<()> {
+        let now = self.time_of_write(now_opt);
+        let retry = self.deferred_rollover_opt.take().is_some();
+        if let Err(e) = self.rollover_with_reason(&now, reason) {
+            if self.strict_full_disk && e.kind() == io::ErrorKind::StorageFull {
+                return Err(e);
+            }
+            if self.deferred_opt.is_some() {
+                // retried by the next write, which is held until then
+                self.deferred_rollover_opt = Some(reason);
+                // a deferral is only reported once, not on every retry
+                if !retry {
+                    self.report(RollingFileError::RotateDeferred {
+                        path: PathBuf::from(&self.base_filename),
+                        source: e,
+                    });
+                }
+                return Ok(());
+            }
+            // If the data can't even be flushed, writing more of it is
+            // pointless, so give up after a few attempts.
+            if self.rollover_flush_failures >= self.max_flush_failures {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!(
+                        "failed to flush logfile {} for rollover {} times in a row: {}",
+                        self.base_filename.to_string_lossy(),
+                        self.rollover_flush_failures,
+                        e
+                    ),
+                ));
+            }
+            // If we can't rollover, just try to continue writing anyway
+            // (better than missing data).
+            self.report(RollingFileError::Rotate {
+                path: PathBuf::from(&self.base_filename),
+                source: e,
+            });
+        }
+        Ok(())
+    }
+
+    /// Deletes the trigger file if there is one, returning whether it
+    /// existed. Deleting rather than checking for it first makes sure only
+    /// one appender rolls over if several watch the same file.
+    fn take_trigger_file(&self) -> bool {
+        match self.trigger_file_opt.as_ref() {
+            Some(p) => self.file_system().remove_file(p).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Keeps the data in the fallback buffer after the current file couldn't
+    /// be opened or written to, if there is one, so that it is written once
+    /// the file can be reopened. Otherwise, returns the error.
+    fn write_to_fallback_buffer(&mut self, buf: &[u8], e: io::Error) -> io::Result<usize> {
+        match self.fallback_opt.as_mut() {
+            Some(fallback) => {
+                fallback.push(buf);
+                // try opening the file again on the next write
+                self.abandon_writer();
+                Ok(buf.len())
+            },
+            None => Err(e),
+        }
+    }
+
+    /// Drops the current file after writing to it failed, so that it is
+    /// reopened by the next write. Data the file still buffered goes to the
+    /// front of the fallback buffer rather than being lost.
+    fn abandon_writer(&mut self) {
+        let unwritten = match self.writer_opt.take() {
+            Some(ActiveWriter::Buffered(w)) => w.into_parts().1.unwrap_or_default(),
+            Some(ActiveWriter::Custom(w)) => w.into_parts().1.unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        if let Some(fallback) = self.fallback_opt.as_mut() {
+            if !unwritten.is_empty() {
+                let held = fallback.take();
+                fallback.push(&unwritten);
+                fallback.push(&held);
+            }
+        }
+    }
+
+    /// Writes the data held in the buffer of deferred writes if `deferred`
+    /// is set, or in the fallback buffer otherwise, if any, reopening the
+    /// current file if needed. Fails if the data is still held.
+    fn drain_held(&mut self, deferred: bool) -> io::Result<()> {
+        if self.held_writes(deferred).is_none_or(|held| held.is_empty()) {
+            return Ok(());
+        }
+        if let Err(e) = self
+            .open_writer_if_needed()
+            .and_then(|()| self.drain_held_writes(deferred))
+        {
+            self.abandon_writer();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Retries the rollover writes were deferred on, if any, and writes the
+    /// data held meanwhile once it succeeds. Fails if the data is still
+    /// held.
+    fn drain_deferred(&mut self) -> io::Result<()> {
+        if self.deferred_opt.is_none() {
+            return Ok(());
+        }
+        if let Some(reason) = self.deferred_rollover_opt.take() {
+            let now = self.now();
+            if let Err(e) = self.rollover_with_reason(&now, reason) {
+                self.deferred_rollover_opt = Some(reason);
+                return Err(e);
+            }
+        }
+        self.drain_held(true)
+    }
+
+    /// Returns the buffer holding deferred writes if `deferred` is set, or
+    /// the fallback buffer otherwise, if there is one.
+    fn held_writes(&mut self, deferred: bool) -> Option<&mut fallback::FallbackBuffer> {
+        if deferred {
+            self.deferred_opt.as_mut()
+        } else {
+            self.fallback_opt.as_mut()
+        }
+    }
+
+    /// Writes the data held in the buffer of deferred writes if `deferred`
+    /// is set, or in the fallback buffer otherwise, to the current file.
+    fn drain_held_writes(&mut self, deferred: bool) -> io::Result<()> {
+        if self.writer_opt.is_none() {
+            return Ok(());
+        }
+        let data = match self.held_writes(deferred) {
+            Some(held) if !held.is_empty() => held.take(),
+            _ => return Ok(()),
+        };
+        if let Some(writer) = self.writer_opt.as_mut() {
+            let mut taken = 0;
+            let written = match encoder::write_encoded(self.encoder_opt.as_mut(), &data, writer, &mut taken) {
+                Ok(written) => written,
+                Err(e) => {
+                    if let Some(held) = self.held_writes(deferred) {
+                        held.push(&data[taken..]);
+                    }
+                    return Err(e);
+                },
+            };
+            self.current_filesize += written;
+            self.condition.on_write(u64::try_from(data.len()).unwrap_or(u64::MAX));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes dropped from the fallback buffer, and
+    /// from the buffer of writes deferred by a failed rollover, because they
+    /// were full.
+    pub fn fallback_dropped_bytes(&self) -> u64 {
+        self.fallback_opt.as_ref().map_or(0, fallback::FallbackBuffer::dropped)
+            + self.deferred_opt.as_ref().map_or(0, fallback::FallbackBuffer::dropped)
+    }
+
+    /// Returns the data with the timestamp prefixed to each line that starts
+    /// in it, if line timestamps are enabled.
+    fn prefix_line_timestamps(&mut self, buf: &[u8], now_opt: &mut Option<DateTime<Local>>) -> Option<Vec<u8>> {
+        // the time is only needed if there is a format
+        self.line_timestamp_format_opt.as_ref()?;
+        let now = self.time_of_write(now_opt);
+        let format = self.line_timestamp_format_opt.as_ref()?;
+        let mut stamp = String::new();
+        // the format was validated, but formatting must never panic
+        let _ = fmt::Write::write_fmt(&mut stamp, format_args!("{}", now.format(format)));
+        let mut prefixed = Vec::with_capacity(buf.len() + stamp.len());
+        for line in buf.split_inclusive(|b| *b == b'\n') {
+            if self.at_line_start {
+                prefixed.extend_from_slice(stamp.as_bytes());
+            }
+            prefixed.extend_from_slice(line);
+            self.at_line_start = line.ends_with(b"\n");
+        }
+        Some(prefixed)
+    }
+
+    /// Returns the size of the current file that the size limits go by, which
+    /// leaves out the bytes added by the appender unless
+    /// `size_counts_framing` is set.
+    fn rolling_size(&self) -> u64 {
+        if self.size_counts_framing {
+            self.current_filesize
+        } else {
+            self.current_filesize.saturating_sub(self.framing_bytes)
+        }
+    }
+
+    /// Every `size_check_interval` writes, compares the tracked size of the
+    /// current file against its size on disk, and resyncs it if the file
+    /// shrank, i.e. was truncated by someone else.
+    fn check_file_size(&mut self) {
+        let interval = match self.size_check_interval_opt {
+            Some(x) => x,
+            None => return,
+        };
+        self.writes_since_size_check += 1;
+        if self.writes_since_size_check < interval {
+            return;
+        }
+        self.writes_since_size_check = 0;
+        let buffered = match self.writer_opt.as_ref() {
+            Some(writer) => writer.buffered_len() as u64,
+            None => return,
+        };
+        let p = self.filename_for(0);
+        // a file that is gone is recreated by the next rollover
+        let on_disk = match self.file_system().metadata(p.as_ref()) {
+            Ok(m) => m.len,
+            Err(_) => return,
+        };
+        if on_disk + buffered < self.current_filesize {
+            let tracked = self.current_filesize;
+            // appending continues at the new end of the file
+            self.current_filesize = on_disk + buffered;
+            self.report(RollingFileError::Truncated {
+                path: PathBuf::from(p),
+                tracked,
+                on_disk,
+            });
+        }
+    }
+
+    /// Rotates an empty file for each of the given periods that passed
+    /// without writes, right after rotating the file written before them.
+    fn fill_skipped_periods(&mut self, skipped: Vec<DateTime<Local>>) -> io::Result<()> {
+        // any older files would be pruned right away
+        let keep = match self.max_files {
+            0 => skipped.len(),
+            n => n.min(skipped.len()),
+        };
+        for start in &skipped[skipped.len() - keep..] {
+            // the empty file is named for its period, not its creation
+            self.current_file_date_opt = Some(start.date_naive());
+            self.rollover_for_write(&mut Some(*start), RolloverReason::Time)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the data in chunks of at most `max_write_size`, if set, rolling
+    /// over between them as needed. If any chunk fails, the error is
+    /// returned, even if earlier chunks were written, so that the write
+    /// never looks like it took the whole record when it didn't.
+    fn write_in_chunks(&mut self, buf: &[u8], now_opt: &mut Option<DateTime<Local>>) -> io::Result<usize> {
+        match self.max_write_size_opt {
+            Some(max) if buf.len() > max => {
+                let mut written = 0;
+                for chunk in buf.chunks(max) {
+                    written += self.rollover_if_needed_and_write(chunk, now_opt)?;
+                }
+                Ok(written)
+            },
+            _ => self.rollover_if_needed_and_write(buf, now_opt),
+        }
+    }
+
+    /// Rolls over if the condition says so, then writes the data.
+    fn rollover_if_needed_and_write(&mut self, buf: &[u8], now_opt: &mut Option<DateTime<Local>>) -> io::Result<usize> {
+        let framed_opt = match self.length_prefix_opt {
+            Some(prefix) => Some(prefix.frame(buf)?),
+            None => None,
+        };
+        self.check_file_size();
+        if let Some(reason) = self.deferred_rollover_opt {
+            self.rollover_for_write(now_opt, reason)?;
+        } else if !self.rolling_paused && self.batch_records_opt.is_none_or(|records| records == 0) {
+            // within a batch, only before its first record
+            if self.take_trigger_file() {
+                self.rollover_for_write(now_opt, RolloverReason::TriggerFile)?;
+            } else {
+                let now = self.condition_time(now_opt);
+                let skipped = if self.fill_gaps {
+                    self.condition.skipped_periods(&now)
+                } else {
+                    Vec::new()
+                };
+                if self.condition.should_rollover(&now, self.rolling_size()) {
+                    let rollovers = self.metrics.rollovers;
+                    self.rollover_for_write(now_opt, self.condition_reason())?;
+                    // only if the file written before the gap was rotated
+                    if self.metrics.rollovers > rollovers {
+                        self.fill_skipped_periods(skipped)?;
+                    }
+                } else if self.over_max_size() {
+                    self.rollover_for_write(now_opt, RolloverReason::Size)?;
+                } else if self.low_on_space() {
+                    self.rollover_for_write(now_opt, RolloverReason::LowDiskSpace)?;
+                }
+            }
+        }
+        let prefixed_opt = framed_opt.or_else(|| self.prefix_line_timestamps(buf, now_opt));
+        let data = prefixed_opt.as_deref().unwrap_or(buf);
+        let buf_len = buf.len();
+        if self.deferred_rollover_opt.is_some() {
+            if let Some(deferred) = self.deferred_opt.as_mut() {
+                deferred.push(data);
+            }
+            return Ok(buf_len);
+        }
+        if let Err(e) = self
+            .open_writer_if_needed()
+            .and_then(|()| self.drain_held_writes(true))
+            .and_then(|()| self.drain_held_writes(false))
+        {
+            return self.write_to_fallback_buffer(data, e).map(|_| buf_len);
+        }
+        if let Some(writer) = self.writer_opt.as_mut() {
+            let mut taken = 0;
+            let written = match encoder::write_encoded(self.encoder_opt.as_mut(), data, writer, &mut taken) {
+                Ok(written) => written,
+                Err(e) => return self.write_to_fallback_buffer(&data[taken..], e).map(|_| buf_len),
+            };
+            let len = u64::try_from(data.len()).unwrap_or(u64::MAX);
+            self.current_filesize = match writer {
+                ActiveWriter::Gzip(w) => w.len(),
+                _ => self.current_filesize + written,
+            };
+            let framing = (data.len() - buf_len) as u64;
+            self.framing_bytes += framing;
+            // only dated numbering needs it, which gets the time of writes
+            if let Some(now) = now_opt {
+                self.current_file_date_opt = Some(now.date_naive());
+            }
+            self.condition
+                .on_write(if self.size_counts_framing { len } else { len - framing });
+            // a batch is flushed and evaluated as a whole once it ends
+            let in_batch = self.batch_records_opt.is_some();
+            if !in_batch {
+                if let Err(e) = self.flush_per_policy(now_opt) {
+                    self.report_after_write(e, |path, source| RollingFileError::Flush { path, source });
+                }
+            }
+            if let Err(e) = self.sync_per_interval(now_opt) {
+                self.report_after_write(e, |path, source| RollingFileError::Sync { path, source });
+            }
+            self.write_to_error_sink(buf, now_opt);
+            if !in_batch {
+                self.rollover_after_write(now_opt);
+            }
+            Ok(buf_len)
+        } else {
+            Err(self.writer_missing())
+        }
+    }
+
+    /// Rolls over if the condition says so right after a write. The write
+    /// already succeeded, so a failed rollover is reported, and retried by
+    /// the next write.
+    fn rollover_after_write(&mut self, now_opt: &mut Option<DateTime<Local>>) {
+        let now = self.condition_time(now_opt);
+        if !self.rolling_paused && self.condition.should_rollover_after_write(&now, self.rolling_size()) {
+            let reason = self.condition_reason();
+            if let Err(e) = self.rollover_for_write(now_opt, reason) {
+                self.deferred_rollover_opt = Some(reason);
+                self.report_after_write(e, |path, source| RollingFileError::Rotate { path, source });
+            }
+        }
+    }
+
+    /// Flushes the current file and the error sink, without rolling over,
+    /// after writing the data held in the fallback buffer.
+    fn flush_buffers(&mut self) -> io::Result<()> {
+        self.drain_held(false)?;
+        if let Some(writer) = self.writer_opt.as_mut() {
+            writer.flush()?;
+        }
+        self.writes_since_flush = 0;
+        if let Some(sink) = self.error_sink_opt.as_mut() {
+            sink.appender.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Rolls over if the condition says so at its current time, as part of a
+    /// flush with `roll_on_flush` set. A file nothing was written to yet is
+    /// left alone, and taken to belong to the new period instead.
+    fn roll_on_flush_if_needed(&mut self) -> io::Result<()> {
+        if self.rolling_paused || self.degraded || self.writer_opt.is_none() {
+            return Ok(());
+        }
+        let now = self.now();
+        if self.condition.should_rollover(&now, self.rolling_size()) {
+            if self.rolling_size() == 0 {
+                self.condition.on_rollover();
+            } else {
+                self.rollover_for_write(&mut Some(now), self.condition_reason())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Describes the appender's state when there is unexpectedly no current
+    /// file to write to.
+    fn writer_missing(&self) -> io::Error {
+        io::Error::other(RollingFileError::WriterMissing {
+            path: PathBuf::from(&self.base_filename),
+            null: self.null,
+            degraded: self.degraded,
+            rotate_only: self.rotate_only,
+        })
+    }
+}
+
+impl<RC> io::Write for RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the time is taken later if something turns out to need it after
+        // all, e.g. a rollover
+        let now_opt = if self.needs_time() {
+            Some(self.clock_opt.as_ref().map_or_else(Local::now, |clock| clock.now()))
+        } else {
+            None
+        };
+        self.write_at(buf, now_opt)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.drain_deferred()?;
+        self.flush_buffers()?;
+        if self.roll_on_flush {
+            self.roll_on_flush_if_needed()?;
+        }
+        Ok(())
+    }
+}
+
+impl<RC> Drop for RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Tries to write the data still held, which is lost otherwise. The file
+    /// flushes itself when it is closed.
+    fn drop(&mut self) {
+        if let Err(e) = self.drain_deferred() {
+            // better written to the current file than lost
+            self.deferred_rollover_opt = None;
+            self.report(RollingFileError::Rotate {
+                path: PathBuf::from(&self.base_filename),
+                source: e,
+            });
+        }
+        if let Err(e) = self.drain_held(true).and_then(|()| self.drain_held(false)) {
+            self.report(RollingFileError::Flush {
+                path: PathBuf::from(&self.base_filename),
+                source: e,
+            });
+        }
+    }
+}
+
+/// Builds a rolling file appender with options beyond the rolling condition
+/// and the number of files to keep.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn docs() {
+/// # use rolling_file::*;
+/// let file_appender = BasicRollingFileAppender::builder(
+///     "/var/log/myprogram",
+///     RollingConditionBasic::new().daily(),
+///     9
+/// )
+/// .flush_policy(FlushPolicy::OnEveryWrite)
+/// .build()
+/// .unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RollingFileAppenderBuilder<RC>
+where
+    RC: RollingCondition,
+{
+    appender: RollingFileAppender<RC>,
+}
+
+impl<RC> RollingFileAppenderBuilder<RC>
+where
+    RC: RollingCondition,
+{
+    /// Sets when buffered data is flushed to the file
+    pub fn flush_policy(mut self, x: FlushPolicy) -> RollingFileAppenderBuilder<RC> {
+        self.appender.flush_policy = x;
+        self
+    }
+
+    /// Sets a minimum amount of free space to keep on the volume holding the
+    /// files. When free space drops below it, the file is rolled over, and the
+    /// oldest files are deleted until enough space is available again (or
+    /// only the current file is left). Note that this queries the volume on
+    /// every write.
+    pub fn min_free_bytes(mut self, x: u64) -> RollingFileAppenderBuilder<RC> {
+        self.appender.min_free_bytes_opt = Some(x);
+        self
+    }
+
+    /// Sets a size to roll over at, as a fraction of the total capacity of
+    /// the volume holding the files, e.g. `0.1` for 10% of the disk. The
+    /// capacity is queried once, when the appender is built, and building
+    /// fails if it can't be. This applies in addition to the rolling
+    /// condition, and is reported as a size-based rollover.
+    pub fn max_size_fraction(mut self, x: f64) -> RollingFileAppenderBuilder<RC> {
+        self.appender.max_size_fraction_opt = Some(x);
+        self
+    }
+
+    /// Sets how many of the most recent rotated files stay uncompressed, e.g.
+    /// to keep grepping the previous file easy. A file is compressed during
+    /// the rollover that makes it older than that instead.
+    pub fn keep_recent_uncompressed(mut self, x: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.keep_recent_uncompressed = x;
+        self
+    }
+
+    /// Sets files to be compressed as they are rotated. Compression happens
+    /// synchronously as part of the rollover, once the new file is opened.
+    /// If compressing fails, the rollover still succeeds: the file is left
+    /// uncompressed, the failure is reported like other internal errors (see
+    /// [`internal_error_handler`](RollingFileAppenderBuilder::internal_error_handler)),
+    /// and compressing it is tried again with the next rollover.
+    pub fn compression(mut self, x: Compression) -> RollingFileAppenderBuilder<RC> {
+        self.appender.compression_opt = Some(x);
+        self
+    }
+
+    /// Sets a minimum size for rotated files to be compressed. Smaller files
+    /// are left uncompressed, since compressing them is hardly worth it.
+    pub fn compress_min_size(mut self, x: u64) -> RollingFileAppenderBuilder<RC> {
+        self.appender.compress_min_size = x;
+        self
+    }
+
+    /// Sets how many times compressing a rotated file is attempted, waiting
+    /// the given delay between attempts, e.g. to get past a transient lack
+    /// of disk space. Without this, compressing is attempted once. If all
+    /// attempts fail, the rollover still succeeds, see
+    /// [`compression`](RollingFileAppenderBuilder::compression).
+    ///
+    /// The delays are slept on the thread rolling over, so a failing
+    /// compression blocks the write or `rollover()` call that triggered it
+    /// for `(attempts - 1) * delay` on top of the attempts themselves. Keep
+    /// both small where writes must not stall.
+    pub fn compress_retry(mut self, attempts: usize, delay: std::time::Duration) -> RollingFileAppenderBuilder<RC> {
+        self.appender.compress_retry_opt = Some((attempts, delay));
+        self
+    }
+
+    /// Sets whether the rolling condition is informed of the modification
+    /// time of an existing non-empty file when it is opened, so that e.g. a
+    /// daily condition rolls over on the first write after a restart on a
+    /// different day.
+    pub fn seed_last_write(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.seed_last_write = x;
+        self
+    }
+
+    /// Sets how many times in a row flushing the file for a rollover may fail
+    /// (e.g. because the disk is full) before writes fail as well, instead
+    /// of piling up more data that can't be flushed. Until then, failures are
+    /// reported on stderr, and data is still written to the current file.
+    /// Defaults to [`DEFAULT_MAX_FLUSH_FAILURES`].
+    pub fn max_flush_failures(mut self, x: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.max_flush_failures = x;
+        self
+    }
+
+    /// Sets how rotated files are numbered
+    pub fn numbering(mut self, x: NumberingScheme) -> RollingFileAppenderBuilder<RC> {
+        self.appender.numbering = x;
+        self
+    }
+
+    /// Sets a secondary file that additionally receives every write for which
+    /// the predicate returns true, e.g. to keep errors in a separate file.
+    /// The secondary file is rolled over daily, independently of the current
+    /// file, and keeps as many files as the appender (as given when this is
+    /// called). A failure to write to it is reported like other internal
+    /// errors, see
+    /// [`internal_error_handler`](RollingFileAppenderBuilder::internal_error_handler).
+    pub fn error_sink<P, F>(mut self, path: P, predicate: F) -> RollingFileAppenderBuilder<RC>
+    where
+        P: AsRef<Path>,
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.appender.error_sink_opt = Some(ErrorSink {
+            appender: Box::new(RollingFileAppender::unopened(
+                path,
+                RollingConditionBasic::new().daily(),
+                self.appender.max_files,
+            )),
+            predicate: Box::new(predicate),
+        });
+        self
+    }
+
+    /// Sets how many bytes a [`write_batch`](RollingFileAppender::write_batch)
+    /// may leave buffered before it flushes them, so that a large batch is
+    /// written out incrementally. This can only lower the memory used by the
+    /// write buffer, whose capacity stays the same; must be greater than
+    /// zero.
+    pub fn max_buffered_bytes(mut self, x: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.max_buffered_bytes_opt = Some(x);
+        self
+    }
+
+    /// Sets a Unix domain socket to notify of each rollover, e.g. for a log
+    /// shipping agent to pick up the rotated file right away. After each
+    /// rollover, the appender connects to the socket and sends a line like
+    /// `{"rolled":"/var/log/myprogram.1","ts":"2021-03-30T01:02:03+00:00"}`
+    /// with the path of the rotated file and the time of the rollover.
+    /// Failing to notify the socket doesn't fail the rollover, and is
+    /// reported on stderr the first time only.
+    #[cfg(unix)]
+    pub fn notify_socket(mut self, path: PathBuf) -> RollingFileAppenderBuilder<RC> {
+        self.appender.notify_socket_opt = Some(path);
+        self
+    }
+
+    /// Sets whether rollovers take an advisory lock on a `.lock` file next to
+    /// the current file (e.g. `basename.lock`), so that several processes
+    /// appending to the same file rotate it one at a time: a process finding
+    /// that the file it last opened was already rotated by another one, even
+    /// if it closed it since, just reopens the current file instead of
+    /// rotating it again. This only
+    /// protects the rotation; concurrent writes still need to be coordinated
+    /// by the application, e.g. by writing whole lines at once.
+    pub fn lock_file(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.lock_file = x;
+        self
+    }
+
+    /// Sets a file that triggers a rollover when it appears, e.g. created by
+    /// a script with `touch /var/log/myprogram.roll`. It is checked for, and
+    /// deleted, on every write before the rolling condition, which costs a
+    /// file system call per write.
+    pub fn roll_on_trigger_file(mut self, path: PathBuf) -> RollingFileAppenderBuilder<RC> {
+        self.appender.trigger_file_opt = Some(path);
+        self
+    }
+
+    /// Sets a buffer of up to the given number of bytes to hold writes while
+    /// the current file can't be opened or written to, e.g. during a brief
+    /// network file system outage. Such writes then succeed, and the buffered
+    /// data is written to the file, in order, as soon as it can be opened
+    /// again. If the buffer overflows, the oldest data is dropped and counted
+    /// by [`fallback_dropped_bytes`](RollingFileAppender::fallback_dropped_bytes).
+    /// Flushing, closing or dropping the appender tries to write the held
+    /// data too, and a flush fails while it can't. Must be greater than zero.
+    pub fn fallback_buffer(mut self, capacity_bytes: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.fallback_opt = Some(fallback::FallbackBuffer::new(capacity_bytes));
+        self
+    }
+
+    /// Sets writes to be held back while a rollover they trigger fails,
+    /// rather than going to the file that should have been rotated, in a
+    /// buffer of up to the given number of bytes. The rollover is then
+    /// retried before each following write, which is held as well while it
+    /// keeps failing, and the held writes go to the new file, in order, once
+    /// it succeeds. Writes succeed meanwhile. If the buffer overflows, the
+    /// oldest data is dropped and counted by
+    /// [`fallback_dropped_bytes`](RollingFileAppender::fallback_dropped_bytes).
+    /// Flushing or closing the appender retries the rollover too, and fails
+    /// while it keeps failing. Dropping the appender writes the held writes
+    /// to the current file if the rollover still fails, rather than losing
+    /// them. Must be greater than zero.
+    pub fn defer_on_rollover_failure(mut self, capacity_bytes: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.deferred_opt = Some(fallback::FallbackBuffer::new(capacity_bytes));
+        self
+    }
+
+    /// Sets a handler receiving the errors the appender carries on from,
+    /// like a failed rollover during a write, instead of printing them to
+    /// stderr, e.g. to route them to another logging backend. The handler
+    /// is called while the appender is in use, so it mustn't write to the
+    /// same appender.
+    pub fn internal_error_handler<F>(mut self, handler: F) -> RollingFileAppenderBuilder<RC>
+    where
+        F: FnMut(&RollingFileError) + Send + 'static,
+    {
+        self.appender.error_handler_opt = Some(Box::new(handler));
+        self
+    }
+
+    /// Sets a timestamp to be written at the start of every line, formatted
+    /// from the datetime of the write with the given
+    /// [`chrono::format::strftime`] format, e.g. `"%Y-%m-%d %H:%M:%S "`.
+    /// A line continued by a later write is only prefixed once. The size of
+    /// the files includes the timestamps, but the number of bytes returned
+    /// by writes doesn't.
+    pub fn line_prefix_timestamp(mut self, format: String) -> RollingFileAppenderBuilder<RC> {
+        self.appender.line_timestamp_format_opt = Some(format);
+        self
+    }
+
+    /// Sets each write to be prefixed with its length, for binary records
+    /// that aren't delimited otherwise, so that they can be read back with
+    /// [`RollingFileReader::frames`]. The size of the files includes the
+    /// prefixes, but the number of bytes returned by writes doesn't. A write
+    /// too long for the prefix fails without writing anything. An empty write
+    /// is written as an empty record, even if
+    /// [`skip_empty_writes`](RollingFileAppenderBuilder::skip_empty_writes)
+    /// is set.
+    pub fn length_prefixed(mut self, x: LengthPrefix) -> RollingFileAppenderBuilder<RC> {
+        self.appender.length_prefix_opt = Some(x);
+        self
+    }
+
+    /// Sets the clock the appender takes the current time from whenever it
+    /// isn't given the datetime of a write, i.e. for writes through
+    /// [`io::Write`] and e.g. flushes with
+    /// [`roll_on_flush`](RollingFileAppenderBuilder::roll_on_flush) set. By
+    /// default, writes take the system time, and the condition provides the
+    /// time otherwise, see [`RollingCondition::now`]. The caller can keep a
+    /// handle to the clock, e.g. to advance a fake clock in tests.
+    pub fn clock(mut self, x: Arc<dyn Clock + Send + Sync>) -> RollingFileAppenderBuilder<RC> {
+        self.appender.clock_opt = Some(x);
+        self
+    }
+
+    /// Sets the file system the current and rotated files are handled with,
+    /// instead of the disk, e.g. to test rotation without touching the disk,
+    /// or to run where the disk can't be accessed directly. The current and
+    /// rotated files, rollover markers and the trigger file are handled with
+    /// it. Options that need more of the disk than it covers, like
+    /// compression, checksums or the lock file, can't be combined with it,
+    /// see [`validate`](RollingFileAppenderBuilder::validate), and
+    /// [`reader`](RollingFileAppender::reader) still reads from the disk.
+    pub fn file_system(mut self, x: Arc<dyn FileSystem>) -> RollingFileAppenderBuilder<RC> {
+        self.appender.file_system_opt = Some(x);
+        self
+    }
+
+    /// Sets directories to spread the files over, e.g. one per disk to spread
+    /// the I/O: the current file is created in the first directory, and each
+    /// rollover moves on to the next one in turn, under the same file name.
+    /// The directory of the path given to the builder is not used unless it
+    /// is in the list. Rotated files stay in the directory they were written
+    /// in, so each directory keeps its own history of up to `max_files`
+    /// files, and [`reader`](RollingFileAppender::reader) only covers the
+    /// current one.
+    pub fn rotate_dirs(mut self, x: Vec<PathBuf>) -> RollingFileAppenderBuilder<RC> {
+        self.appender.rotate_dirs = x;
+        self
+    }
+
+    /// Sets a channel to send a [`RolloverEvent`] to after each rollover, for
+    /// a receiver to poll. Sending never blocks; if the receiver is dropped,
+    /// events stop being sent.
+    pub fn rollover_events(mut self, sender: Sender<RolloverEvent>) -> RollingFileAppenderBuilder<RC> {
+        self.appender.event_sender_opt = Some(events::EventSender::Unbounded(sender));
+        self
+    }
+
+    /// Like [`rollover_events`](Self::rollover_events), for a bounded
+    /// channel. Events are dropped while the channel is full rather than
+    /// blocking the writer.
+    pub fn rollover_events_bounded(mut self, sender: SyncSender<RolloverEvent>) -> RollingFileAppenderBuilder<RC> {
+        self.appender.event_sender_opt = Some(events::EventSender::Bounded(sender));
+        self
+    }
+
+    /// Sets whether rotated files are made read-only (mode 0444) right after
+    /// they are rotated, so that anything accidentally writing to them again
+    /// fails loudly. The current file stays writable. If a file can't be made
+    /// read-only, that is reported, and the rollover still succeeds.
+    #[cfg(unix)]
+    pub fn read_only_archives(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.read_only_archives = x;
+        self
+    }
+
+    /// Sets a path that is kept hard-linked to the current file, e.g. for
+    /// tailing it under a name that doesn't depend on the naming scheme.
+    /// Whenever a current file is opened, including after each rollover, the
+    /// link is atomically replaced by one to the new file. It must be on the
+    /// same file system as the current file.
+    ///
+    /// Unlike a symbolic link, the hard link refers to the file itself
+    /// rather than to its name, so it stays valid while the file is renamed
+    /// by a rollover. A reader that opened the link before a rollover keeps
+    /// reading the same file, which is then the newest rotated file, rather
+    /// than switching to the new current file; to follow the rollover, it has
+    /// to reopen the link by name, as `tail -F` does. Until the link is
+    /// replaced, e.g. while a rollover is in progress or if replacing it
+    /// failed, it refers to the rotated file. The link is left in place when
+    /// the appender is dropped.
+    #[cfg(unix)]
+    pub fn stable_hardlink(mut self, path: PathBuf) -> RollingFileAppenderBuilder<RC> {
+        self.appender.stable_hardlink_opt = Some(path);
+        self
+    }
+
+    /// Sets a `.sha256` sidecar to be written next to each rotated file
+    /// (e.g. `basename.1.gz.sha256`) during the rollover, holding the
+    /// SHA-256 checksum of either the data before compression or the file as
+    /// stored. The sidecar is written atomically, and rotated along with its
+    /// file. Use [`verify_checksum`] to check a file against it. A sidecar
+    /// that can't be written is reported rather than failing the rollover.
+    #[cfg(feature = "checksum")]
+    pub fn checksum(mut self, x: Checksum) -> RollingFileAppenderBuilder<RC> {
+        self.appender.checksum_opt = Some(x);
+        self
+    }
+
+    /// Sets whether writing an empty buffer is skipped entirely, which is the
+    /// default, unless writes are
+    /// [`length_prefixed`](RollingFileAppenderBuilder::length_prefixed).
+    /// Otherwise, an empty write is handled like any other: the
+    /// rolling condition is evaluated (so it may roll over, and e.g. the time
+    /// of the last write is updated), and it counts towards the flush policy.
+    pub fn skip_empty_writes(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.skip_empty_writes = x;
+        self
+    }
+
+    /// Sets an interval at which the current file is synced to disk, on the
+    /// first write after the interval has elapsed since the last sync, based
+    /// on the datetime of the writes. Unlike a flush, which only hands the
+    /// buffered data to the OS, this waits for the data to reach the disk
+    /// (`File::sync_data`). That way, at most the data written within about
+    /// one interval (plus any writes after the last one) is lost if the
+    /// system crashes. A failed sync doesn't fail the write, but is reported,
+    /// and the next write tries again.
+    pub fn sync_interval(mut self, x: Duration) -> RollingFileAppenderBuilder<RC> {
+        self.appender.sync_interval_opt = Some(x);
+        self
+    }
+
+    /// Sets a maximum number of entries that the directory holding the files
+    /// may already have when the appender is built, which fails otherwise.
+    /// This helps to stay within an inode budget, together with `max_files`.
+    pub fn max_dir_entries(mut self, x: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.max_dir_entries_opt = Some(x);
+        self
+    }
+
+    /// Sets whether the current file is written with `O_DIRECT`, bypassing
+    /// the page cache, e.g. for a high-volume logger that shouldn't evict
+    /// anything else from it. The file system must support it, or opening
+    /// the file fails.
+    ///
+    /// `O_DIRECT` requires every write to start at a file offset, from a
+    /// memory address, and with a length that are all multiples of the
+    /// device's logical block size. Writes are therefore copied to an
+    /// internal staging buffer aligned to 4096 bytes, and written out in
+    /// whole blocks, so callers needn't align their buffers. A partial last
+    /// block is written padded with zeros when flushing, after which the file
+    /// is truncated back to the data, and the block is written again with
+    /// the data that follows it. Each flush of a partial block thus costs a
+    /// write of a whole block, so flush as rarely as possible.
+    #[cfg(target_os = "linux")]
+    pub fn direct_io(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.direct_io = x;
+        self
+    }
+
+    /// Sets whether running out of disk space is an error rather than handled
+    /// on a best-effort basis. In this mode, a full disk (`ENOSPC`) during a
+    /// write or rollover fails the write, and leaves the appender degraded:
+    /// all further writes fail right away, instead of retrying over and over,
+    /// until [`RollingFileAppender::reopen`] is called once space is
+    /// available again. If the disk fills up after the data of a write was
+    /// taken, e.g. while flushing it, that write still succeeds, and only the
+    /// following ones fail.
+    pub fn strict_full_disk(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.strict_full_disk = x;
+        self
+    }
+
+    /// Sets how often `write_batch()` evaluates the rolling condition
+    pub fn batch_rollover(mut self, x: BatchRollover) -> RollingFileAppenderBuilder<RC> {
+        self.appender.batch_rollover = x;
+        self
+    }
+
+    /// Sets whether each new file begins with a header holding a sequence
+    /// number, like `#seq:42`, that increases by one with every file, so that
+    /// consumers can detect missing files. The last number is kept in a
+    /// sidecar (`basename.seq`), so that it survives restarts.
+    pub fn sequence_header(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.sequence_header = x;
+        self
+    }
+
+    /// Sets bytes each new file begins with, e.g. a UTF-8 byte order mark
+    /// (`b"\xEF\xBB\xBF"`) for tools expecting one. Only files that are
+    /// empty when opened get it, so reopening an existing file on restart
+    /// doesn't write it again. It comes before the
+    /// [`sequence_header`](RollingFileAppenderBuilder::sequence_header), and
+    /// counts towards the file size.
+    pub fn preamble<B: Into<Vec<u8>>>(mut self, x: B) -> RollingFileAppenderBuilder<RC> {
+        self.appender.preamble_opt = Some(x.into());
+        self
+    }
+
+    /// Sets whether the appender only rotates the current file, which is
+    /// written by another process, e.g. a snapshot dropped at the path,
+    /// instead of writing it. The appender then never opens or creates the
+    /// current file, writes fail with `Unsupported`, and a
+    /// [`rollover`](RollingFileAppender::rollover) rotates whatever is at the
+    /// path into the rotated files, doing nothing if there is nothing.
+    pub fn rotate_only(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.rotate_only = x;
+        self
+    }
+
+    /// Sets the size of the chunks a larger write is split into, each of
+    /// which is written as if on its own, evaluating the rolling condition
+    /// before it, so that a huge write doesn't take the file far past a
+    /// `max_size`. The write still returns its full length, or fails if any
+    /// chunk does, even if earlier chunks were written. Chunks are split
+    /// at arbitrary bytes, not at line or character boundaries; must be
+    /// greater than zero, and can't be combined with `length_prefixed`, which
+    /// needs each write to stay one record, or `line_prefix_timestamp`, which
+    /// would take the start of a chunk for the start of a line.
+    pub fn max_write_size(mut self, x: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.max_write_size_opt = Some(x);
+        self
+    }
+
+    /// Sets an encoder transforming the data on its way into the current
+    /// file, e.g. to encrypt it, after any line timestamps or length
+    /// prefixes were added. The encoder is finalized whenever the current
+    /// file is rotated or closed, see [`Encoder::finalize`]. Sizes, e.g. for
+    /// `max_size`, are those of the encoded data in the file. The
+    /// [`GzipEncoder`] compresses the current file like
+    /// [`stream_compression`](RollingFileAppenderBuilder::stream_compression),
+    /// but without giving rotated files a `.gz` extension.
+    pub fn encoder<E: Encoder + 'static>(mut self, x: E) -> RollingFileAppenderBuilder<RC> {
+        self.appender.encoder_opt = Some(Box::new(x));
+        self
+    }
+
+    /// Sets whether `flush()` also evaluates the rolling condition at the
+    /// condition's current time (see [`RollingCondition::now`]), and rolls
+    /// over if it says so, so that an application flushing on a timer gets
+    /// its files rolled over on time during idle periods, without a thread
+    /// of its own. This couples the two: a flush may then take as long as a
+    /// rollover, and fails if rolling over fails in a way a write would fail
+    /// for. It also counts as a write for conditions tracking the time of
+    /// the last write. The file is only rolled over if anything was written
+    /// to it, so idle periods don't produce empty files, and flushes the
+    /// appender does by itself, e.g. per the
+    /// [`flush_policy`](RollingFileAppenderBuilder::flush_policy), never roll
+    /// over.
+    pub fn roll_on_flush(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.roll_on_flush = x;
+        self
+    }
+
+    /// Sets what happens when a rollover renames a file to a name that is
+    /// already taken, e.g. by a file another process left there, or because
+    /// renaming the file in the way failed earlier in the same rollover. By
+    /// default, the existing file is replaced, losing it.
+    ///
+    /// Whether a plain rename replaces the destination differs between
+    /// platforms: on Unix, it always does, atomically and silently. On
+    /// Windows, it does as long as no other process has the destination
+    /// open, and fails otherwise, e.g. while a tailer reads it. A policy other
+    /// than [`CollisionPolicy::Overwrite`] checks for the destination before
+    /// renaming, which is not atomic, so a file appearing in between is
+    /// still replaced.
+    ///
+    /// Independently of the policy, on Windows a rename during a rollover
+    /// that fails with `PermissionDenied` or a sharing violation, as while
+    /// another process, e.g. a log viewer, has the file open, is retried up
+    /// to three times, waiting 10 ms before the first retry and twice as long
+    /// before each further one, before the rollover fails. Otherwise, a
+    /// rollover failing halfway through leaves the files it already renamed
+    /// where they are, and the next rollover carries on from there.
+    ///
+    /// With `NumberingScheme::ShiftUp`, a file already at `basename.1` is an
+    /// ordinary rotated file, and is shifted to `basename.2` before the
+    /// current file takes its place, so it only collides if shifting it
+    /// failed.
+    pub fn collision_policy(mut self, x: CollisionPolicy) -> RollingFileAppenderBuilder<RC> {
+        self.appender.collision_policy = x;
+        self
+    }
+
+    /// Sets whether the current file is written through a gzip encoder, so
+    /// that rotated files are compressed already, and merely renamed to end
+    /// in `.gz` on rollover instead of being compressed in a separate pass.
+    /// This requires [`Compression::Gzip`].
+    ///
+    /// Be aware of the following:
+    ///
+    /// - The current file is only readable with gzip tools, and keeps its name without `.gz`. Until it is
+    ///   closed, it lacks the end of the gzip stream, so reading it, also with
+    ///   [`reader`](RollingFileAppender::reader), ends in an `UnexpectedEof` error after the data flushed.
+    /// - The file size that size-based rolling goes by is the compressed size, which lags behind the data
+    ///   written, as the encoder holds data until it has enough to compress. A size limit thus applies to
+    ///   compressed bytes.
+    /// - Each flush compresses the data held so far, at some cost to the compression ratio, so flush
+    ///   sparingly.
+    /// - Data not flushed yet is lost on a crash, and a file not closed cleanly keeps lacking the end of the
+    ///   gzip stream.
+    /// - Reopening an existing file, e.g. after a restart, appends another gzip member to it, which gzip
+    ///   tools decompress as if it were one.
+    pub fn stream_compression(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.stream_compression = x;
+        self
+    }
+
+    /// Sets how often, in writes, the tracked size of the current file is
+    /// compared against its size on disk. If another process truncated the
+    /// file, e.g. `logrotate` with `copytruncate`, the tracked size is resynced
+    /// to the smaller size on disk, so that rolling over by size keeps working,
+    /// and the truncation is reported as a
+    /// [`RollingFileError::Truncated`]. Each check costs a `stat` of the file,
+    /// which is why this is off by default.
+    pub fn size_check_interval(mut self, writes: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.size_check_interval_opt = Some(writes);
+        self
+    }
+
+    /// Sets whether the bytes the appender adds to the data written count
+    /// towards the size that the rolling condition, and `max_size_fraction`,
+    /// compare against their size limits, which they do by default. These
+    /// are the [`preamble`](RollingFileAppenderBuilder::preamble), the
+    /// [`sequence_header`](RollingFileAppenderBuilder::sequence_header), and
+    /// the prefixes of
+    /// [`line_prefix_timestamp`](RollingFileAppenderBuilder::line_prefix_timestamp)
+    /// and [`length_prefixed`](RollingFileAppenderBuilder::length_prefixed).
+    /// Without them, a size limit is a budget for the payload alone, and
+    /// files end up larger than it by the added bytes. What an existing file
+    /// holds when it is opened counts as payload either way, as does data
+    /// held while the file was unavailable.
+    /// [`current_file_size`](RollingFileAppender::current_file_size) always
+    /// includes everything.
+    pub fn size_counts_framing(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.size_counts_framing = x;
+        self
+    }
+
+    /// Sets a suffix for an empty marker file created next to each rotated
+    /// file once it is complete, i.e. flushed, compressed and so on, e.g.
+    /// `app.log.1.done` for the suffix `.done`, for tools that ship rotated
+    /// files when their marker appears. The marker is named after the
+    /// uncompressed name of the file whether or not it is compressed, and is
+    /// renamed and deleted along with it. Failing to create the marker is
+    /// reported, see [`RollingFileError::FinishRotated`], and doesn't fail the
+    /// rollover.
+    pub fn rollover_marker_suffix(mut self, x: String) -> RollingFileAppenderBuilder<RC> {
+        self.appender.marker_suffix_opt = Some(x);
+        self
+    }
+
+    /// Sets a maximum length for the names of rotated files, in bytes, e.g.
+    /// 255 for the usual `NAME_MAX`, so that a long current file name doesn't
+    /// make rollovers fail once an index, date or extension is added to it.
+    /// If the current file's name doesn't leave room for the longest of these
+    /// within the limit, rotated files are named after a shortened form of
+    /// it instead: as much of its beginning as fits, followed by `~`, a hash
+    /// of the whole name, and its extension, e.g.
+    /// `averylongname~1a2b3c4d.log.1`. The shortened form only depends on
+    /// the name and the limit, so it stays the same across rollovers and
+    /// restarts. The current file keeps its name.
+    pub fn max_filename_len(mut self, x: usize) -> RollingFileAppenderBuilder<RC> {
+        self.appender.max_filename_len_opt = Some(x);
+        self
+    }
+
+    /// Sets whether the appender discards writes instead of failing to build
+    /// if the current file can't be opened for a reason that won't go away
+    /// by itself, e.g. a read-only file system or a missing directory, so
+    /// that a program in a sandbox can run without its log. The failure is
+    /// reported as a [`RollingFileError::Unwritable`], and while
+    /// [`is_null`](RollingFileAppender::is_null), writes succeed without
+    /// doing anything, counted in
+    /// [`null_dropped_bytes`](RollingFileAppender::null_dropped_bytes) rather
+    /// than the metrics, and rollovers do nothing.
+    /// [`reopen`](RollingFileAppender::reopen) recovers once the path is
+    /// writable. Other failures, e.g. running out of disk space, still fail
+    /// the build.
+    pub fn allow_null_fallback(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.allow_null_fallback = x;
+        self
+    }
+
+    /// Sets whether a rollover for a new period also creates an empty rotated
+    /// file for each period in between that had no writes, according to
+    /// [`RollingCondition::skipped_periods`], so that there is exactly one
+    /// file per period even across idle gaps. Without it, e.g. a daily
+    /// appender writing again after two idle days rolls over just once. This
+    /// only applies to rollovers before a write; at most `max_files` empty
+    /// files are created, as older ones would be deleted right away.
+    pub fn fill_gaps(mut self, x: bool) -> RollingFileAppenderBuilder<RC> {
+        self.appender.fill_gaps = x;
+        self
+    }
+
+    /// Checks that the options don't contradict each other, returning an
+    /// `InvalidInput` error that describes the conflict otherwise. This is
+    /// also done by `build()`. The rules are:
+    ///
+    /// - `compress_min_size` requires `compression` to be set.
+    /// - `compress_retry` requires `compression` to be set, and at least one attempt.
+    /// - `keep_recent_uncompressed` requires `compression` to be set.
+    /// - `stream_compression` requires `Compression::Gzip`, and can't be combined with `compress_min_size`,
+    ///   `compress_retry`, `keep_recent_uncompressed`, `checksum` or `direct_io`.
+    /// - `NumberingScheme::DateGrouped` doesn't support `keep_recent_uncompressed` or `checksum`.
+    /// - The condition's `max_size` must be greater than zero.
+    /// - `max_buffered_bytes` must be greater than zero.
+    /// - `max_write_size` must be greater than zero, and can't be combined with `length_prefixed` or
+    ///   `line_prefix_timestamp`.
+    /// - `rotate_dirs` requires the path to end with a file name.
+    /// - `line_prefix_timestamp` must be a valid format.
+    /// - `preamble` must not be empty.
+    /// - `encoder` can't be combined with `preamble` or `sequence_header`.
+    /// - `length_prefixed` can't be combined with `line_prefix_timestamp`, `sequence_header` or `preamble`.
+    /// - `fallback_buffer` must be greater than zero.
+    /// - `defer_on_rollover_failure` must be greater than zero.
+    /// - `max_size_fraction` must be greater than zero and at most one.
+    /// - `file_system` can't be combined with `compression`, `min_free_bytes`, `max_size_fraction`,
+    ///   `max_dir_entries`, `lock_file`, `sequence_header`, `read_only_archives`, `stable_hardlink`,
+    ///   `checksum` or `direct_io`.
+    /// - `FlushPolicy::EveryN` requires N to be at least 1.
+    /// - `FlushPolicy::Interval` requires a positive interval.
+    /// - `sync_interval` requires a positive interval.
+    /// - `max_filename_len` must be at least 64.
+    /// - `rollover_marker_suffix` must not be empty.
+    /// - `size_check_interval` must be greater than zero.
+    pub fn validate(&self) -> io::Result<()> {
+        let rfa = &self.appender;
+        if rfa.compress_min_size > 0 && rfa.compression_opt.is_none() {
+            return Err(invalid_config("compress_min_size requires compression to be set"));
+        }
+        if let Some((attempts, _)) = rfa.compress_retry_opt {
+            if rfa.compression_opt.is_none() {
+                return Err(invalid_config("compress_retry requires compression to be set"));
+            }
+            if attempts == 0 {
+                return Err(invalid_config("compress_retry requires at least one attempt"));
+            }
+        }
+        if rfa.keep_recent_uncompressed > 0 && rfa.compression_opt.is_none() {
+            return Err(invalid_config(
+                "keep_recent_uncompressed requires compression to be set",
+            ));
+        }
+        if rfa.stream_compression {
+            if rfa.compression_opt != Some(Compression::Gzip) {
+                return Err(invalid_config("stream_compression requires Compression::Gzip"));
+            }
+            let mut unsupported = Vec::new();
+            unsupported.extend((rfa.compress_min_size > 0).then_some("compress_min_size"));
+            unsupported.extend(rfa.compress_retry_opt.map(|_| "compress_retry"));
+            unsupported.extend((rfa.keep_recent_uncompressed > 0).then_some("keep_recent_uncompressed"));
+            #[cfg(feature = "checksum")]
+            unsupported.extend(rfa.checksum_opt.map(|_| "checksum"));
+            #[cfg(target_os = "linux")]
+            unsupported.extend(rfa.direct_io.then_some("direct_io"));
+            if let Some(option) = unsupported.first() {
+                return Err(invalid_config(&format!(
+                    "stream_compression can't be combined with {}",
+                    option
+                )));
+            }
+        }
+        if rfa.numbering == NumberingScheme::DateGrouped && rfa.keep_recent_uncompressed > 0 {
+            return Err(invalid_config(
+                "NumberingScheme::DateGrouped doesn't support keep_recent_uncompressed",
+            ));
+        }
+        #[cfg(feature = "checksum")]
+        if rfa.numbering == NumberingScheme::DateGrouped && rfa.checksum_opt.is_some() {
+            return Err(invalid_config("NumberingScheme::DateGrouped doesn't support checksum"));
+        }
+        if rfa.condition.max_size() == Some(0) {
+            return Err(invalid_config("max_size must be greater than zero"));
+        }
+        if rfa.max_buffered_bytes_opt == Some(0) {
+            return Err(invalid_config("max_buffered_bytes must be greater than zero"));
+        }
+        if rfa.max_write_size_opt == Some(0) {
+            return Err(invalid_config("max_write_size must be greater than zero"));
+        }
+        if rfa.max_write_size_opt.is_some() && rfa.length_prefix_opt.is_some() {
+            return Err(invalid_config("max_write_size can't be combined with length_prefixed"));
+        }
+        if rfa.max_write_size_opt.is_some() && rfa.line_timestamp_format_opt.is_some() {
+            return Err(invalid_config(
+                "max_write_size can't be combined with line_prefix_timestamp",
+            ));
+        }
+        if !rfa.rotate_dirs.is_empty() && Path::new(&rfa.base_filename).file_name().is_none() {
+            return Err(invalid_config("rotate_dirs requires the path to end with a file name"));
+        }
+        if let Some(format) = rfa.line_timestamp_format_opt.as_ref() {
+            if chrono::format::StrftimeItems::new(format).any(|x| x == chrono::format::Item::Error) {
+                return Err(invalid_config("line_prefix_timestamp is not a valid format"));
+            }
+        }
+        if rfa.preamble_opt.as_ref().is_some_and(Vec::is_empty) {
+            return Err(invalid_config("preamble must not be empty"));
+        }
+        if rfa.encoder_opt.is_some() && (rfa.preamble_opt.is_some() || rfa.sequence_header) {
+            return Err(invalid_config(
+                "encoder can't be combined with preamble or sequence_header",
+            ));
+        }
+        if rfa.length_prefix_opt.is_some()
+            && (rfa.line_timestamp_format_opt.is_some() || rfa.sequence_header || rfa.preamble_opt.is_some())
+        {
+            return Err(invalid_config(
+                "length_prefixed can't be combined with line_prefix_timestamp, sequence_header or preamble",
+            ));
+        }
+        if rfa.fallback_opt.as_ref().map(fallback::FallbackBuffer::capacity) == Some(0) {
+            return Err(invalid_config("fallback_buffer must be greater than zero"));
+        }
+        if let Some(fraction) = rfa.max_size_fraction_opt {
+            // also rejects NaN
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                return Err(invalid_config(
+                    "max_size_fraction must be greater than zero and at most one",
+                ));
+            }
+        }
+        if rfa.file_system_opt.is_some() {
+            let mut unsupported = Vec::new();
+            unsupported.extend(rfa.compression_opt.map(|_| "compression"));
+            unsupported.extend(rfa.min_free_bytes_opt.map(|_| "min_free_bytes"));
+            unsupported.extend(rfa.max_size_fraction_opt.map(|_| "max_size_fraction"));
+            unsupported.extend(rfa.max_dir_entries_opt.map(|_| "max_dir_entries"));
+            unsupported.extend(rfa.lock_file.then_some("lock_file"));
+            unsupported.extend(rfa.sequence_header.then_some("sequence_header"));
+            #[cfg(unix)]
+            unsupported.extend(rfa.read_only_archives.then_some("read_only_archives"));
+            #[cfg(unix)]
+            unsupported.extend(rfa.stable_hardlink_opt.as_ref().map(|_| "stable_hardlink"));
+            #[cfg(feature = "checksum")]
+            unsupported.extend(rfa.checksum_opt.map(|_| "checksum"));
+            #[cfg(target_os = "linux")]
+            unsupported.extend(rfa.direct_io.then_some("direct_io"));
+            if let Some(option) = unsupported.first() {
+                return Err(invalid_config(&format!(
+                    "file_system can't be combined with {}",
+                    option
+                )));
+            }
+        }
+        if rfa.deferred_opt.as_ref().map(fallback::FallbackBuffer::capacity) == Some(0) {
+            return Err(invalid_config("defer_on_rollover_failure must be greater than zero"));
+        }
+        match rfa.flush_policy {
+            FlushPolicy::EveryN(0) => {
+                return Err(invalid_config(
+                    "FlushPolicy::EveryN requires N to be at least 1 (use FlushPolicy::OnEveryWrite instead)",
+                ))
+            },
+            FlushPolicy::Interval(interval) if interval <= Duration::zero() => {
+                return Err(invalid_config("FlushPolicy::Interval requires a positive interval"))
+            },
+            _ => {},
+        }
+        if rfa.sync_interval_opt.is_some_and(|x| x <= Duration::zero()) {
+            return Err(invalid_config("sync_interval requires a positive interval"));
+        }
+        if rfa.marker_suffix_opt.as_ref().is_some_and(String::is_empty) {
+            return Err(invalid_config("rollover_marker_suffix must not be empty"));
+        }
+        if rfa.max_filename_len_opt.is_some_and(|x| x < MIN_MAX_FILENAME_LEN) {
+            return Err(invalid_config("max_filename_len must be at least 64"));
+        }
+        if rfa.size_check_interval_opt == Some(0) {
+            return Err(invalid_config("size_check_interval must be greater than zero"));
+        }
+        Ok(())
+    }
+
+    /// Builds the appender, opening the current file.
+    pub fn build(self) -> io::Result<RollingFileAppender<RC>> {
+        self.validate()?;
+        let mut rfa = self.appender;
+        if !rfa.rotate_dirs.is_empty() {
+            rfa.enter_rotate_dir();
+        }
+        if let Some(max_dir_entries) = rfa.max_dir_entries_opt {
+            let entries = fs::read_dir(rfa.directory())?.count();
+            if entries > max_dir_entries {
+                return Err(io::Error::other(format!(
+                    "directory {} has {} entries, more than the maximum of {}",
+                    rfa.directory().display(),
+                    entries,
+                    max_dir_entries
+                )));
+            }
+        }
+        if let Some(fraction) = rfa.max_size_fraction_opt {
+            let total = disk::total_space(rfa.directory())?;
+            rfa.max_size_opt = Some((total as f64 * fraction) as u64);
+        }
+        // Fail if we can't open the file initially...
+        if let Err(e) = rfa.open_writer_if_needed() {
+            if !rfa.allow_null_fallback || !is_permanent(&e) {
+                return Err(e);
+            }
+            rfa.null = true;
+            rfa.report(RollingFileError::Unwritable {
+                path: PathBuf::from(rfa.filename_for(0)),
+                source: e,
+            });
+        }
+        if let Some(sink) = rfa.error_sink_opt.as_mut() {
+            sink.appender.open_writer_if_needed()?;
+        }
+        if rfa.seed_last_write && rfa.current_filesize > 0 {
+            let modified = rfa.file_system().metadata(rfa.filename_for(0).as_ref())?.modified;
+            rfa.condition.seed_last_write(&DateTime::<Local>::from(modified));
+        }
+        Ok(rfa)
+    }
+}
+
+/// A rolling file appender with a rolling condition based on date/time or size.
+pub type BasicRollingFileAppender = RollingFileAppender<RollingConditionBasic>;
+
+impl BasicRollingFileAppender {
+    /// Creates an appender that rolls over daily at local midnight, keeping
+    /// at most `max_files` rotated files compressed with gzip, and flushing at
+    /// most a second after a write. This is a shortcut for the equivalent
+    /// [`builder`](RollingFileAppender::builder) calls, which allow adjusting
+    /// any of these.
+    pub fn daily_compressed<P>(path: P, max_files: usize) -> io::Result<BasicRollingFileAppender>
+    where
+        P: AsRef<Path>,
+    {
+        BasicRollingFileAppender::compressed(path, RollingConditionBasic::new().daily(), max_files)
+    }
+
+    /// Like [`daily_compressed`](BasicRollingFileAppender::daily_compressed),
+    /// rolling over at midnight UTC instead.
+    pub fn daily_utc_compressed<P>(path: P, max_files: usize) -> io::Result<BasicRollingFileAppender>
+    where
+        P: AsRef<Path>,
+    {
+        BasicRollingFileAppender::compressed(path, RollingConditionBasic::new().daily_utc(), max_files)
+    }
+
+    fn compressed<P>(
+        path: P,
+        condition: RollingConditionBasic,
+        max_files: usize,
+    ) -> io::Result<BasicRollingFileAppender>
+    where
+        P: AsRef<Path>,
+    {
+        RollingFileAppender::builder(path, condition, max_files)
+            .compression(Compression::Gzip)
+            .flush_policy(FlushPolicy::Interval(Duration::seconds(1)))
+            .build()
+    }
+}
+
+/// The room kept for what the names of rotated files add to the current
+/// file's name with `max_filename_len`: an index, or a date and an index,
+/// followed by a compression extension and a checksum extension
+const ROTATED_SUFFIX_RESERVE: usize = 40;
+
+/// The smallest `max_filename_len`, which leaves room for a shortened name
+const MIN_MAX_FILENAME_LEN: usize = 64;
+
+/// How often a rename during a rotation is retried if the file is in use
+const RENAME_RETRIES: usize = 3;
+
+/// The delay before the first retry of a rename, doubled for each further one
+const RENAME_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Returns whether an error may be caused by another process having the file
+/// open, which is what `PermissionDenied` means for a rename on Windows,
+/// besides the sharing and lock violations reported as raw OS errors.
+/// Elsewhere, open files can be renamed, and a denied rename is permanent.
+fn is_sharing_violation(e: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+    cfg!(windows) && (e.kind() == io::ErrorKind::PermissionDenied || matches!(e.raw_os_error(), Some(32) | Some(33)))
+}
+
+/// Determines the next rotated file of the given date, after the existing
+/// ones of that date among the given rotated files.
+fn next_of_date(archives: &[Archive], date: NaiveDate) -> Archive {
+    let k = archives
+        .iter()
+        .filter_map(|a| match a {
+            Archive::Dated(d, k) if *d == date => Some(k + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    Archive::Dated(date, k)
+}
+
+/// Shortens a file name to at most `max_len` bytes, keeping as much of its
+/// beginning as fits, followed by `~`, the CRC32 of the whole name in hex,
+/// and the extension, if that fits as well.
+fn shorten_file_name(name: &OsStr, max_len: usize) -> OsString {
+    let mut crc = flate2::Crc::new();
+    crc.update(name.as_encoded_bytes());
+    let hash = format!("~{:08x}", crc.sum());
+    let extension = match Path::new(name).extension() {
+        Some(x) if x.len() + 1 + hash.len() < max_len => format!(".{}", x.to_string_lossy()),
+        _ => String::new(),
+    };
+    let lossy = name.to_string_lossy();
+    let mut end = max_len.saturating_sub(hash.len() + extension.len()).min(lossy.len());
+    while !lossy.is_char_boundary(end) {
+        end -= 1;
+    }
+    OsString::from(format!("{}{}{}", &lossy[..end], hash, extension))
+}
+
+/// Determines whether an error opening a file is bound to repeat until
+/// someone changes the file system.
+fn is_permanent(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::PermissionDenied
+            | io::ErrorKind::NotFound
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::NotADirectory
+            | io::ErrorKind::IsADirectory
+    )
+}
+
+fn invalid_config(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid rolling file appender configuration: {}", message),
+    )
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use chrono::LocalResult;
+    use std::io::Read;
+
+    struct Context {
+        _tempdir: tempfile::TempDir,
+        rolling: BasicRollingFileAppender,
+    }
+
+    impl Context {
+        fn verify_contains(&mut self, needle: &str, n: usize) {
+            self.rolling.flush().unwrap();
+            let p = self.rolling.filename_for(n);
+            let haystack = fs::read_to_string(&p).unwrap();
+            if !haystack.contains(needle) {
+                panic!("file {:?} did not contain expected contents {}", p, needle);
+            }
+        }
+    }
+
+    fn build_context(condition: RollingConditionBasic, max_files: usize) -> Context {
+        let tempdir = tempfile::tempdir().unwrap();
+        let rolling = BasicRollingFileAppender::new(tempdir.path().join("test.log"), condition, max_files).unwrap();
+        Context {
+            _tempdir: tempdir,
+            rolling,
+        }
+    }
+
+    #[test]
+    fn frequency_every_day() {
+        let mut c = build_context(RollingConditionBasic::new().daily(), 9);
+        c.rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 3\n", &Local.with_ymd_and_hms(2021, 3, 31, 1, 4, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 4\n", &Local.with_ymd_and_hms(2021, 5, 31, 1, 4, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 5\n", &Local.with_ymd_and_hms(2022, 5, 31, 1, 4, 0).unwrap())
+            .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(4)).exists());
+        c.verify_contains("Line 1", 3);
+        c.verify_contains("Line 2", 3);
+        c.verify_contains("Line 3", 2);
+        c.verify_contains("Line 4", 1);
+        c.verify_contains("Line 5", 0);
+    }
+
+    #[test]
+    fn frequency_every_day_limited_files() {
+        let mut c = build_context(RollingConditionBasic::new().daily(), 2);
+        c.rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 3\n", &Local.with_ymd_and_hms(2021, 3, 31, 1, 4, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 4\n", &Local.with_ymd_and_hms(2021, 5, 31, 1, 4, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 5\n", &Local.with_ymd_and_hms(2022, 5, 31, 1, 4, 0).unwrap())
+            .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(4)).exists());
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists());
+        c.verify_contains("Line 3", 2);
+        c.verify_contains("Line 4", 1);
+        c.verify_contains("Line 5", 0);
+    }
+
+    #[test]
+    fn frequency_every_hour() {
+        let mut c = build_context(RollingConditionBasic::new().hourly(), 9);
+        c.rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 2).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 3\n", &Local.with_ymd_and_hms(2021, 3, 30, 2, 1, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 4\n", &Local.with_ymd_and_hms(2021, 3, 31, 2, 1, 0).unwrap())
+            .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists());
+        c.verify_contains("Line 1", 2);
+        c.verify_contains("Line 2", 2);
+        c.verify_contains("Line 3", 1);
+        c.verify_contains("Line 4", 0);
+    }
+
+    #[test]
+    fn frequency_every_minute() {
+        let mut c = build_context(RollingConditionBasic::new().frequency(RollingFrequency::EveryMinute), 9);
+        c.rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 3\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 4).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 4\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 5\n", &Local.with_ymd_and_hms(2021, 3, 30, 2, 3, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 6\n", &Local.with_ymd_and_hms(2022, 3, 30, 2, 3, 0).unwrap())
+            .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(4)).exists());
+        c.verify_contains("Line 1", 3);
+        c.verify_contains("Line 2", 3);
+        c.verify_contains("Line 3", 3);
+        c.verify_contains("Line 4", 2);
+        c.verify_contains("Line 5", 1);
+        c.verify_contains("Line 6", 0);
+    }
+
+    #[test]
+    fn max_size() {
+        let mut c = build_context(RollingConditionBasic::new().max_size(10), 9);
+        c.rolling
+            .write_with_datetime(b"12345", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"6789", &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"0", &Local.with_ymd_and_hms(2021, 3, 30, 2, 3, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(
+                b"abcdefghijklmn",
+                &Local.with_ymd_and_hms(2021, 3, 31, 2, 3, 3).unwrap(),
+            )
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"ZZZ", &Local.with_ymd_and_hms(2022, 3, 31, 1, 2, 3).unwrap())
+            .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists());
+        c.verify_contains("1234567890", 2);
+        c.verify_contains("abcdefghijklmn", 1);
+        c.verify_contains("ZZZ", 0);
+    }
+
+    #[test]
+    fn max_size_existing() {
+        let mut c = build_context(RollingConditionBasic::new().max_size(10), 9);
+        c.rolling
+            .write_with_datetime(b"12345", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        // close the file and make sure that it can re-open it, and that it
+        // resets the file size properly.
+        c.rolling.writer_opt.take();
+        c.rolling.current_filesize = 0;
+        c.rolling
+            .write_with_datetime(b"6789", &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"0", &Local.with_ymd_and_hms(2021, 3, 30, 2, 3, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(
+                b"abcdefghijklmn",
+                &Local.with_ymd_and_hms(2021, 3, 31, 2, 3, 3).unwrap(),
+            )
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"ZZZ", &Local.with_ymd_and_hms(2022, 3, 31, 1, 2, 3).unwrap())
+            .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists());
+        c.verify_contains("1234567890", 2);
+        c.verify_contains("abcdefghijklmn", 1);
+        c.verify_contains("ZZZ", 0);
+    }
+
+    #[test]
+    fn daily_and_max_size() {
+        let mut c = build_context(RollingConditionBasic::new().daily().max_size(10), 9);
+        c.rolling
+            .write_with_datetime(b"12345", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"6789", &Local.with_ymd_and_hms(2021, 3, 30, 2, 3, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"0", &Local.with_ymd_and_hms(2021, 3, 31, 2, 3, 3).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(
+                b"abcdefghijklmn",
+                &Local.with_ymd_and_hms(2021, 3, 31, 3, 3, 3).unwrap(),
+            )
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"ZZZ", &Local.with_ymd_and_hms(2021, 3, 31, 4, 4, 4).unwrap())
+            .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists());
+        c.verify_contains("123456789", 2);
+        c.verify_contains("0abcdefghijklmn", 1);
+        c.verify_contains("ZZZ", 0);
+    }
+
+    #[test]
+    fn from_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut file = OpenOptions::new().append(true).create(true).open(&path).unwrap();
+        file.write_all(b"12345").unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::from_file(file, &path, RollingConditionBasic::new().max_size(10), 9).unwrap();
+        assert_eq!(rolling.current_file_size(), 5);
+        rolling
+            .write_with_datetime(b"67890", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rolling
+            .write_with_datetime(b"abc", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "1234567890");
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "abc");
+    }
+
+    fn build_flush_context(flush_policy: FlushPolicy) -> Context {
+        let tempdir = tempfile::tempdir().unwrap();
+        let rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(100),
+            9,
+        )
+        .flush_policy(flush_policy)
+        .build()
+        .unwrap();
+        Context {
+            _tempdir: tempdir,
+            rolling,
+        }
+    }
+
+    fn on_disk(rolling: &BasicRollingFileAppender, n: usize) -> String {
+        fs::read_to_string(rolling.filename_for(n)).unwrap()
+    }
+
+    #[test]
+    fn flush_on_every_write() {
+        let mut c = build_flush_context(FlushPolicy::OnEveryWrite);
+        c.rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "Line 1\n");
+        c.rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "Line 1\nLine 2\n");
+    }
+
+    #[test]
+    fn flush_every_n() {
+        let mut c = build_flush_context(FlushPolicy::EveryN(2));
+        c.rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "");
+        c.rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "Line 1\nLine 2\n");
+        c.rolling
+            .write_with_datetime(b"Line 3\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "Line 1\nLine 2\n");
+    }
+
+    #[test]
+    fn flush_interval() {
+        let mut c = build_flush_context(FlushPolicy::Interval(Duration::minutes(1)));
+        c.rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 0).unwrap())
+            .unwrap();
+        c.rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 30).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "");
+        c.rolling
+            .write_with_datetime(b"Line 3\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 0).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "Line 1\nLine 2\nLine 3\n");
+        c.rolling
+            .write_with_datetime(b"Line 4\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 59).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "Line 1\nLine 2\nLine 3\n");
+    }
+
+    #[test]
+    fn flush_only_on_rollover() {
+        let mut c = build_flush_context(FlushPolicy::OnlyOnRollover);
+        c.rolling
+            .write_with_datetime(&[b'x'; 100], &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "");
+        c.rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        assert_eq!(on_disk(&c.rolling, 1).len(), 100);
+        assert_eq!(on_disk(&c.rolling, 0), "");
+        c.rolling.flush().unwrap();
+        assert_eq!(on_disk(&c.rolling, 0), "Line 2\n");
+    }
+
+    #[test]
+    fn max_size_fraction() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let total = match disk::total_space(tempdir.path()) {
+            Ok(total) => total,
+            // not supported on this platform
+            Err(_) => return,
+        };
+        let build = |fraction| {
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .max_size_fraction(fraction)
+                .build()
+        };
+        let tenth = build(0.1).unwrap().config().max_size.unwrap();
+        let whole = build(1.0).unwrap().config().max_size.unwrap();
+        assert!(tenth > 0);
+        assert_eq!(whole, total);
+        assert!(tenth.abs_diff(total / 10) <= 1);
+        for fraction in [0.0, 1.5, -0.1, f64::NAN] {
+            assert_eq!(build(fraction).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+        // rolls over once the file reaches the size
+        let mut rolling = build(0.1).unwrap();
+        rolling.max_size_opt = Some(7);
+        for line in &["Line 1\n", "Line 2\n"] {
+            rolling.write_all(line.as_bytes()).unwrap();
+        }
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\n");
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "Line 2\n");
+    }
+
+    #[test]
+    fn min_free_bytes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        fs::write(tempdir.path().join("test.log.1"), "old 1").unwrap();
+        fs::write(tempdir.path().join("test.log.2"), "old 2").unwrap();
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .min_free_bytes(u64::MAX)
+            .build()
+            .unwrap();
+        rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        for n in 1..=3 {
+            assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(n)).exists());
+        }
+        // only the first write while low on space causes a rollover
+        rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rolling.flush().unwrap();
+        assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(1)).exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 1\nLine 2\n");
+    }
+
+    #[test]
+    fn compress_min_size() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(10),
+            9,
+        )
+        .compression(Compression::Gzip)
+        .compress_min_size(50)
+        .build()
+        .unwrap();
+        rolling
+            .write_with_datetime(&[b'x'; 60], &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rolling
+            .write_with_datetime(b"0123456789", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rolling
+            .write_with_datetime(b"ZZZ", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&rolling.compressed_filename_for(1)).exists());
+        assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(2)).exists());
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "0123456789");
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(File::open(rolling.compressed_filename_for(2)).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, "x".repeat(60));
+    }
+
+    #[test]
+    fn validate_conflicts() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let builder =
+            || BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9);
+        let conflicts = vec![
+            (builder().compress_min_size(100), "compress_min_size"),
+            (
+                builder().compress_retry(3, std::time::Duration::ZERO),
+                "compress_retry requires compression",
+            ),
+            (
+                builder()
+                    .compression(Compression::Gzip)
+                    .compress_retry(0, std::time::Duration::ZERO),
+                "at least one attempt",
+            ),
+            (builder().flush_policy(FlushPolicy::EveryN(0)), "EveryN"),
+            (builder().size_check_interval(0), "size_check_interval"),
+            (builder().max_filename_len(10), "max_filename_len"),
+            (builder().max_write_size(0), "max_write_size"),
+            (
+                builder().max_write_size(10).line_prefix_timestamp("%H ".to_string()),
+                "max_write_size can't be combined with line_prefix_timestamp",
+            ),
+            (builder().encoder(GzipEncoder::new()).sequence_header(true), "encoder"),
+            (builder().stream_compression(true), "stream_compression requires"),
+            (
+                builder()
+                    .compression(Compression::Gzip)
+                    .compress_min_size(100)
+                    .stream_compression(true),
+                "stream_compression can't be combined with compress_min_size",
+            ),
+            (
+                builder().flush_policy(FlushPolicy::Interval(Duration::zero())),
+                "Interval",
+            ),
+            (
+                builder()
+                    .compression(Compression::Gzip)
+                    .keep_recent_uncompressed(1)
+                    .numbering(NumberingScheme::DateGrouped),
+                "DateGrouped",
+            ),
+        ];
+        for (b, option) in conflicts {
+            let e = b.build().unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert!(e.to_string().contains(option), "{}", e);
+        }
+        builder()
+            .compression(Compression::Gzip)
+            .compress_min_size(100)
+            .flush_policy(FlushPolicy::EveryN(1))
+            .validate()
+            .unwrap();
+        // nothing was created for the rejected configurations
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn seed_last_write() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        fs::write(&path, "Line 1\n").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(Local.with_ymd_and_hms(2021, 3, 30, 22, 0, 0).unwrap().into())
+            .unwrap();
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().daily(), 9)
+            .seed_last_write(true)
+            .build()
+            .unwrap();
+        rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 31, 1, 0, 0).unwrap())
+            .unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\n");
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "Line 2\n");
+
+        let mut c = RollingConditionBasic::new()
+            .daily()
+            .last_write(Local.with_ymd_and_hms(2021, 3, 30, 22, 0, 0).unwrap());
+        assert!(c.should_rollover(&Local.with_ymd_and_hms(2021, 3, 31, 1, 0, 0).unwrap(), 0));
+    }
+
+    #[test]
+    fn display() {
+        let mut c = build_context(RollingConditionBasic::new().daily().max_size(1024), 9);
         c.rolling
-            .write_with_datetime(b"Line 5\n", &Local.ymd(2022, 5, 31).and_hms(1, 4, 0))
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        let s = c.rolling.to_string();
+        assert!(s.contains(&*c.rolling.base_filename.to_string_lossy()), "{}", s);
+        assert!(
+            s.ends_with("(rolling daily, max size 1024 bytes, max files: 9, current size: 7 bytes)"),
+            "{}",
+            s
+        );
+        let s = format!("{:?}", c.rolling);
+        assert!(s.contains("max_files: 9"), "{}", s);
+        assert!(!s.contains("BufWriter"), "{}", s);
+    }
+
+    #[derive(Debug)]
+    struct FakeClock(std::sync::Mutex<DateTime<Local>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> DateTime<Local> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn appender_with_clock() {
+        let clock = Arc::new(FakeClock(std::sync::Mutex::new(
+            Local.with_ymd_and_hms(2021, 3, 30, 23, 59, 0).unwrap(),
+        )));
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new().daily(), 9)
+                .clock(Arc::clone(&clock) as Arc<dyn Clock + Send + Sync>)
+                .build()
+                .unwrap();
+        assert!(rolling.config().custom_clock);
+        // writes without a datetime take it from the clock
+        rolling.write_all(b"Line 1\n").unwrap();
+        *clock.0.lock().unwrap() = Local.with_ymd_and_hms(2021, 3, 30, 23, 59, 59).unwrap();
+        rolling.write_all(b"Line 2\n").unwrap();
+        assert_eq!(rolling.metrics().rollovers, 0);
+        *clock.0.lock().unwrap() = Local.with_ymd_and_hms(2021, 3, 31, 0, 0, 0).unwrap();
+        rolling.write_all(b"Line 3\n").unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(rolling.metrics().rollovers, 1);
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\nLine 2\n");
+    }
+
+    #[test]
+    fn roll_on_flush() {
+        let clock = Arc::new(FakeClock(std::sync::Mutex::new(
+            Local.with_ymd_and_hms(2021, 3, 30, 23, 0, 0).unwrap(),
+        )));
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new().daily(), 9)
+                .clock(Arc::clone(&clock) as Arc<dyn Clock + Send + Sync>)
+                .roll_on_flush(true)
+                .build()
+                .unwrap();
+        rolling.write_with_datetime(b"Line 1\n", &clock.now()).unwrap();
+        *clock.0.lock().unwrap() = Local.with_ymd_and_hms(2021, 3, 30, 23, 59, 59).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(rolling.metrics().rollovers, 0);
+        // the first flush of the next day rolls over, without any write
+        *clock.0.lock().unwrap() = Local.with_ymd_and_hms(2021, 3, 31, 0, 0, 1).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(rolling.metrics().rollovers, 1);
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\n");
+        // an idle day leaves the empty file alone
+        *clock.0.lock().unwrap() = Local.with_ymd_and_hms(2021, 4, 1, 0, 0, 1).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(rolling.metrics().rollovers, 1);
+        rolling.write_with_datetime(b"Line 2\n", &clock.now()).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "Line 2\n");
+        assert!(!Path::new(&rolling.filename_for(2)).exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn flush_failures_on_rollover() {
+        let tempdir = tempfile::tempdir().unwrap();
+        // writing to /dev/full always fails with ENOSPC
+        let full = OpenOptions::new().append(true).open("/dev/full").unwrap();
+        let mut rolling = BasicRollingFileAppender::from_file(
+            full,
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(1),
+            9,
+        )
+        .unwrap();
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        rolling.write_with_datetime(b"a", &now).unwrap();
+        for _ in 0..DEFAULT_MAX_FLUSH_FAILURES - 1 {
+            rolling.write_with_datetime(b"a", &now).unwrap();
+        }
+        let e = rolling.write_with_datetime(b"a", &now).unwrap_err();
+        assert!(e.to_string().contains("for rollover 3 times in a row"), "{}", e);
+        assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(1)).exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn flush_failure_after_write() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let full = OpenOptions::new().append(true).open("/dev/full").unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::from_file(full, tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .unwrap();
+        rolling.flush_policy = FlushPolicy::OnEveryWrite;
+        let (tx, rx) = std::sync::mpsc::channel();
+        rolling.error_handler_opt = Some(Box::new(move |e| tx.send(e.to_string()).unwrap()));
+        // the data was taken, so the write succeeds and the failure is reported
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        assert_eq!(rolling.write_with_datetime(b"Line 1\n", &now).unwrap(), 7);
+        let e = rx.try_recv().unwrap();
+        assert!(e.starts_with("Failed to flush logfile"), "{}", e);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn rollover_failure_after_write() {
+        for strict in [false, true] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let full = OpenOptions::new().append(true).open("/dev/full").unwrap();
+            let mut rolling = BasicRollingFileAppender::from_file(
+                full,
+                tempdir.path().join("test.log"),
+                RollingConditionBasic::new()
+                    .max_size(1)
+                    .size_timing(RolloverTiming::AfterWrite),
+                9,
+            )
+            .unwrap();
+            rolling.strict_full_disk = strict;
+            rolling.max_flush_failures = 2;
+            let (tx, rx) = std::sync::mpsc::channel();
+            // reported again when dropped, after the receiver
+            rolling.error_handler_opt = Some(Box::new(move |e| {
+                let _ = tx.send(e.to_string());
+            }));
+            let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+            // the data was taken before the rollover failed, so the writes
+            // succeed, even once retrying is pointless
+            let writes = if strict { 1 } else { 2 };
+            for _ in 0..writes {
+                assert_eq!(rolling.write_with_datetime(b"a", &now).unwrap(), 1);
+                let e = rx.try_recv().unwrap();
+                assert!(e.starts_with("Failed to rotate logfile"), "{}", e);
+            }
+            assert_eq!(rolling.is_degraded(), strict);
+            // the next write retries the rollover before writing, which fails
+            let e = rolling.write_with_datetime(b"a", &now).unwrap_err();
+            let expected = if strict {
+                "degraded"
+            } else {
+                "for rollover 3 times in a row"
+            };
+            assert!(e.to_string().contains(expected), "{}", e);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn buffer_cap_flush_failure() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let full = OpenOptions::new().append(true).open("/dev/full").unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::from_file(full, tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .unwrap();
+        rolling.max_buffered_bytes_opt = Some(1);
+        let (tx, rx) = std::sync::mpsc::channel();
+        rolling.error_handler_opt = Some(Box::new(move |e| tx.send(e.to_string()).unwrap()));
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        // the records were taken, so the whole batch succeeds
+        assert_eq!(rolling.write_batch(&[b"Line 1\n", b"Line 2\n"], &now).unwrap(), 14);
+        assert_eq!(
+            rx.try_iter()
+                .filter(|e| e.starts_with("Failed to flush logfile"))
+                .count(),
+            2
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sync_failure_after_write() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let full = OpenOptions::new().append(true).open("/dev/full").unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::from_file(full, tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .unwrap();
+        rolling.sync_interval_opt = Some(Duration::zero());
+        let (tx, rx) = std::sync::mpsc::channel();
+        rolling.error_handler_opt = Some(Box::new(move |e| tx.send(e.to_string()).unwrap()));
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        // the first write only starts the interval
+        for _ in 0..2 {
+            assert_eq!(rolling.write_with_datetime(b"Line 1\n", &now).unwrap(), 7);
+        }
+        let e = rx.try_recv().unwrap();
+        assert!(e.starts_with("Failed to sync logfile"), "{}", e);
+        // and it's retried on the next write
+        rolling.write_with_datetime(b"Line 1\n", &now).unwrap();
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn strict_full_disk() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let full = OpenOptions::new().append(true).open("/dev/full").unwrap();
+        let mut rolling = BasicRollingFileAppender::from_file(
+            full,
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(1),
+            9,
+        )
+        .unwrap();
+        rolling.strict_full_disk = true;
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        rolling.write_with_datetime(b"a", &now).unwrap();
+        // the rollover fails to flush, which fails the write right away
+        let e = rolling.write_with_datetime(b"a", &now).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::StorageFull);
+        assert!(rolling.is_degraded());
+        for _ in 0..3 {
+            let e = rolling.write_with_datetime(b"a", &now).unwrap_err();
+            assert!(e.to_string().contains("degraded"), "{}", e);
+        }
+        rolling.reopen().unwrap();
+        assert!(!rolling.is_degraded());
+        rolling.write_with_datetime(b"b", &now).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "b");
+    }
+
+    #[test]
+    fn write_batch() {
+        for (batch_rollover, expected) in [
+            (BatchRollover::PerRecord, vec!["eee\n", "ccc\nddd\n", "aaa\nbbb\n"]),
+            (BatchRollover::PerBatch, vec!["ccc\nddd\neee\n", "aaa\nbbb\n"]),
+        ] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling = BasicRollingFileAppender::builder(
+                tempdir.path().join("test.log"),
+                RollingConditionBasic::new().max_size(8),
+                9,
+            )
+            .batch_rollover(batch_rollover)
+            .build()
+            .unwrap();
+            let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+            assert_eq!(rolling.write_batch(&[b"aaa\n", b"bbb\n"], &now).unwrap(), 8);
+            // crosses max_size after the first record
+            assert_eq!(rolling.write_batch(&[b"ccc\n", b"ddd\n", b"eee\n"], &now).unwrap(), 12);
+            assert!(!rolling.is_rolling_paused());
+            rolling.flush().unwrap();
+            let files: Vec<_> = (0..expected.len())
+                .map(|n| fs::read_to_string(rolling.filename_for(n)).unwrap())
+                .collect();
+            assert_eq!(files, expected, "{:?}", batch_rollover);
+            assert!(!Path::new(&rolling.filename_for(expected.len())).exists());
+        }
+
+        // rolling over after writes, a batch crossing max_size is only
+        // rotated once it's complete
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new()
+                .max_size(12)
+                .size_timing(RolloverTiming::AfterWrite),
+            9,
+        )
+        .batch_rollover(BatchRollover::PerBatch)
+        .flush_policy(FlushPolicy::EveryN(2))
+        .build()
+        .unwrap();
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        rolling
+            .write_batch(&[b"aaa\n", b"bbb\n", b"ccc\n", b"ddd\n"], &now)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(rolling.filename_for(1)).unwrap(),
+            "aaa\nbbb\nccc\nddd\n"
+        );
+        // each batch counts as a single write to be flushed
+        rolling.write_batch(&[b"eee\n", b"fff\n"], &now).unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "");
+        rolling.write_batch(&[b"g"], &now).unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "eee\nfff\ng");
+        assert_eq!(rolling.batch_records_opt, None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn direct_io() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .direct_io(true)
+            .build()
+            .unwrap();
+        let block: Vec<u8> = (0..direct::ALIGNMENT).map(|i| (i % 256) as u8).collect();
+        rolling.write_all(&block).unwrap();
+        rolling.write_all(&block).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [&block[..], &block[..]].concat());
+        rolling.rollover().unwrap();
+        rolling.write_all(b"Line 1\n").unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(rolling.current_file_size(), 7);
+        assert_eq!(fs::read(&path).unwrap(), b"Line 1\n");
+        let mut all = Vec::new();
+        rolling.reader().unwrap().read_to_end(&mut all).unwrap();
+        assert_eq!(all, [&block[..], &block[..], b"Line 1\n"].concat());
+    }
+
+    #[test]
+    fn keyed_condition() {
+        let tempdir = tempfile::tempdir().unwrap();
+        // 10-minute windows
+        let condition = RollingConditionKeyed::new(|now| (now.date_naive(), now.hour(), now.minute() / 10));
+        let mut rolling = RollingFileAppender::new(tempdir.path().join("test.log"), condition, 9).unwrap();
+        for (minute, line) in [
+            (2, "Line 1\n"),
+            (9, "Line 2\n"),
+            (10, "Line 3\n"),
+            (19, "Line 4\n"),
+            (31, "Line 5\n"),
+        ] {
+            rolling
+                .write_with_datetime(
+                    line.as_bytes(),
+                    &Local.with_ymd_and_hms(2021, 3, 30, 1, minute, 0).unwrap(),
+                )
+                .unwrap();
+        }
+        rolling.flush().unwrap();
+        let files: Vec<_> = (0..3)
+            .map(|n| fs::read_to_string(rolling.filename_for(n)).unwrap())
+            .collect();
+        assert_eq!(files, ["Line 5\n", "Line 3\nLine 4\n", "Line 1\nLine 2\n"]);
+    }
+
+    #[test]
+    fn data_age_condition() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let condition = RollingConditionDataAge::new(Duration::minutes(10));
+        let mut rolling = RollingFileAppender::new(tempdir.path().join("test.log"), condition, 9).unwrap();
+        // anchored to the first record of each file, not to fixed periods
+        for (minute, line) in [
+            (5, "Line 1\n"),
+            (14, "Line 2\n"),
+            (15, "Line 3\n"),
+            (24, "Line 4\n"),
+            (26, "Line 5\n"),
+        ] {
+            rolling
+                .write_with_datetime(
+                    line.as_bytes(),
+                    &Local.with_ymd_and_hms(2021, 3, 30, 1, minute, 0).unwrap(),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            rolling.condition.first_write(),
+            Some(Local.with_ymd_and_hms(2021, 3, 30, 1, 26, 0).unwrap())
+        );
+        // a manual rollover starts over as well
+        rolling.rollover().unwrap();
+        assert_eq!(rolling.condition.first_write(), None);
+        rolling.flush().unwrap();
+        let files: Vec<_> = (1..4)
+            .map(|n| fs::read_to_string(rolling.filename_for(n)).unwrap())
+            .collect();
+        assert_eq!(files, ["Line 5\n", "Line 3\nLine 4\n", "Line 1\nLine 2\n"]);
+    }
+
+    #[test]
+    fn write_all_from() {
+        let mut c = build_context(RollingConditionBasic::new().max_size(10), 9);
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        let lines = (1..=4).map(|i| format!("Line {}\n", i));
+        assert_eq!(c.rolling.write_all_from(lines, &now).unwrap(), 28);
+        c.rolling.flush().unwrap();
+        // crosses max_size after every second line
+        c.verify_contains("Line 1\nLine 2\n", 1);
+        assert_eq!(
+            fs::read_to_string(c.rolling.filename_for(0)).unwrap(),
+            "Line 3\nLine 4\n"
+        );
+
+        // stops at the first failure without taking further records
+        c.rolling.degraded = true;
+        let mut taken = 0;
+        let records = (0..3).map(|_| {
+            taken += 1;
+            "Line
+"
+        });
+        assert!(c.rolling.write_all_from(records, &now).is_err());
+        assert_eq!(taken, 1);
+    }
+
+    #[test]
+    fn bare_filename() {
+        /// Resolves relative paths against a base directory rather than the
+        /// working directory, which a test can't change without affecting
+        /// the others running at the same time
+        #[derive(Debug)]
+        struct RootedFileSystem(PathBuf);
+
+        impl FileSystem for RootedFileSystem {
+            fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+                RealFileSystem.open_append(&self.0.join(path))
+            }
+
+            fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+                RealFileSystem.rename(&self.0.join(from), &self.0.join(to))
+            }
+
+            fn remove_file(&self, path: &Path) -> io::Result<()> {
+                RealFileSystem.remove_file(&self.0.join(path))
+            }
+
+            fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+                RealFileSystem.metadata(&self.0.join(path))
+            }
+
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+                RealFileSystem.read_dir(&self.0.join(path))
+            }
+        }
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling = BasicRollingFileAppender::builder("app.log", RollingConditionBasic::new(), 0)
+            .numbering(NumberingScheme::MonotonicIncrement)
+            .file_system(Arc::new(RootedFileSystem(tempdir.path().to_path_buf())))
+            .build()
+            .unwrap();
+        assert_eq!(rolling.directory(), Path::new("."));
+        // each rollover finds the rotated files in the current directory
+        for line in &["Line 1\n", "Line 2\n", "Line 3\n"] {
+            rolling.write_all(line.as_bytes()).unwrap();
+            rolling.rollover().unwrap();
+        }
+        rolling.write_all(b"Line 4\n").unwrap();
+        rolling.flush().unwrap();
+        let read = |name| fs::read_to_string(tempdir.path().join(name)).unwrap();
+        assert_eq!(read("app.log"), "Line 4\n");
+        assert_eq!(read("app.log.1"), "Line 1\n");
+        assert_eq!(read("app.log.2"), "Line 2\n");
+        assert_eq!(read("app.log.3"), "Line 3\n");
+        assert_eq!(rolling.total_managed_size().unwrap(), 28);
+    }
+
+    #[test]
+    fn flush_reporting() {
+        let mut c = build_context(RollingConditionBasic::new(), 9);
+        assert_eq!(c.rolling.flush_reporting().unwrap(), 0);
+        c.rolling.write_all(b"Line 1\n").unwrap();
+        c.rolling.write_all(b"Line 2\n").unwrap();
+        assert_eq!(c.rolling.flush_reporting().unwrap(), 14);
+        assert_eq!(c.rolling.flush_reporting().unwrap(), 0);
+        c.verify_contains("Line 2", 0);
+    }
+
+    #[test]
+    fn sequence_header() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let build = || {
+            BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .sequence_header(true)
+                .build()
+                .unwrap()
+        };
+        let mut rolling = build();
+        for i in 0..3 {
+            writeln!(rolling, "Line {}", i).unwrap();
+            rolling.rollover().unwrap();
+        }
+        drop(rolling);
+        // restarting continues with the existing file and sequence
+        let mut rolling = build();
+        rolling.write_all(b"Line 3\n").unwrap();
+        rolling.rollover().unwrap();
+        rolling.flush().unwrap();
+        let files: Vec<_> = (0..5)
+            .rev()
+            .map(|n| fs::read_to_string(rolling.filename_for(n)).unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                "#seq:1\nLine 0\n",
+                "#seq:2\nLine 1\n",
+                "#seq:3\nLine 2\n",
+                "#seq:4\nLine 3\n",
+                "#seq:5\n"
+            ]
+        );
+        assert_eq!(fs::read_to_string(rolling.sequence_filename()).unwrap(), "5\n");
+    }
+
+    #[test]
+    fn rotate_only() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .rotate_only(true)
+            .build()
+            .unwrap();
+        assert!(!path.exists());
+        assert!(!rolling.is_open());
+        // nothing to rotate yet
+        rolling.rollover().unwrap();
+        assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(1)).exists());
+        fs::write(&path, "Snapshot 1\n").unwrap();
+        rolling.rollover().unwrap();
+        fs::write(&path, "Snapshot 2\n").unwrap();
+        rolling.rollover().unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Snapshot 2\n");
+        assert_eq!(fs::read_to_string(rolling.filename_for(2)).unwrap(), "Snapshot 1\n");
+        assert_eq!(
+            rolling.write_all(b"Line\n").unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn writer_missing() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .rotate_only(true)
+            .build()
             .unwrap();
-        assert_eq!(AsRef::<Path>::as_ref(&c.rolling.filename_for(4)).exists(), false);
-        c.verify_contains("Line 1", 3);
-        c.verify_contains("Line 2", 3);
-        c.verify_contains("Line 3", 2);
-        c.verify_contains("Line 4", 1);
-        c.verify_contains("Line 5", 0);
+        // bypasses the check for rotate_only, so that no writer is opened
+        let e = rolling
+            .rollover_if_needed_and_write(
+                b"Line\n",
+                &mut Some(Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap()),
+            )
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Other);
+        match e.get_ref().and_then(|e| e.downcast_ref::<RollingFileError>()) {
+            Some(RollingFileError::WriterMissing {
+                path: p,
+                null: false,
+                degraded: false,
+                rotate_only: true,
+            }) => assert_eq!(p, &path),
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(e.to_string().contains("rotate only: true"), "{}", e);
+    }
+
+    #[test]
+    fn daily_compressed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::daily_compressed(&path, 9).unwrap();
+        let config = rolling.config();
+        assert_eq!(config.compression, Some(Compression::Gzip));
+        assert_eq!(config.flush_policy, FlushPolicy::Interval(Duration::seconds(1)));
+        rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 31, 1, 2, 3).unwrap())
+            .unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 2\n");
+        assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(1)).exists());
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(File::open(rolling.compressed_filename_for(1)).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, "Line 1\n");
+    }
+
+    #[test]
+    fn set_current_file_size() {
+        let mut c = build_context(RollingConditionBasic::new().max_size(100), 9);
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        c.rolling.write_with_datetime(b"Line 1\n", &now).unwrap();
+        c.rolling.set_current_file_size(100);
+        assert_eq!(c.rolling.current_file_size(), 100);
+        c.rolling.write_with_datetime(b"Line 2\n", &now).unwrap();
+        c.rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(c.rolling.filename_for(1)).unwrap(), "Line 1\n");
+        assert_eq!(fs::read_to_string(c.rolling.filename_for(0)).unwrap(), "Line 2\n");
+        assert_eq!(c.rolling.current_file_size(), 7);
+    }
+
+    #[test]
+    fn size_counts_framing() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for (counts_framing, rollovers) in [(true, 10), (false, 1)] {
+            let path = tempdir.path().join(format!("{}.log", counts_framing));
+            let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().max_size(50), 20)
+                .preamble(vec![b'#'; 100])
+                .size_counts_framing(counts_framing)
+                .build()
+                .unwrap();
+            for _ in 0..10 {
+                writeln!(rolling, "Line 1").unwrap();
+            }
+            rolling.flush().unwrap();
+            // with the header counting, every write finds the file over the limit
+            assert_eq!(rolling.metrics().rollovers, rollovers, "{}", counts_framing);
+            assert_eq!(rolling.current_file_size(), fs::metadata(&path).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn rollover_marker_suffix() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 2)
+            .compression(Compression::Gzip)
+            .rollover_marker_suffix(".done".to_string())
+            .build()
+            .unwrap();
+        let marker = |n| tempdir.path().join(format!("test.log.{}.done", n));
+        writeln!(rolling, "Line 1").unwrap();
+        rolling.rollover().unwrap();
+        assert_eq!(fs::read(marker(1)).unwrap(), b"");
+        assert!(AsRef::<Path>::as_ref(&rolling.compressed_filename_for(1)).exists());
+        for line in &["Line 2", "Line 3"] {
+            writeln!(rolling, "{}", line).unwrap();
+            rolling.rollover().unwrap();
+        }
+        // the markers moved along with their files, and the oldest was pruned
+        assert_eq!(fs::read(marker(1)).unwrap(), b"");
+        assert_eq!(fs::read(marker(2)).unwrap(), b"");
+        assert!(!marker(3).exists());
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 5);
+
+        // a marker that can't be created doesn't fail the rollover
+        let path = tempdir.path().join("blocked.log");
+        let (tx, rx) = std::sync::mpsc::channel();
+        // in a directory that doesn't exist
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 2)
+            .rollover_marker_suffix(".d/done".to_string())
+            .internal_error_handler(move |e| tx.send(e.to_string()).unwrap())
+            .build()
+            .unwrap();
+        writeln!(rolling, "Line 1").unwrap();
+        rolling.rollover().unwrap();
+        assert!(rx.try_recv().unwrap().starts_with("Failed to finish rotated file"));
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\n");
+        assert_eq!(rolling.metrics().rollover_failures, 0);
+    }
+
+    #[test]
+    fn max_filename_len() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let name = format!("{}.log", "x".repeat(250));
+        let path = tempdir.path().join(&name);
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 2)
+            .max_filename_len(255)
+            .build()
+            .unwrap();
+        for line in &["Line 1\n", "Line 2\n", "Line 3\n", "Line 4\n"] {
+            rolling.write_all(line.as_bytes()).unwrap();
+            rolling.rollover().unwrap();
+        }
+        let rotated = |n| {
+            PathBuf::from(rolling.filename_for(n))
+                .file_name()
+                .unwrap()
+                .to_os_string()
+        };
+        let shortened = rolling.rotated_base();
+        let shortened = Path::new(&shortened)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        assert!(shortened.len() + ROTATED_SUFFIX_RESERVE <= 255);
+        assert!(
+            shortened.starts_with("xxxx") && shortened.ends_with(".log"),
+            "{}",
+            shortened
+        );
+        assert_eq!(rotated(1), OsString::from(format!("{}.1", shortened)));
+        assert_eq!(rotated(2), OsString::from(format!("{}.2", shortened)));
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 4\n");
+        assert_eq!(fs::read_to_string(rolling.filename_for(2)).unwrap(), "Line 3\n");
+        // the older files were pruned
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 3);
+        assert!(path.exists());
+
+        // names that fit are left alone
+        let rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 2)
+                .max_filename_len(255)
+                .build()
+                .unwrap();
+        assert_eq!(
+            rolling.filename_for(1),
+            tempdir.path().join("test.log.1").into_os_string()
+        );
+        assert_eq!(shorten_file_name(OsStr::new(&name), 100).len(), 100);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn allow_null_fallback() {
+        let tempdir = tempfile::tempdir().unwrap();
+        // a file where the directory should be can't be opened even as root
+        let dir = tempdir.path().join("logs");
+        fs::write(&dir, "").unwrap();
+        let path = dir.join("test.log");
+        let e = BasicRollingFileAppender::new(&path, RollingConditionBasic::new(), 9).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotADirectory);
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().max_size(1), 9)
+            .allow_null_fallback(true)
+            .internal_error_handler(move |e| tx.send(e.to_string()).unwrap())
+            .build()
+            .unwrap();
+        assert!(rolling.is_null());
+        assert!(rx.try_recv().unwrap().starts_with("Failed to open logfile"));
+        writeln!(rolling, "Line 1").unwrap();
+        writeln!(rolling, "Line 2").unwrap();
+        rolling.flush().unwrap();
+        rolling.rollover().unwrap();
+        assert_eq!(rolling.null_dropped_bytes(), 14);
+        assert_eq!(rolling.metrics().writes, 0);
+
+        fs::remove_file(&dir).unwrap();
+        fs::create_dir(&dir).unwrap();
+        rolling.reopen().unwrap();
+        assert!(!rolling.is_null());
+        writeln!(rolling, "Line 3").unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 3\n");
+        assert_eq!(rolling.null_dropped_bytes(), 14);
+    }
+
+    #[test]
+    fn stream_compression() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .compression(Compression::Gzip)
+            .stream_compression(true)
+            .build()
+            .unwrap();
+        let decompress = |p: &Path| {
+            let mut decompressed = String::new();
+            flate2::read::MultiGzDecoder::new(File::open(p).unwrap())
+                .read_to_string(&mut decompressed)
+                .unwrap();
+            decompressed
+        };
+        writeln!(rolling, "Line 1").unwrap();
+        writeln!(rolling, "Line 2").unwrap();
+        rolling.flush().unwrap();
+        // the encoder holds the data until flushed
+        assert!(rolling.current_file_size() > 0);
+        rolling.rollover().unwrap();
+        assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(1)).exists());
+        assert_eq!(
+            decompress(rolling.compressed_filename_for(1).as_ref()),
+            "Line 1\nLine 2\n"
+        );
+        writeln!(rolling, "Line 3").unwrap();
+        rolling.close().unwrap();
+        assert_eq!(decompress(&path), "Line 3\n");
+        let mut read = String::new();
+        rolling.reader().unwrap().read_to_string(&mut read).unwrap();
+        assert_eq!(read, "Line 1\nLine 2\nLine 3\n");
+
+        // a restart appends another member
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .compression(Compression::Gzip)
+            .stream_compression(true)
+            .build()
+            .unwrap();
+        writeln!(rolling, "Line 4").unwrap();
+        rolling.close().unwrap();
+        assert_eq!(decompress(&path), "Line 3\nLine 4\n");
+    }
+
+    #[test]
+    fn fill_gaps() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().daily(), 9)
+            .fill_gaps(true)
+            .build()
+            .unwrap();
+        rolling
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        // two days later, with nothing written on March 31st
+        rolling
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 4, 1, 1, 2, 3).unwrap())
+            .unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(rolling.metrics().rollovers, 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 2\n");
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "");
+        assert_eq!(fs::read_to_string(rolling.filename_for(2)).unwrap(), "Line 1\n");
+
+        // a write in the next period has no gap to fill
+        rolling
+            .write_with_datetime(b"Line 3\n", &Local.with_ymd_and_hms(2021, 4, 2, 1, 2, 3).unwrap())
+            .unwrap();
+        assert_eq!(rolling.metrics().rollovers, 3);
+
+        let mut c = RollingConditionBasic::new()
+            .hourly()
+            .last_write(Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap());
+        assert_eq!(
+            c.skipped_periods(&Local.with_ymd_and_hms(2021, 3, 30, 4, 0, 0).unwrap()),
+            vec![
+                Local.with_ymd_and_hms(2021, 3, 30, 2, 0, 0).unwrap(),
+                Local.with_ymd_and_hms(2021, 3, 30, 3, 0, 0).unwrap(),
+            ]
+        );
+        assert!(c
+            .skipped_periods(&Local.with_ymd_and_hms(2021, 3, 30, 2, 59, 0).unwrap())
+            .is_empty());
+        c = RollingConditionBasic::new().hourly();
+        assert!(c
+            .skipped_periods(&Local.with_ymd_and_hms(2021, 3, 30, 4, 0, 0).unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn size_check_interval() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().max_size(20), 9)
+            .size_check_interval(1)
+            .internal_error_handler(move |e| tx.send(e.to_string()).unwrap())
+            .build()
+            .unwrap();
+        writeln!(rolling, "Line 1").unwrap();
+        writeln!(rolling, "Line 2").unwrap();
+        rolling.flush().unwrap();
+        File::options().write(true).open(&path).unwrap().set_len(0).unwrap();
+        writeln!(rolling, "Line 3").unwrap();
+        assert_eq!(rolling.current_file_size(), 7);
+        // without the resync, the tracked 21 bytes would have rolled over
+        writeln!(rolling, "Line 4").unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(rolling.current_file_size(), 14);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 3\nLine 4\n");
+        assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(1)).exists());
+        let reported: Vec<_> = rx.try_iter().collect();
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].contains("truncated to 0 bytes"), "{}", reported[0]);
+    }
+
+    #[test]
+    fn preamble() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let build = || {
+            BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .preamble(&b"\xEF\xBB\xBF"[..])
+                .sequence_header(true)
+                .build()
+                .unwrap()
+        };
+        let mut rolling = build();
+        rolling.write_all(b"Line 0\n").unwrap();
+        rolling.rollover().unwrap();
+        rolling.write_all(b"Line 1\n").unwrap();
+        drop(rolling);
+        // restarting reopens the existing file without a second preamble
+        let mut rolling = build();
+        rolling.write_all(b"Line 2\n").unwrap();
+        rolling.rollover().unwrap();
+        rolling.flush().unwrap();
+        let files: Vec<_> = (0..3)
+            .rev()
+            .map(|n| fs::read(rolling.filename_for(n)).unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                b"\xEF\xBB\xBF#seq:1\nLine 0\n".to_vec(),
+                b"\xEF\xBB\xBF#seq:2\nLine 1\nLine 2\n".to_vec(),
+                b"\xEF\xBB\xBF#seq:3\n".to_vec(),
+            ]
+        );
+        assert_eq!(rolling.current_filesize, 10);
+    }
+
+    #[test]
+    fn rollover_timing() {
+        fn files_after(condition: RollingConditionBasic, writes: &[(&str, u32)]) -> Vec<String> {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling = RollingFileAppender::new(tempdir.path().join("test.log"), condition, 9).unwrap();
+            for (line, day) in writes {
+                rolling
+                    .write_with_datetime(
+                        line.as_bytes(),
+                        &Local.with_ymd_and_hms(2021, 3, *day, 1, 2, 3).unwrap(),
+                    )
+                    .unwrap();
+            }
+            rolling.flush().unwrap();
+            (0..9)
+                .rev()
+                .filter_map(|n| fs::read_to_string(rolling.filename_for(n)).ok())
+                .collect()
+        }
+
+        // the write reaching the limit ends the file either way, but only
+        // after the write does the new file start right away
+        let size = RollingConditionBasic::new().max_size(6);
+        let writes = [("aaaa", 1), ("bbbb", 1)];
+        assert_eq!(files_after(size, &writes), vec!["aaaabbbb"]);
+        assert_eq!(
+            files_after(size.size_timing(RolloverTiming::AfterWrite), &writes),
+            vec!["aaaabbbb", ""]
+        );
+
+        let daily = RollingConditionBasic::new().daily();
+        let writes = [("aaa", 1), ("bbb", 2), ("ccc", 2)];
+        assert_eq!(files_after(daily, &writes), vec!["aaa", "bbbccc"]);
+        assert_eq!(
+            files_after(daily.time_timing(RolloverTiming::AfterWrite), &writes),
+            vec!["aaabbb", "ccc"]
+        );
+    }
+
+    /// A timezone moving from UTC to UTC+1 at midnight on 2021-03-28, so
+    /// that the first hour of that day doesn't exist.
+    #[derive(Clone, Debug)]
+    struct MidnightDst;
+
+    impl MidnightDst {
+        fn change() -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(2021, 3, 28)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        }
+
+        fn offset_after(after: bool) -> FixedOffset {
+            FixedOffset::east_opt(if after { 3600 } else { 0 }).unwrap()
+        }
+    }
+
+    impl TimeZone for MidnightDst {
+        type Offset = FixedOffset;
+
+        fn from_offset(_offset: &FixedOffset) -> Self {
+            MidnightDst
+        }
+
+        // the day of the change mostly has the new offset
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            LocalResult::Single(Self::offset_after(*local >= Self::change().date()))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let change = Self::change();
+            if *local < change {
+                LocalResult::Single(Self::offset_after(false))
+            } else if *local < change + Duration::hours(1) {
+                LocalResult::None
+            } else {
+                LocalResult::Single(Self::offset_after(true))
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            Self::offset_after(*utc >= Self::change().date())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            Self::offset_after(*utc >= Self::change())
+        }
+    }
+
+    #[test]
+    fn dst_gap_frequency() {
+        let dt = |d, h| MidnightDst.with_ymd_and_hms(2021, 3, d, h, 30, 0).unwrap();
+        // midnight doesn't exist on the 28th, so the day starts at 01:00
+        let start = RollingFrequency::EveryDay.equivalent_datetime(&dt(28, 1));
+        assert_eq!(start, MidnightDst.with_ymd_and_hms(2021, 3, 28, 1, 0, 0).unwrap());
+        assert_eq!(start, RollingFrequency::EveryDay.equivalent_datetime(&dt(28, 23)));
+        assert_ne!(start, RollingFrequency::EveryDay.equivalent_datetime(&dt(27, 23)));
+        assert_ne!(start, RollingFrequency::EveryDay.equivalent_datetime(&dt(29, 0)));
+        assert_eq!(
+            RollingFrequency::EveryHour.equivalent_datetime(&dt(28, 1)),
+            MidnightDst.with_ymd_and_hms(2021, 3, 28, 1, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn fixed_offset_frequency() {
+        let ist = FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap();
+        let dt = |d, h, m| ist.with_ymd_and_hms(2021, 3, d, h, m, 30).unwrap();
+        assert_eq!(
+            RollingFrequency::EveryDay.equivalent_datetime(&dt(30, 23, 59)),
+            ist.with_ymd_and_hms(2021, 3, 30, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            RollingFrequency::EveryHour.equivalent_datetime(&dt(30, 5, 10)),
+            ist.with_ymd_and_hms(2021, 3, 30, 5, 0, 0).unwrap()
+        );
+        assert_eq!(
+            RollingFrequency::EveryMinute.equivalent_datetime(&dt(30, 5, 10)),
+            ist.with_ymd_and_hms(2021, 3, 30, 5, 10, 0).unwrap()
+        );
+        // 04:00 at +05:30 is still the previous day in UTC
+        assert_eq!(
+            RollingFrequency::EveryUtcDay.equivalent_datetime(&dt(30, 4, 0)),
+            ist.with_ymd_and_hms(2021, 3, 29, 5, 30, 0).unwrap()
+        );
+        for frequency in [
+            RollingFrequency::EveryDay,
+            RollingFrequency::EveryHour,
+            RollingFrequency::EveryMinute,
+        ] {
+            assert_ne!(
+                frequency.equivalent_datetime(&dt(30, 23, 59)),
+                frequency.equivalent_datetime(&dt(31, 0, 0))
+            );
+            assert_eq!(
+                frequency.equivalent_datetime(&dt(31, 0, 0)),
+                frequency.equivalent_datetime(&(dt(31, 0, 0) + Duration::seconds(20)))
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn non_utf8_filename() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join(OsStr::from_bytes(b"test\xff.log"));
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 0)
+            .numbering(NumberingScheme::MonotonicIncrement)
+            .compression(Compression::Gzip)
+            .build()
+            .unwrap();
+        assert_eq!(
+            rolling.filename_for(2).as_bytes(),
+            [path.as_os_str().as_bytes(), b".2"].concat()
+        );
+        assert_eq!(
+            rolling.compressed_filename_for(2).as_bytes(),
+            [path.as_os_str().as_bytes(), b".2.gz"].concat()
+        );
+        for i in 0..3 {
+            writeln!(rolling, "Line {}", i).unwrap();
+            rolling.rollover().unwrap();
+        }
+        assert_eq!(rolling.archive_indices().unwrap(), vec![1, 2, 3]);
+        assert!(Path::new(&rolling.compressed_filename_for(3)).exists());
+        let lines: Vec<_> = rolling.reader().unwrap().lines().map(|x| x.unwrap()).collect();
+        assert_eq!(lines, vec!["Line 0", "Line 1", "Line 2"]);
+    }
+
+    #[test]
+    fn size_only_fast_path() {
+        assert!(!RollingConditionBasic::new().max_size(10).needs_time());
+        assert!(RollingConditionBasic::new().daily().max_size(10).needs_time());
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut fast = BasicRollingFileAppender::new(
+            tempdir.path().join("fast.log"),
+            RollingConditionBasic::new().max_size(25),
+            9,
+        )
+        .unwrap();
+        let mut general = BasicRollingFileAppender::new(
+            tempdir.path().join("general.log"),
+            RollingConditionBasic::new().max_size(25),
+            9,
+        )
+        .unwrap();
+        assert!(!fast.needs_time());
+        for i in 0..20 {
+            let line = format!("Line {}\n", i);
+            fast.write_all(line.as_bytes()).unwrap();
+            general.write_with_datetime(line.as_bytes(), &Local::now()).unwrap();
+        }
+        fast.flush().unwrap();
+        general.flush().unwrap();
+        for n in 0..=9 {
+            assert_eq!(
+                fs::read(fast.filename_for(n)).ok(),
+                fs::read(general.filename_for(n)).ok(),
+                "file {}",
+                n
+            );
+        }
+        assert!(AsRef::<Path>::as_ref(&fast.filename_for(3)).exists());
+        // the rollovers took the current time when they happened
+        assert!(Local::now().signed_duration_since(fast.untimed_now) < Duration::minutes(1));
+    }
+
+    #[test]
+    fn rotate_dirs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dirs = vec![tempdir.path().join("a"), tempdir.path().join("b")];
+        for dir in &dirs {
+            fs::create_dir(dir).unwrap();
+        }
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .rotate_dirs(dirs.clone())
+                .build()
+                .unwrap();
+        for i in 0..4 {
+            writeln!(rolling, "Line {}", i).unwrap();
+            rolling.rollover().unwrap();
+        }
+        writeln!(rolling, "Line 4").unwrap();
+        rolling.flush().unwrap();
+        assert!(!tempdir.path().join("test.log").exists());
+        let read = |dir: usize, name: &str| fs::read_to_string(dirs[dir].join(name)).unwrap();
+        assert_eq!(read(0, "test.log"), "Line 4\n");
+        assert_eq!(read(0, "test.log.1"), "Line 2\n");
+        assert_eq!(read(0, "test.log.2"), "Line 0\n");
+        assert!(!dirs[1].join("test.log").exists());
+        assert_eq!(read(1, "test.log.1"), "Line 3\n");
+        assert_eq!(read(1, "test.log.2"), "Line 1\n");
+    }
+
+    #[test]
+    fn max_buffered_bytes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .max_buffered_bytes(64)
+            .build()
+            .unwrap();
+        let records = vec![&b"012345678\n"[..]; 100];
+        assert_eq!(rolling.write_batch(&records, &Local::now()).unwrap(), 1000);
+        // all but the last few records were flushed during the batch
+        let on_disk = fs::metadata(&path).unwrap().len();
+        assert!(on_disk > 1000 - 64, "{}", on_disk);
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "012345678\n".repeat(100));
+
+        // without the cap, the batch stays in the write buffer
+        let path = tempdir.path().join("uncapped.log");
+        let mut rolling = BasicRollingFileAppender::new(&path, RollingConditionBasic::new(), 9).unwrap();
+        rolling.write_batch(&records, &Local::now()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+        assert!(
+            BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .max_buffered_bytes(0)
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn max_write_size() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(10),
+            9,
+        )
+        .max_write_size(10)
+        .build()
+        .unwrap();
+        let buf = b"0123456789".repeat(3);
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        assert_eq!(rolling.write_with_datetime(&buf, &now).unwrap(), 30);
+        assert_eq!(rolling.write_with_datetime(b"abcde", &now).unwrap(), 5);
+        rolling.flush().unwrap();
+        assert_eq!(rolling.metrics().rollovers, 3);
+        let files: Vec<_> = (0..4)
+            .map(|n| fs::read_to_string(rolling.filename_for(n)).unwrap())
+            .collect();
+        assert_eq!(files, ["abcde", "0123456789", "0123456789", "0123456789"]);
+
+        /// Fails every rename, so that no rollover succeeds, which fails the
+        /// write with `max_flush_failures(0)`
+        #[derive(Debug)]
+        struct NoRenameFileSystem;
+
+        impl FileSystem for NoRenameFileSystem {
+            fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+                RealFileSystem.open_append(path)
+            }
+
+            fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+                Err(io::ErrorKind::PermissionDenied.into())
+            }
+
+            fn remove_file(&self, path: &Path) -> io::Result<()> {
+                RealFileSystem.remove_file(path)
+            }
+
+            fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+                RealFileSystem.metadata(path)
+            }
+
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+                RealFileSystem.read_dir(path)
+            }
+        }
+
+        // a chunk failing after the first one fails the whole record
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("stuck.log"),
+            RollingConditionBasic::new().max_size(10),
+            9,
+        )
+        .max_write_size(10)
+        .max_flush_failures(0)
+        .file_system(Arc::new(NoRenameFileSystem))
+        .build()
+        .unwrap();
+        assert!(rolling.write_with_datetime(&buf, &now).is_err());
+        assert!(rolling.write_batch(&[b"abcde", &buf], &now).is_err());
+        assert_eq!(rolling.metrics().write_errors, 2);
+
+        assert_eq!(
+            BasicRollingFileAppender::builder(tempdir.path().join("other.log"), RollingConditionBasic::new(), 9)
+                .max_write_size(10)
+                .length_prefixed(LengthPrefix::U32LE)
+                .validate()
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn rollover_reasons() {
+        let mut c = RollingConditionBasic::new().daily().max_size(10);
+        assert_eq!(c.rollover_reason(), None);
+        assert!(!c.should_rollover(&Local.with_ymd_and_hms(2021, 3, 30, 1, 0, 0).unwrap(), 0));
+        assert!(c.should_rollover(&Local.with_ymd_and_hms(2021, 3, 30, 2, 0, 0).unwrap(), 10));
+        assert_eq!(c.rollover_reason(), Some(RolloverReason::Size));
+        assert!(c.should_rollover(&Local.with_ymd_and_hms(2021, 3, 31, 1, 0, 0).unwrap(), 0));
+        assert_eq!(c.rollover_reason(), Some(RolloverReason::Time));
+
+        let mut c = RollingConditionBasic::new()
+            .max_size(10)
+            .size_timing(RolloverTiming::AfterWrite);
+        assert!(c.should_rollover_after_write(&Local.with_ymd_and_hms(2021, 3, 30, 1, 0, 0).unwrap(), 10));
+        assert_eq!(c.rollover_reason(), Some(RolloverReason::Size));
+        assert_eq!(
+            Debounced::new(c, Duration::seconds(10)).rollover_reason(),
+            Some(RolloverReason::Size)
+        );
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().daily().max_size(10),
+            9,
+        )
+        .rollover_events(tx)
+        .build()
+        .unwrap();
+        rolling
+            .write_with_datetime(b"0123456789\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 0, 0).unwrap())
+            .unwrap();
+        rolling
+            .write_with_datetime(b"a\n", &Local.with_ymd_and_hms(2021, 3, 30, 2, 0, 0).unwrap())
+            .unwrap();
+        rolling
+            .write_with_datetime(b"b\n", &Local.with_ymd_and_hms(2021, 3, 31, 1, 0, 0).unwrap())
+            .unwrap();
+        let reasons: Vec<_> = rx.try_iter().map(|e| e.reason).collect();
+        assert_eq!(reasons, vec![RolloverReason::Size, RolloverReason::Time]);
+    }
+
+    #[test]
+    fn rollover_events() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new().daily(), 9)
+                .compression(Compression::Gzip)
+                .rollover_events(tx)
+                .build()
+                .unwrap();
+        let day1 = Local.with_ymd_and_hms(2021, 3, 30, 1, 0, 0).unwrap();
+        let day2 = Local.with_ymd_and_hms(2021, 3, 31, 1, 0, 0).unwrap();
+        rolling.write_with_datetime(b"Line 1\n", &day1).unwrap();
+        rolling.write_with_datetime(b"Line 2\n", &day2).unwrap();
+        rolling.rollover().unwrap();
+        let events: Vec<_> = rx.try_iter().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].path, tempdir.path().join("test.log.1.gz"));
+        assert_eq!(events[0].timestamp, day2);
+        assert_eq!(events[0].reason, RolloverReason::Time);
+        assert_eq!(events[1].path, tempdir.path().join("test.log.1.gz"));
+        assert_eq!(events[1].reason, RolloverReason::Manual);
+
+        // a full bounded channel drops events instead of blocking
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("bounded.log"), RollingConditionBasic::new(), 9)
+                .rollover_events_bounded(tx)
+                .build()
+                .unwrap();
+        for _ in 0..3 {
+            rolling.rollover().unwrap();
+        }
+        let events: Vec<_> = rx.try_iter().collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].path, tempdir.path().join("bounded.log.1"));
+        drop(rx);
+        rolling.rollover().unwrap();
+        assert!(!rolling.config().has_rollover_events);
+    }
+
+    #[test]
+    fn keep_recent_uncompressed() {
+        for numbering in [NumberingScheme::ShiftUp, NumberingScheme::MonotonicIncrement] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling =
+                BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                    .compression(Compression::Gzip)
+                    .keep_recent_uncompressed(1)
+                    .numbering(numbering)
+                    .build()
+                    .unwrap();
+            for i in 0..4 {
+                writeln!(rolling, "Line {}", i).unwrap();
+                rolling.rollover().unwrap();
+            }
+            let mut files: Vec<_> = fs::read_dir(tempdir.path())
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            files.sort();
+            let expected = match numbering {
+                NumberingScheme::ShiftUp => vec![
+                    "test.log",
+                    "test.log.1",
+                    "test.log.2.gz",
+                    "test.log.3.gz",
+                    "test.log.4.gz",
+                ],
+                NumberingScheme::MonotonicIncrement => {
+                    vec![
+                        "test.log",
+                        "test.log.1.gz",
+                        "test.log.2.gz",
+                        "test.log.3.gz",
+                        "test.log.4",
+                    ]
+                },
+                NumberingScheme::DateGrouped => unreachable!("doesn't support keep_recent_uncompressed"),
+            };
+            assert_eq!(files, expected);
+            let lines: Vec<_> = rolling.reader().unwrap().lines().map(|x| x.unwrap()).collect();
+            assert_eq!(lines, vec!["Line 0", "Line 1", "Line 2", "Line 3"]);
+        }
+    }
+
+    #[test]
+    fn numbering_schemes() {
+        for (numbering, expected) in [
+            (
+                NumberingScheme::ShiftUp,
+                vec![("test.log.1", "ccccc"), ("test.log.2", "bbbbb")],
+            ),
+            (
+                NumberingScheme::MonotonicIncrement,
+                vec![("test.log.2", "bbbbb"), ("test.log.3", "ccccc")],
+            ),
+        ] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling = BasicRollingFileAppender::builder(
+                tempdir.path().join("test.log"),
+                RollingConditionBasic::new().max_size(5),
+                2,
+            )
+            .numbering(numbering)
+            .build()
+            .unwrap();
+            for line in &["aaaaa", "bbbbb", "ccccc", "ddddd"] {
+                rolling
+                    .write_with_datetime(line.as_bytes(), &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+                    .unwrap();
+            }
+            rolling.flush().unwrap();
+            let mut files: Vec<_> = fs::read_dir(tempdir.path())
+                .unwrap()
+                .map(|e| {
+                    let e = e.unwrap();
+                    (
+                        e.file_name().into_string().unwrap(),
+                        fs::read_to_string(e.path()).unwrap(),
+                    )
+                })
+                .collect();
+            files.sort();
+            let mut expected: Vec<_> = expected
+                .into_iter()
+                .map(|(f, c)| (f.to_string(), c.to_string()))
+                .collect();
+            expected.insert(0, ("test.log".to_string(), "ddddd".to_string()));
+            assert_eq!(files, expected, "{:?}", numbering);
+        }
+    }
+
+    #[test]
+    fn error_sink() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let errors = tempdir.path().join("errors.log");
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(20),
+            9,
+        )
+        .error_sink(&errors, |buf| buf.windows(5).any(|w| w == b"ERROR"))
+        .build()
+        .unwrap();
+        assert!(errors.exists());
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        for line in &["INFO one\n", "ERROR two\n", "INFO three\n", "ERROR four\n"] {
+            rolling.write_with_datetime(line.as_bytes(), &now).unwrap();
+        }
+        rolling.flush().unwrap();
+        // the secondary file doesn't roll over with the current file
+        assert!(Path::new(&rolling.filename_for(1)).exists());
+        assert_eq!(fs::read_to_string(&errors).unwrap(), "ERROR two\nERROR four\n");
+    }
+
+    #[test]
+    fn debounced() {
+        fn count_files<RC: RollingCondition>(condition: RC) -> usize {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling = RollingFileAppender::new(tempdir.path().join("test.log"), condition, 100).unwrap();
+            for burst in 0..3 {
+                let start = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap() + Duration::minutes(burst);
+                for i in 0..10 {
+                    rolling
+                        .write_with_datetime(b"line\n", &(start + Duration::milliseconds(i)))
+                        .unwrap();
+                }
+            }
+            rolling.flush().unwrap();
+            fs::read_dir(tempdir.path()).unwrap().count()
+        }
+        let condition = RollingConditionBasic::new().max_size(12);
+        assert_eq!(count_files(condition), 10);
+        // once the size is exceeded, only one rollover per burst
+        assert_eq!(count_files(Debounced::new(condition, Duration::seconds(10))), 4);
+    }
+
+    #[test]
+    fn maintenance_window() {
+        fn files(max_size: u64, writes: &[(u32, u32, u32, &str)]) -> Vec<String> {
+            let tempdir = tempfile::tempdir().unwrap();
+            let condition = RollingConditionBasic::new()
+                .daily()
+                .max_size(max_size)
+                .maintenance_window(
+                    NaiveTime::from_hms_opt(2, 0, 0).unwrap(),
+                    NaiveTime::from_hms_opt(4, 0, 0).unwrap(),
+                );
+            let mut rolling = RollingFileAppender::new(tempdir.path().join("test.log"), condition, 9).unwrap();
+            for (day, hour, minute, line) in writes {
+                rolling
+                    .write_with_datetime(
+                        line.as_bytes(),
+                        &Local.with_ymd_and_hms(2021, 3, *day, *hour, *minute, 0).unwrap(),
+                    )
+                    .unwrap();
+            }
+            rolling.flush().unwrap();
+            (0..3)
+                .map(|n| fs::read_to_string(rolling.filename_for(n)).unwrap_or_default())
+                .collect()
+        }
+        // the roll due at midnight waits until 02:00
+        assert_eq!(
+            files(
+                1000,
+                &[
+                    (29, 23, 0, "Line 1\n"),
+                    (30, 0, 0, "Line 2\n"),
+                    (30, 1, 59, "Line 3\n"),
+                    (30, 2, 0, "Line 4\n"),
+                    (30, 3, 0, "Line 5\n"),
+                ]
+            ),
+            ["Line 4\nLine 5\n", "Line 1\nLine 2\nLine 3\n", ""]
+        );
+        // a window crossing midnight
+        let c = MaintenanceWindow::new(
+            RollingConditionBasic::new(),
+            NaiveTime::from_hms_opt(23, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(1, 0, 0).unwrap(),
+        );
+        assert!(c.in_window(&Local.with_ymd_and_hms(2021, 3, 30, 23, 30, 0).unwrap()));
+        assert!(c.in_window(&Local.with_ymd_and_hms(2021, 3, 30, 0, 30, 0).unwrap()));
+        assert!(!c.in_window(&Local.with_ymd_and_hms(2021, 3, 30, 1, 0, 0).unwrap()));
+        // the size limit applies at any time, and a size rollover also ends
+        // the deferral
+        assert_eq!(
+            files(
+                14,
+                &[
+                    (29, 23, 0, "Line 1\n"),
+                    (30, 0, 0, "Line 2\n"),
+                    (30, 1, 0, "Line 3\n"),
+                    (30, 2, 0, "Line 4\n"),
+                ]
+            ),
+            ["Line 3\nLine 4\n", "Line 1\nLine 2\n", ""]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn read_only_archives() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(5),
+            9,
+        )
+        .read_only_archives(true)
+        .build()
+        .unwrap();
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        for line in &["aaaaa", "bbbbb", "ccccc"] {
+            rolling.write_with_datetime(line.as_bytes(), &now).unwrap();
+        }
+        let mode = |n| fs::metadata(rolling.filename_for(n)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(1), 0o444);
+        assert_eq!(mode(2), 0o444);
+        assert_ne!(mode(0) & 0o200, 0);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stable_hardlink() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let stable = tempdir.path().join("current.log");
+        let builder = || {
+            BasicRollingFileAppender::builder(
+                tempdir.path().join("test.log"),
+                RollingConditionBasic::new().max_size(7),
+                9,
+            )
+            .stable_hardlink(stable.clone())
+        };
+        let mut rolling = builder().build().unwrap();
+        let ino = |p: &dyn AsRef<Path>| fs::metadata(p).unwrap().ino();
+        assert_eq!(ino(&stable), ino(&rolling.filename_for(0)));
+        let mut tailer = File::open(&stable).unwrap();
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        for line in &["Line 1\n", "Line 2\n"] {
+            rolling.write_with_datetime(line.as_bytes(), &now).unwrap();
+        }
+        rolling.flush().unwrap();
+        // the link follows the rollover to the new current file
+        assert_eq!(ino(&stable), ino(&rolling.filename_for(0)));
+        assert_eq!(fs::read_to_string(&stable).unwrap(), "Line 2\n");
+        // while a reader of the old link stays with the rotated file
+        let mut s = String::new();
+        io::Read::read_to_string(&mut tailer, &mut s).unwrap();
+        assert_eq!(s, "Line 1\n");
+        assert_eq!(fs::metadata(&stable).unwrap().nlink(), 2);
+        // reopening the current file keeps the link, without leftovers
+        drop(rolling);
+        let rolling = builder().build().unwrap();
+        assert_eq!(ino(&stable), ino(&rolling.filename_for(0)));
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 3);
+        let e = builder().file_system(Arc::new(RealFileSystem)).build().unwrap_err();
+        assert!(e.to_string().contains("stable_hardlink"), "{}", e);
+    }
+
+    #[test]
+    fn encoder() {
+        const KEY: u8 = 0x5a;
+
+        /// XORs every byte with the key, and ends each file with the number
+        /// of bytes it encoded
+        #[derive(Default)]
+        struct XorEncoder {
+            encoded: usize,
+        }
+
+        impl Encoder for XorEncoder {
+            fn encode(&mut self, input: &[u8], out: &mut dyn Write) -> io::Result<()> {
+                self.encoded += input.len();
+                out.write_all(&input.iter().map(|b| b ^ KEY).collect::<Vec<_>>())
+            }
+
+            fn finalize(&mut self, out: &mut dyn Write) -> io::Result<()> {
+                let trailer = format!("#{}\n", std::mem::take(&mut self.encoded));
+                self.encode(trailer.as_bytes(), out)?;
+                self.encoded = 0;
+                Ok(())
+            }
+        }
+
+        fn decode(path: OsString) -> String {
+            String::from_utf8(fs::read(path).unwrap().iter().map(|b| b ^ KEY).collect()).unwrap()
+        }
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(7),
+            9,
+        )
+        .encoder(XorEncoder::default())
+        .build()
+        .unwrap();
+        assert!(rolling.config().encoder);
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        for line in &["Line 1\n", "Line 2\n", "Line 3\n"] {
+            rolling.write_with_datetime(line.as_bytes(), &now).unwrap();
+        }
+        rolling.close().unwrap();
+        assert_ne!(fs::read(rolling.filename_for(0)).unwrap(), b"Line 3\n#7\n");
+        // each file ends where its encoding was finalized
+        assert_eq!(decode(rolling.filename_for(2)), "Line 1\n#7\n");
+        assert_eq!(decode(rolling.filename_for(1)), "Line 2\n#7\n");
+        assert_eq!(decode(rolling.filename_for(0)), "Line 3\n#7\n");
+    }
+
+    #[test]
+    fn rename_retry() {
+        /// Fails the first renames of the first rotated file the way Windows
+        /// does while someone else has it open.
+        #[derive(Debug)]
+        struct InUseFileSystem {
+            failures: std::sync::atomic::AtomicUsize,
+        }
+
+        impl FileSystem for InUseFileSystem {
+            fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+                RealFileSystem.open_append(path)
+            }
+
+            fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+                let remaining = self.failures.load(std::sync::atomic::Ordering::Relaxed);
+                if from.to_string_lossy().ends_with(".log.1") && remaining > 0 {
+                    self.failures.store(remaining - 1, std::sync::atomic::Ordering::Relaxed);
+                    return Err(io::ErrorKind::PermissionDenied.into());
+                }
+                RealFileSystem.rename(from, to)
+            }
+
+            fn remove_file(&self, path: &Path) -> io::Result<()> {
+                RealFileSystem.remove_file(path)
+            }
+
+            fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+                RealFileSystem.metadata(path)
+            }
+
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+                RealFileSystem.read_dir(path)
+            }
+        }
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let fs = Arc::new(InUseFileSystem {
+            failures: std::sync::atomic::AtomicUsize::new(0),
+        });
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .file_system(Arc::clone(&fs) as Arc<dyn FileSystem>)
+                .build()
+                .unwrap();
+        rolling.write_all(b"Line 1\n").unwrap();
+        rolling.rollover().unwrap();
+        // released before running out of retries
+        fs.failures.store(RENAME_RETRIES, std::sync::atomic::Ordering::Relaxed);
+        rolling.write_all(b"Line 2\n").unwrap();
+        if !cfg!(windows) {
+            // elsewhere, a denied rename isn't caused by the file being in
+            // use, so it fails right away
+            assert_eq!(rolling.rollover().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+            assert_eq!(
+                fs.failures.load(std::sync::atomic::Ordering::Relaxed),
+                RENAME_RETRIES - 1
+            );
+            return;
+        }
+        rolling.rollover().unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 2\n");
+        assert_eq!(fs::read_to_string(rolling.filename_for(2)).unwrap(), "Line 1\n");
+        // held for longer, the rollover fails, leaving the files shifted
+        // before the failure where they are
+        fs.failures
+            .store(RENAME_RETRIES + 1, std::sync::atomic::Ordering::Relaxed);
+        rolling.write_all(b"Line 3\n").unwrap();
+        assert_eq!(rolling.rollover().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(fs::read_to_string(rolling.filename_for(3)).unwrap(), "Line 1\n");
+    }
+
+    #[test]
+    fn collision_policy() {
+        /// Fails to shift the first rotated file, which leaves it in the way
+        /// of the current file.
+        #[derive(Debug)]
+        struct StuckFileSystem;
+
+        impl FileSystem for StuckFileSystem {
+            fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+                RealFileSystem.open_append(path)
+            }
+
+            fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+                if to.to_string_lossy().ends_with(".log.2") {
+                    return Err(io::ErrorKind::PermissionDenied.into());
+                }
+                RealFileSystem.rename(from, to)
+            }
+
+            fn remove_file(&self, path: &Path) -> io::Result<()> {
+                RealFileSystem.remove_file(path)
+            }
+
+            fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+                RealFileSystem.metadata(path)
+            }
+
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+                RealFileSystem.read_dir(path)
+            }
+        }
+
+        for policy in [
+            CollisionPolicy::Overwrite,
+            CollisionPolicy::FailFast,
+            CollisionPolicy::Backup,
+        ] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let path = tempdir.path().join("test.log");
+            let read = |name: &str| fs::read_to_string(tempdir.path().join(name)).ok();
+            fs::write(tempdir.path().join("test.log.1"), "Old\n").unwrap();
+            let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .file_system(Arc::new(StuckFileSystem))
+                .collision_policy(policy)
+                .build()
+                .unwrap();
+            assert_eq!(rolling.config().collision_policy, policy);
+            rolling.write_all(b"Line 1\n").unwrap();
+            let e = rolling.rollover().unwrap_err();
+            match policy {
+                CollisionPolicy::Overwrite => {
+                    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+                    assert_eq!(read("test.log.1").unwrap(), "Line 1\n");
+                },
+                CollisionPolicy::FailFast => {
+                    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+                    assert_eq!(read("test.log.1").unwrap(), "Old\n");
+                    assert_eq!(read("test.log").unwrap(), "Line 1\n");
+                },
+                CollisionPolicy::Backup => {
+                    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+                    assert_eq!(read("test.log.1").unwrap(), "Line 1\n");
+                    assert_eq!(read("test.log.1.bak").unwrap(), "Old\n");
+                },
+            }
+        }
     }
 
     #[test]
-    fn frequency_every_day_limited_files() {
-        let mut c = build_context(RollingConditionBasic::new().daily(), 2);
-        c.rolling
-            .write_with_datetime(b"Line 1\n", &Local.ymd(2021, 3, 30).and_hms(1, 2, 3))
-            .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 2\n", &Local.ymd(2021, 3, 30).and_hms(1, 3, 0))
-            .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 3\n", &Local.ymd(2021, 3, 31).and_hms(1, 4, 0))
-            .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 4\n", &Local.ymd(2021, 5, 31).and_hms(1, 4, 0))
-            .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 5\n", &Local.ymd(2022, 5, 31).and_hms(1, 4, 0))
+    fn compress_retry() {
+        /// Puts a non-empty directory in the way of compressing the first
+        /// rotated file, which makes compressing it fail.
+        #[derive(Debug, Default)]
+        struct BlockingFileSystem {
+            blocked: std::sync::atomic::AtomicBool,
+        }
+
+        impl FileSystem for BlockingFileSystem {
+            fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+                RealFileSystem.open_append(path)
+            }
+
+            fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+                RealFileSystem.rename(from, to)?;
+                if !self.blocked.swap(true, std::sync::atomic::Ordering::Relaxed) {
+                    let mut blocker = to.as_os_str().to_os_string();
+                    blocker.push(".gz");
+                    fs::create_dir(&blocker)?;
+                    fs::write(Path::new(&blocker).join("file"), "")?;
+                }
+                Ok(())
+            }
+
+            fn remove_file(&self, path: &Path) -> io::Result<()> {
+                RealFileSystem.remove_file(path)
+            }
+
+            fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+                RealFileSystem.metadata(path)
+            }
+
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+                RealFileSystem.read_dir(path)
+            }
+        }
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let delay = std::time::Duration::from_millis(10);
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .compression(Compression::Gzip)
+            .compress_retry(3, delay)
+            .internal_error_handler(move |e| tx.send(e.to_string()).unwrap())
+            .build()
             .unwrap();
-        assert_eq!(AsRef::<Path>::as_ref(&c.rolling.filename_for(4)).exists(), false);
-        assert_eq!(AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists(), false);
-        c.verify_contains("Line 3", 2);
-        c.verify_contains("Line 4", 1);
-        c.verify_contains("Line 5", 0);
+        // set after validation, which rejects compression with a custom file
+        // system since compressing bypasses it
+        rolling.file_system_opt = Some(Arc::new(BlockingFileSystem::default()));
+        rolling.write_all(b"Line 1\n").unwrap();
+        let start = std::time::Instant::now();
+        rolling.rollover().unwrap();
+        // attempted three times, and left intact
+        assert!(start.elapsed() >= 2 * delay);
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\n");
+        let reported: Vec<_> = rx.try_iter().collect();
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].starts_with("Failed to compress"));
+        // the next rollover compresses both files
+        fs::remove_dir_all(tempdir.path().join("test.log.1.gz")).unwrap();
+        rolling.write_all(b"Line 2\n").unwrap();
+        rolling.rollover().unwrap();
+        for (n, expected) in [(1, "Line 2\n"), (2, "Line 1\n")] {
+            assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(n)).exists());
+            let mut s = String::new();
+            flate2::read::GzDecoder::new(File::open(rolling.compressed_filename_for(n)).unwrap())
+                .read_to_string(&mut s)
+                .unwrap();
+            assert_eq!(s, expected);
+        }
+        assert_eq!(rx.try_iter().count(), 0);
+
+        // without retries, a failure is reported all the same
+        let tempdir = tempfile::tempdir().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .compression(Compression::Gzip)
+                .internal_error_handler(move |e| tx.send(e.to_string()).unwrap())
+                .build()
+                .unwrap();
+        rolling.file_system_opt = Some(Arc::new(BlockingFileSystem::default()));
+        rolling.write_all(b"Line 1\n").unwrap();
+        rolling.rollover().unwrap();
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\n");
+        let reported: Vec<_> = rx.try_iter().collect();
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].starts_with("Failed to compress"));
     }
 
     #[test]
-    fn frequency_every_hour() {
-        let mut c = build_context(RollingConditionBasic::new().hourly(), 9);
-        c.rolling
-            .write_with_datetime(b"Line 1\n", &Local.ymd(2021, 3, 30).and_hms(1, 2, 3))
+    fn compress_existing() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        for n in &[1, 3] {
+            fs::write(format!("{}.{}", path.display(), n), "uncompressed").unwrap();
+        }
+        fs::write(format!("{}.2.gz", path.display()), "compressed").unwrap();
+        let rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .compression(Compression::Gzip)
+            .build()
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 2\n", &Local.ymd(2021, 3, 30).and_hms(1, 3, 2))
+        fs::write(&path, "current").unwrap();
+        assert_eq!(rolling.compress_existing().unwrap(), 2);
+        let mut files: Vec<_> = fs::read_dir(tempdir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["test.log", "test.log.1.gz", "test.log.2.gz", "test.log.3.gz"]
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "current");
+        assert_eq!(
+            fs::read_to_string(format!("{}.2.gz", path.display())).unwrap(),
+            "compressed"
+        );
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(File::open(format!("{}.3.gz", path.display())).unwrap())
+            .read_to_string(&mut decompressed)
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 3\n", &Local.ymd(2021, 3, 30).and_hms(2, 1, 0))
+        assert_eq!(decompressed, "uncompressed");
+        assert_eq!(rolling.compress_existing().unwrap(), 0);
+    }
+
+    #[test]
+    fn is_open() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::new(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9).unwrap();
+        assert!(rolling.is_open());
+        rolling.close().unwrap();
+        assert!(!rolling.is_open());
+        rolling.write_all(b"reopened\n").unwrap();
+        assert!(rolling.is_open());
+        rolling.close().unwrap();
+        rolling.ensure_open().unwrap();
+        assert!(rolling.is_open());
+        rolling.ensure_open().unwrap();
+        assert!(rolling.is_open());
+    }
+
+    #[test]
+    fn daily_utc() {
+        let mut c = RollingConditionBasic::new().daily_utc();
+        let utc = |h, m| {
+            Utc.with_ymd_and_hms(2021, 3, 30, h, m, 0)
+                .unwrap()
+                .with_timezone(&Local)
+        };
+        assert!(!c.should_rollover(&utc(12, 0), 0));
+        assert!(!c.should_rollover(&utc(23, 59), 0));
+        // the first write after midnight UTC rolls over, whatever the local time
+        assert!(c.should_rollover(&(utc(23, 59) + Duration::minutes(2)), 0));
+        assert!(!c.should_rollover(&(utc(23, 59) + Duration::hours(23)), 0));
+        assert_eq!(c.to_string(), "daily (UTC)");
+    }
+
+    #[test]
+    fn set_max_files_unlimited() {
+        for numbering in [NumberingScheme::ShiftUp, NumberingScheme::MonotonicIncrement] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling =
+                BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 2)
+                    .numbering(numbering)
+                    .build()
+                    .unwrap();
+            for i in 0..4 {
+                writeln!(rolling, "line {}", i).unwrap();
+                rolling.rollover().unwrap();
+            }
+            assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 3);
+            rolling.set_max_files(0);
+            for i in 4..10 {
+                writeln!(rolling, "line {}", i).unwrap();
+                rolling.rollover().unwrap();
+            }
+            assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 9, "{:?}", numbering);
+            let mut lines: Vec<_> = fs::read_dir(tempdir.path())
+                .unwrap()
+                .map(|e| fs::read_to_string(e.unwrap().path()).unwrap())
+                .collect();
+            lines.sort();
+            assert_eq!(lines[0], "");
+            assert_eq!(
+                lines[1..],
+                (2..10).map(|i| format!("line {}\n", i)).collect::<Vec<_>>()[..]
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "checksum")]
+    fn checksum_sidecar() {
+        use sha2::{Digest, Sha256};
+
+        let hex = |data: &[u8]| {
+            Sha256::digest(data)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        for mode in [Checksum::BeforeCompression, Checksum::AfterCompression] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let path = tempdir.path().join("test.log");
+            let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .compression(Compression::Gzip)
+                .checksum(mode)
+                .build()
+                .unwrap();
+            rolling.write_all(b"first file\n").unwrap();
+            rolling.rollover().unwrap();
+            rolling.write_all(b"second file\n").unwrap();
+            rolling.rollover().unwrap();
+
+            let archive = tempdir.path().join("test.log.2.gz");
+            let sidecar = fs::read_to_string(tempdir.path().join("test.log.2.gz.sha256")).unwrap();
+            let expected = match mode {
+                Checksum::BeforeCompression => hex(b"first file\n"),
+                Checksum::AfterCompression => hex(&fs::read(&archive).unwrap()),
+            };
+            assert_eq!(sidecar, format!("{}\n", expected));
+            assert!(verify_checksum(&archive).unwrap());
+            assert!(verify_checksum(tempdir.path().join("test.log.1.gz")).unwrap());
+
+            fs::write(&archive, b"tampered").unwrap();
+            assert!(!verify_checksum(&archive).unwrap());
+        }
+    }
+
+    #[test]
+    fn pause_rolling() {
+        let mut c = build_context(RollingConditionBasic::new().max_size(10), 9);
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        c.rolling.pause_rolling();
+        assert!(c.rolling.is_rolling_paused());
+        for _ in 0..5 {
+            c.rolling.write_with_datetime(b"0123456789", &now).unwrap();
+        }
+        c.rolling.flush().unwrap();
+        assert_eq!(fs::metadata(c.rolling.filename_for(0)).unwrap().len(), 50);
+        assert!(!Path::new(&c.rolling.filename_for(1)).exists());
+        c.rolling.resume_rolling();
+        c.rolling.write_with_datetime(b"resumed", &now).unwrap();
+        c.rolling.flush().unwrap();
+        assert_eq!(fs::metadata(c.rolling.filename_for(1)).unwrap().len(), 50);
+        c.verify_contains("resumed", 0);
+    }
+
+    #[test]
+    fn empty_writes() {
+        for skip in [true, false] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling = BasicRollingFileAppender::builder(
+                tempdir.path().join("test.log"),
+                RollingConditionBasic::new().daily(),
+                9,
+            )
+            .skip_empty_writes(skip)
+            .build()
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 4\n", &Local.ymd(2021, 3, 31).and_hms(2, 1, 0))
+            rolling
+                .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+                .unwrap();
+            assert_eq!(
+                rolling
+                    .write_with_datetime(b"", &Local.with_ymd_and_hms(2021, 3, 31, 1, 2, 3).unwrap())
+                    .unwrap(),
+                0
+            );
+            assert_eq!(Path::new(&rolling.filename_for(1)).exists(), !skip);
+            // when skipped, the empty write didn't update the last write either
+            rolling
+                .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 31, 1, 2, 4).unwrap())
+                .unwrap();
+            rolling.flush().unwrap();
+            assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "Line 2\n");
+            assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\n");
+        }
+    }
+
+    #[test]
+    fn reader_lines() {
+        for numbering in [NumberingScheme::ShiftUp, NumberingScheme::MonotonicIncrement] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling = BasicRollingFileAppender::builder(
+                tempdir.path().join("test.log"),
+                RollingConditionBasic::new().max_size(30),
+                100,
+            )
+            .compression(Compression::Gzip)
+            .compress_min_size(40)
+            .numbering(numbering)
+            .build()
             .unwrap();
-        assert_eq!(AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists(), false);
-        c.verify_contains("Line 1", 2);
-        c.verify_contains("Line 2", 2);
-        c.verify_contains("Line 3", 1);
-        c.verify_contains("Line 4", 0);
+            for i in 0..100 {
+                // vary the line lengths so only some files are compressed
+                writeln!(rolling, "Line {}{}", i, " ".repeat(i % 15)).unwrap();
+            }
+            rolling.flush().unwrap();
+            let names: Vec<_> = fs::read_dir(tempdir.path())
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            assert!(names.iter().any(|x| x.ends_with(".gz")));
+            assert!(names.iter().any(|x| x != "test.log" && !x.ends_with(".gz")));
+
+            let lines: Vec<_> = rolling.reader().unwrap().lines().map(|x| x.unwrap()).collect();
+            let expected: Vec<_> = (0..100).map(|i| format!("Line {}{}", i, " ".repeat(i % 15))).collect();
+            assert_eq!(lines, expected, "{:?}", numbering);
+        }
     }
 
     #[test]
-    fn frequency_every_minute() {
-        let mut c = build_context(RollingConditionBasic::new().frequency(RollingFrequency::EveryMinute), 9);
-        c.rolling
-            .write_with_datetime(b"Line 1\n", &Local.ymd(2021, 3, 30).and_hms(1, 2, 3))
+    fn sync_interval() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .sync_interval(Duration::seconds(10))
+                .build()
+                .unwrap();
+        let start = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        rolling.write_with_datetime(b"Line 1\n", &start).unwrap();
+        assert_eq!(rolling.last_sync_opt, Some(start));
+        rolling
+            .write_with_datetime(b"Line 2\n", &(start + Duration::seconds(5)))
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"Line 2\n", &Local.ymd(2021, 3, 30).and_hms(1, 2, 3))
+        assert_eq!(rolling.last_sync_opt, Some(start));
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "");
+        // syncing flushes the buffer as well
+        rolling
+            .write_with_datetime(b"Line 3\n", &(start + Duration::seconds(10)))
             .unwrap();
+        assert_eq!(rolling.last_sync_opt, Some(start + Duration::seconds(10)));
+        assert_eq!(
+            fs::read_to_string(rolling.filename_for(0)).unwrap(),
+            "Line 1\nLine 2\nLine 3\n"
+        );
+
+        assert!(
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .sync_interval(Duration::zero())
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn max_dir_entries() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for i in 0..5 {
+            fs::write(tempdir.path().join(format!("other{}", i)), "").unwrap();
+        }
+        let build = |x| {
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .max_dir_entries(x)
+                .build()
+        };
+        let e = build(4).unwrap_err();
+        assert!(e.to_string().contains("has 5 entries"), "{}", e);
+        assert!(!tempdir.path().join("test.log").exists());
+        build(5).unwrap();
+    }
+
+    #[test]
+    fn take_metrics() {
+        let mut c = build_context(RollingConditionBasic::new().max_size(10), 9);
+        for line in &["Line 1\n", "Line 2\n", "Line 3\n"] {
+            c.rolling.write_all(line.as_bytes()).unwrap();
+        }
+        let first = c.rolling.take_metrics();
+        assert_eq!((first.writes, first.bytes_written, first.rollovers), (3, 21, 1));
+        assert_eq!(c.rolling.metrics(), AppenderMetrics::default());
+        c.rolling.write_all(b"Line 4\n").unwrap();
+        c.rolling.rollover().unwrap();
+        // metrics() doesn't reset
+        assert_eq!(c.rolling.metrics().writes, 1);
+        let second = c.rolling.take_metrics();
+        assert_eq!(
+            (
+                second.writes,
+                second.bytes_written,
+                second.rollovers,
+                second.write_errors
+            ),
+            (1, 7, 1, 0)
+        );
+        c.rolling.degraded = true;
+        assert!(c.rolling.write_all(b"Line 5\n").is_err());
+        assert_eq!(c.rolling.take_metrics().write_errors, 1);
+    }
+
+    #[test]
+    fn config() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new().daily(), 9)
+                .compression(Compression::BlockGzip)
+                .compress_min_size(1024)
+                .flush_policy(FlushPolicy::EveryN(10))
+                .numbering(NumberingScheme::MonotonicIncrement)
+                .min_free_bytes(1)
+                .build()
+                .unwrap();
+        let config = rolling.config();
+        assert_eq!(config.max_files, 9);
+        assert_eq!(config.numbering, NumberingScheme::MonotonicIncrement);
+        assert_eq!(config.compression, Some(Compression::BlockGzip));
+        assert_eq!(config.compress_min_size, 1024);
+        assert_eq!(config.flush_policy, FlushPolicy::EveryN(10));
+        assert_eq!(config.min_free_bytes, Some(1));
+        assert_eq!(config.sync_interval, None);
+        assert_eq!(config.max_flush_failures, DEFAULT_MAX_FLUSH_FAILURES);
+        assert!(!config.has_error_sink);
+
+        rolling.set_max_files(0);
+        assert_ne!(rolling.config(), config);
+        assert_eq!(rolling.config().max_files, 0);
+    }
+
+    #[test]
+    fn session_bytes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        fs::write(&path, "x".repeat(95)).unwrap();
+        let mut rolling = RollingFileAppender::new(&path, RollingConditionSessionBytes::new(10), 9).unwrap();
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        // the existing 95 bytes don't count
+        rolling.write_with_datetime(b"aaaaa", &now).unwrap();
+        rolling.write_with_datetime(b"bbbbb", &now).unwrap();
+        assert!(!Path::new(&rolling.filename_for(1)).exists());
+        rolling.write_with_datetime(b"ccccc", &now).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::metadata(rolling.filename_for(1)).unwrap().len(), 105);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ccccc");
+
+        // a manual rollover resets the count as well
+        rolling.rollover().unwrap();
+        rolling.write_with_datetime(b"ddddd", &now).unwrap();
+        rolling.write_with_datetime(b"eeeee", &now).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "dddddeeeee");
+    }
+
+    #[test]
+    fn daily_min_duration() {
+        let mut c = build_context(RollingConditionBasic::new().daily().min_duration(Duration::hours(1)), 9);
         c.rolling
-            .write_with_datetime(b"Line 3\n", &Local.ymd(2021, 3, 30).and_hms(1, 2, 4))
+            .write_with_datetime(b"Line 1\n", &Local.with_ymd_and_hms(2021, 3, 30, 23, 50, 0).unwrap())
             .unwrap();
         c.rolling
-            .write_with_datetime(b"Line 4\n", &Local.ymd(2021, 3, 30).and_hms(1, 3, 0))
+            .write_with_datetime(b"Line 2\n", &Local.with_ymd_and_hms(2021, 3, 31, 0, 10, 0).unwrap())
             .unwrap();
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(1)).exists());
         c.rolling
-            .write_with_datetime(b"Line 5\n", &Local.ymd(2021, 3, 30).and_hms(2, 3, 0))
+            .write_with_datetime(b"Line 3\n", &Local.with_ymd_and_hms(2021, 3, 31, 0, 55, 0).unwrap())
             .unwrap();
         c.rolling
-            .write_with_datetime(b"Line 6\n", &Local.ymd(2022, 3, 30).and_hms(2, 3, 0))
+            .write_with_datetime(b"Line 4\n", &Local.with_ymd_and_hms(2021, 3, 31, 10, 0, 0).unwrap())
             .unwrap();
-        assert_eq!(AsRef::<Path>::as_ref(&c.rolling.filename_for(4)).exists(), false);
-        c.verify_contains("Line 1", 3);
-        c.verify_contains("Line 2", 3);
-        c.verify_contains("Line 3", 3);
-        c.verify_contains("Line 4", 2);
-        c.verify_contains("Line 5", 1);
-        c.verify_contains("Line 6", 0);
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(2)).exists());
+        c.verify_contains("Line 1\nLine 2\n", 1);
+        c.verify_contains("Line 3\nLine 4\n", 0);
     }
 
+    #[cfg(unix)]
     #[test]
-    fn max_size() {
-        let mut c = build_context(RollingConditionBasic::new().max_size(10), 9);
-        c.rolling
-            .write_with_datetime(b"12345", &Local.ymd(2021, 3, 30).and_hms(1, 2, 3))
-            .unwrap();
-        c.rolling
-            .write_with_datetime(b"6789", &Local.ymd(2021, 3, 30).and_hms(1, 3, 3))
+    fn notify_socket() {
+        use std::{io::BufRead, os::unix::net::UnixListener};
+        let tempdir = tempfile::tempdir().unwrap();
+        let socket = tempdir.path().join("agent.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .notify_socket(socket.clone())
+            .build()
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"0", &Local.ymd(2021, 3, 30).and_hms(2, 3, 3))
+        writeln!(rolling, "Line 1").unwrap();
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        rolling.rollover_with_reason(&now, RolloverReason::Manual).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        io::BufReader::new(stream).read_line(&mut line).unwrap();
+        assert_eq!(
+            line,
+            format!(
+                "{{\"rolled\":\"{}\",\"ts\":\"{}\"}}\n",
+                tempdir.path().join("test.log.1").display(),
+                now.to_rfc3339()
+            )
+        );
+
+        // a rollover by size alone is timestamped too
+        let mut sized = BasicRollingFileAppender::builder(
+            tempdir.path().join("sized.log"),
+            RollingConditionBasic::new().max_size(7),
+            9,
+        )
+        .notify_socket(socket.clone())
+        .build()
+        .unwrap();
+        writeln!(sized, "Line 1").unwrap();
+        writeln!(sized, "Line 2").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        io::BufReader::new(stream).read_line(&mut line).unwrap();
+        let ts = line.rsplit('"').nth(1).unwrap();
+        assert!(DateTime::parse_from_rfc3339(ts).unwrap().year() > 2020, "{}", line);
+
+        // a missing listener doesn't fail the rollover
+        drop(listener);
+        fs::remove_file(&socket).unwrap();
+        rolling.rollover().unwrap();
+        assert!(AsRef::<Path>::as_ref(&rolling.filename_for(2)).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lock_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let open = || {
+            BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .lock_file(true)
+                .build()
+                .unwrap()
+        };
+        let (mut a, mut b) = (open(), open());
+        a.write_all(b"a1\n").unwrap();
+        a.flush().unwrap();
+        b.write_all(b"b1\n").unwrap();
+        b.flush().unwrap();
+        a.rollover().unwrap();
+        // b's file was rotated already, so b only switches to the new one
+        b.rollover().unwrap();
+        a.write_all(b"a2\n").unwrap();
+        a.flush().unwrap();
+        b.write_all(b"b2\n").unwrap();
+        b.flush().unwrap();
+        assert_eq!(fs::read_to_string(a.filename_for(1)).unwrap(), "a1\nb1\n");
+        assert!(!AsRef::<Path>::as_ref(&a.filename_for(2)).exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a2\nb2\n");
+        assert!(tempdir.path().join("test.log.lock").exists());
+        // also once b closed the file it was writing
+        b.close().unwrap();
+        a.rollover().unwrap();
+        b.rollover().unwrap();
+        assert_eq!(fs::read_to_string(a.filename_for(1)).unwrap(), "a2\nb2\n");
+        assert_eq!(fs::read_to_string(a.filename_for(2)).unwrap(), "a1\nb1\n");
+        assert!(!AsRef::<Path>::as_ref(&a.filename_for(3)).exists());
+
+        // threads rolling over concurrently never lose a file
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let mut rolling = open();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        writeln!(rolling, "{}", i).unwrap();
+                        rolling.rollover().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let lines: Vec<_> = a.reader().unwrap().lines().map(|x| x.unwrap()).collect();
+        assert!(!lines.is_empty());
+        for n in 1..=9 {
+            assert!(AsRef::<Path>::as_ref(&a.filename_for(n)).exists(), "{}", n);
+        }
+    }
+
+    #[test]
+    fn total_managed_size() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::new(&path, RollingConditionBasic::new(), 0).unwrap();
+        assert_eq!(rolling.total_managed_size().unwrap(), 0);
+        fs::write(rolling.filename_for(1), "x".repeat(100)).unwrap();
+        fs::write(rolling.compressed_filename_for(2), "x".repeat(20)).unwrap();
+        fs::write(rolling.filename_for(12), "x".repeat(3)).unwrap();
+        // not managed by the appender
+        fs::write(tempdir.path().join("test.log.old"), "x".repeat(1000)).unwrap();
+        fs::write(tempdir.path().join("other.log.1"), "x".repeat(1000)).unwrap();
+        rolling.write_all(&[b'x'; 7]).unwrap();
+        assert_eq!(rolling.total_managed_size().unwrap(), 123);
+        rolling.flush().unwrap();
+        assert_eq!(rolling.total_managed_size().unwrap(), 130);
+    }
+
+    #[test]
+    fn roll_on_trigger_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let trigger = tempdir.path().join("test.log.roll");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut rolling =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .roll_on_trigger_file(trigger.clone())
+                .rollover_events(tx)
+                .build()
+                .unwrap();
+        writeln!(rolling, "Line 1").unwrap();
+        writeln!(rolling, "Line 2").unwrap();
+        assert!(!AsRef::<Path>::as_ref(&rolling.filename_for(1)).exists());
+        fs::write(&trigger, "").unwrap();
+        writeln!(rolling, "Line 3").unwrap();
+        rolling.flush().unwrap();
+        assert!(!trigger.exists());
+        assert_eq!(fs::read_to_string(rolling.filename_for(1)).unwrap(), "Line 1\nLine 2\n");
+        assert_eq!(fs::read_to_string(rolling.filename_for(0)).unwrap(), "Line 3\n");
+        assert_eq!(rx.try_recv().unwrap().reason, RolloverReason::TriggerFile);
+    }
+
+    #[test]
+    fn fallback_buffer() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().join("logs");
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .fallback_buffer(16)
+            .build()
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"abcdefghijklmn", &Local.ymd(2021, 3, 31).and_hms(2, 3, 3))
+        writeln!(rolling, "Line 1").unwrap();
+        rolling.close().unwrap();
+        // the directory disappears, so the file can't be reopened
+        fs::remove_dir_all(&dir).unwrap();
+        writeln!(rolling, "Line 2").unwrap();
+        writeln!(rolling, "Line 3").unwrap();
+        writeln!(rolling, "Line 4").unwrap();
+        assert!(!rolling.is_open());
+        assert_eq!(rolling.fallback_dropped_bytes(), 5);
+        fs::create_dir(&dir).unwrap();
+        writeln!(rolling, "Line 5").unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2\nLine 3\nLine 4\nLine 5\n");
+        assert_eq!(rolling.current_file_size(), 23);
+
+        // held writes are written by a flush, or failing that, fail it
+        rolling.close().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        writeln!(rolling, "Line 6").unwrap();
+        assert!(rolling.flush().is_err());
+        fs::create_dir(&dir).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 6\n");
+        // or when the appender is dropped
+        rolling.close().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        writeln!(rolling, "Line 7").unwrap();
+        fs::create_dir(&dir).unwrap();
+        drop(rolling);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 7\n");
+
+        // held writes are framed like any other
+        let path = dir.join("test.bin");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .fallback_buffer(16)
+            .length_prefixed(LengthPrefix::U32LE)
+            .build()
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"ZZZ", &Local.ymd(2022, 3, 31).and_hms(1, 2, 3))
+        rolling.close().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        rolling.write_all(b"ab").unwrap();
+        fs::create_dir(&dir).unwrap();
+        rolling.write_all(b"c").unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"\x02\x00\x00\x00ab\x01\x00\x00\x00c");
+
+        assert!(
+            BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .fallback_buffer(0)
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn fallback_partial_write() {
+        /// Takes up to 5000 bytes per write, failing once out of writes.
+        #[derive(Debug)]
+        struct FlakyFileSystem {
+            writes: Arc<std::sync::atomic::AtomicUsize>,
+        }
+
+        struct FlakyFile {
+            file: File,
+            writes: Arc<std::sync::atomic::AtomicUsize>,
+        }
+
+        impl Write for FlakyFile {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let writes = self.writes.load(std::sync::atomic::Ordering::Relaxed);
+                if writes == 0 {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+                self.writes.store(writes - 1, std::sync::atomic::Ordering::Relaxed);
+                self.file.write(&buf[..buf.len().min(5000)])
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.file.flush()
+            }
+        }
+
+        impl FileSystem for FlakyFileSystem {
+            fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+                Ok(Box::new(FlakyFile {
+                    file: OpenOptions::new().append(true).create(true).open(path)?,
+                    writes: Arc::clone(&self.writes),
+                }))
+            }
+
+            fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+                RealFileSystem.rename(from, to)
+            }
+
+            fn remove_file(&self, path: &Path) -> io::Result<()> {
+                RealFileSystem.remove_file(path)
+            }
+
+            fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+                RealFileSystem.metadata(path)
+            }
+
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+                RealFileSystem.read_dir(path)
+            }
+        }
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let writes = Arc::new(std::sync::atomic::AtomicUsize::new(usize::MAX));
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .file_system(Arc::new(FlakyFileSystem {
+                writes: Arc::clone(&writes),
+            }))
+            .fallback_buffer(100_000)
+            .build()
             .unwrap();
-        assert_eq!(AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists(), false);
-        c.verify_contains("1234567890", 2);
-        c.verify_contains("abcdefghijklmn", 1);
-        c.verify_contains("ZZZ", 0);
+        let record: Vec<u8> = (0..12_000).map(|i| b'a' + (i % 26) as u8).collect();
+        // the first part of the record is taken, the rest is held
+        writes.store(1, std::sync::atomic::Ordering::Relaxed);
+        rolling.write_all(&record).unwrap();
+        // data still buffered by the abandoned file is held before newer data
+        writes.store(usize::MAX, std::sync::atomic::Ordering::Relaxed);
+        rolling.write_all(b"Line 1\n").unwrap();
+        writes.store(0, std::sync::atomic::Ordering::Relaxed);
+        rolling.write_all(&record).unwrap();
+        writes.store(usize::MAX, std::sync::atomic::Ordering::Relaxed);
+        rolling.flush().unwrap();
+        let mut expected = record.clone();
+        expected.extend(b"Line 1\n");
+        expected.extend(&record);
+        assert_eq!(fs::read(&path).unwrap(), expected);
     }
 
     #[test]
-    fn max_size_existing() {
-        let mut c = build_context(RollingConditionBasic::new().max_size(10), 9);
-        c.rolling
-            .write_with_datetime(b"12345", &Local.ymd(2021, 3, 30).and_hms(1, 2, 3))
+    fn import_existing() {
+        for (numbering, expected) in [
+            (
+                NumberingScheme::ShiftUp,
+                ["test.log.1", "test.log.2", "test.log.3", "test.log.4"],
+            ),
+            (
+                NumberingScheme::MonotonicIncrement,
+                ["test.log.4", "test.log.3", "test.log.2", "test.log.1"],
+            ),
+        ] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let path = tempdir.path().join("test.log");
+            let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .numbering(numbering)
+                .build()
+                .unwrap();
+            writeln!(rolling, "rotated").unwrap();
+            rolling.rollover().unwrap();
+            // named by another tool, and modified in a different order
+            let mut imported = Vec::new();
+            for (name, day) in [
+                ("test.log.2024-01-02", 2),
+                ("test.log.old", 1),
+                ("test.log.2024-01-03", 3),
+            ] {
+                let p = tempdir.path().join(name);
+                fs::write(&p, format!("{}\n", name)).unwrap();
+                File::options()
+                    .write(true)
+                    .open(&p)
+                    .unwrap()
+                    .set_modified(Local.with_ymd_and_hms(2024, 1, day, 12, 0, 0).unwrap().into())
+                    .unwrap();
+                imported.push(p);
+            }
+            let moved = rolling.import_existing(&imported).unwrap();
+            assert_eq!(moved.len(), 3);
+            // newest first
+            let contents: Vec<_> = expected
+                .iter()
+                .map(|name| fs::read_to_string(tempdir.path().join(name)).unwrap())
+                .collect();
+            assert_eq!(
+                contents,
+                [
+                    "rotated\n",
+                    "test.log.2024-01-03\n",
+                    "test.log.2024-01-02\n",
+                    "test.log.old\n"
+                ],
+                "{:?}",
+                numbering
+            );
+            assert!(imported.iter().all(|p| !p.exists()));
+            assert!(rolling.import_existing(std::slice::from_ref(&path)).is_err());
+        }
+    }
+
+    #[test]
+    fn defer_on_rollover_failure() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().max_size(7), 1)
+            .defer_on_rollover_failure(1024)
+            .internal_error_handler(move |e| tx.send(e.to_string()).unwrap())
+            .build()
             .unwrap();
-        // close the file and make sure that it can re-open it, and that it
-        // resets the file size properly.
-        c.rolling.writer_opt.take();
-        c.rolling.current_filesize = 0;
-        c.rolling
-            .write_with_datetime(b"6789", &Local.ymd(2021, 3, 30).and_hms(1, 3, 3))
+        writeln!(rolling, "Line 1").unwrap();
+        // a non-empty directory in the way makes the rotation fail
+        let blocker = tempdir.path().join("test.log.1");
+        fs::create_dir(&blocker).unwrap();
+        fs::write(blocker.join("file"), "").unwrap();
+        writeln!(rolling, "Line 2").unwrap();
+        writeln!(rolling, "Line 3").unwrap();
+        // a flush fails while writes are held
+        assert!(rolling.flush().is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 1\n");
+        // the deferral is reported once, not on every retry
+        assert_eq!(rx.try_iter().count(), 1);
+        fs::remove_dir_all(&blocker).unwrap();
+        // a flush retries the rollover too
+        rolling.flush().unwrap();
+        assert_eq!(fs::read_to_string(&blocker).unwrap(), "Line 1\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 2\nLine 3\n");
+        assert_eq!(rolling.current_file_size(), 14);
+        assert_eq!(rolling.fallback_dropped_bytes(), 0);
+
+        // dropping the appender writes held writes to the current file if
+        // the rollover still fails, rather than losing them
+        fs::remove_file(&blocker).unwrap();
+        fs::create_dir(&blocker).unwrap();
+        fs::write(blocker.join("file"), "").unwrap();
+        writeln!(rolling, "Line 4").unwrap();
+        drop(rolling);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 2\nLine 3\nLine 4\n");
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn internal_error_handler() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().max_size(7), 1)
+            .internal_error_handler(move |e| tx.send(e.to_string()).unwrap())
+            .build()
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"0", &Local.ymd(2021, 3, 30).and_hms(2, 3, 3))
+        writeln!(rolling, "Line 1").unwrap();
+        // a non-empty directory in the way makes the rotation fail
+        let blocker = tempdir.path().join("test.log.1");
+        fs::create_dir(&blocker).unwrap();
+        fs::write(blocker.join("file"), "").unwrap();
+        writeln!(rolling, "Line 2").unwrap();
+        rolling.flush().unwrap();
+        // writing carried on with the current file
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 1\nLine 2\n");
+        let reported: Vec<_> = rx.try_iter().collect();
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].starts_with(&format!("Failed to rotate logfile {}: ", path.display())));
+    }
+
+    #[test]
+    fn line_prefix_timestamp() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+            .line_prefix_timestamp("[%H:%M:%S] ".to_string())
+            .build()
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"abcdefghijklmn", &Local.ymd(2021, 3, 31).and_hms(2, 3, 3))
+        let t1 = Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap();
+        let t2 = Local.with_ymd_and_hms(2021, 3, 30, 4, 5, 6).unwrap();
+        assert_eq!(rolling.write_with_datetime(b"Line 1\nLine 2\nLine", &t1).unwrap(), 18);
+        rolling.write_with_datetime(b" 3\n", &t2).unwrap();
+        rolling.write_with_datetime(b"Line 4\n\n", &t2).unwrap();
+        rolling.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[01:02:03] Line 1\n[01:02:03] Line 2\n[01:02:03] Line 3\n[04:05:06] Line 4\n[04:05:06] \n"
+        );
+        assert_eq!(rolling.current_file_size(), 11 * 5 + 29);
+
+        assert!(
+            BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .line_prefix_timestamp("%Q".to_string())
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn zero_max_size() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let e = BasicRollingFileAppender::new(&path, RollingConditionBasic::new().max_size(0), 9).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("max_size must be greater than zero"), "{}", e);
+        // also when wrapped
+        let condition = Debounced::new(RollingConditionBasic::new().max_size(0), Duration::seconds(10));
+        assert!(RollingFileAppender::new(&path, condition, 9).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn length_prefixed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.bin");
+        let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new().max_size(5), 9)
+            .length_prefixed(LengthPrefix::U32LE)
+            .build()
             .unwrap();
+        let records: [&[u8]; 4] = [b"\x00\x01\x02", b"", b"\n\xff\n\xff\n", b"abc"];
+        let now = Local::now();
+        for record in &records {
+            assert_eq!(rolling.write_with_datetime(record, &now).unwrap(), record.len());
+        }
+        assert_eq!(rolling.current_file_size(), 4 + 3);
+        rolling.flush().unwrap();
+        // the empty write is an empty record, and the records are split by
+        // size
+        assert_eq!(
+            fs::read(rolling.filename_for(2)).unwrap(),
+            b"\x03\x00\x00\x00\x00\x01\x02"
+        );
+        assert_eq!(&fs::read(rolling.filename_for(1)).unwrap()[..4], b"\x00\x00\x00\x00");
+        let frames: Vec<_> = rolling
+            .reader()
+            .unwrap()
+            .frames(LengthPrefix::U32LE)
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(frames, records);
+
+        assert!(
+            BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 9)
+                .length_prefixed(LengthPrefix::U32LE)
+                .sequence_header(true)
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn files_to_be_pruned() {
+        for numbering in [
+            NumberingScheme::ShiftUp,
+            NumberingScheme::MonotonicIncrement,
+            NumberingScheme::DateGrouped,
+        ] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut rolling =
+                BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 2)
+                    .numbering(numbering)
+                    .compression(Compression::Gzip)
+                    .build()
+                    .unwrap();
+            assert!(rolling.files_to_be_pruned().unwrap().is_empty());
+            for i in 0..5usize {
+                writeln!(rolling, "Line {}", i).unwrap();
+                let predicted = rolling.files_to_be_pruned().unwrap();
+                let pruned_lines: Vec<_> = predicted
+                    .iter()
+                    .map(|p| {
+                        let mut content = String::new();
+                        flate2::read::GzDecoder::new(File::open(p).unwrap())
+                            .read_to_string(&mut content)
+                            .unwrap();
+                        content
+                    })
+                    .collect();
+                rolling.rollover().unwrap();
+                let lines: Vec<_> = rolling.reader().unwrap().lines().map(|x| x.unwrap()).collect();
+                if i < 2 {
+                    assert!(predicted.is_empty(), "{:?}", predicted);
+                } else {
+                    // the oldest file is the one that's gone
+                    assert_eq!(pruned_lines, vec![format!("Line {}\n", i - 2)]);
+                }
+                assert_eq!(lines.first().unwrap(), &format!("Line {}", i.saturating_sub(1)));
+            }
+        }
+    }
+
+    #[test]
+    fn next_rollover_layout() {
+        for numbering in [
+            NumberingScheme::ShiftUp,
+            NumberingScheme::MonotonicIncrement,
+            NumberingScheme::DateGrouped,
+        ] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let path = tempdir.path().join("test.log");
+            let mut rolling = BasicRollingFileAppender::builder(&path, RollingConditionBasic::new(), 3)
+                .numbering(numbering)
+                .compression(Compression::Gzip)
+                .compress_min_size(10)
+                .build()
+                .unwrap();
+            for i in 0..5 {
+                // only files with two lines are large enough to be compressed
+                for _ in 0..=i % 2 {
+                    writeln!(rolling, "Line {}", i).unwrap();
+                }
+                let layout = rolling.next_rollover_layout().unwrap();
+                rolling.rollover().unwrap();
+                assert_eq!(layout.current, path);
+                let mut files: Vec<_> = fs::read_dir(tempdir.path())
+                    .unwrap()
+                    .map(|e| e.unwrap().path())
+                    .filter(|p| *p != path)
+                    .collect();
+                files.sort();
+                let mut planned = layout.archives.clone();
+                planned.sort();
+                assert_eq!(files, planned, "{:?}", numbering);
+                // newest first
+                let newest = &layout.archives[0];
+                let mut content = String::new();
+                if newest.extension() == Some("gz".as_ref()) {
+                    flate2::read::GzDecoder::new(File::open(newest).unwrap())
+                        .read_to_string(&mut content)
+                        .unwrap();
+                } else {
+                    content = fs::read_to_string(newest).unwrap();
+                }
+                assert!(content.starts_with(&format!("Line {}\n", i)));
+            }
+        }
+    }
+
+    #[test]
+    fn date_grouped() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling = BasicRollingFileAppender::builder(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().daily().max_size(14),
+            3,
+        )
+        .numbering(NumberingScheme::DateGrouped)
+        .build()
+        .unwrap();
+        for (i, day) in [30, 30, 30, 30, 30, 31, 31, 31].iter().enumerate() {
+            rolling
+                .write_with_datetime(
+                    format!("Line {}\n", i + 1).as_bytes(),
+                    &Local.with_ymd_and_hms(2021, 3, *day, 12, 0, 0).unwrap(),
+                )
+                .unwrap();
+        }
+        rolling.flush().unwrap();
+        let read = |name: &str| fs::read_to_string(tempdir.path().join(name)).unwrap();
+        // the oldest file is pruned to keep 3 rotated files
+        assert!(!tempdir.path().join("test-20210330.log").exists());
+        assert_eq!(read("test-20210330.1.log"), "Line 3\nLine 4\n");
+        assert_eq!(read("test-20210330.2.log"), "Line 5\n");
+        assert_eq!(read("test-20210331.log"), "Line 6\nLine 7\n");
+        assert_eq!(read("test.log"), "Line 8\n");
+        let mut all = String::new();
+        rolling.reader().unwrap().read_to_string(&mut all).unwrap();
+        assert_eq!(all, "Line 3\nLine 4\nLine 5\nLine 6\nLine 7\nLine 8\n");
+    }
+
+    #[test]
+    fn daily_min_size() {
+        let mut c = build_context(
+            RollingConditionBasic::new()
+                .daily()
+                .min_size_before_time_roll(20)
+                .max_size(40),
+            9,
+        );
+        // sparse writes over several days stay in one file until it has 20
+        // bytes
+        for (day, line) in [(1, "Day 1\n"), (2, "Day 2\n"), (3, "Day 3\n"), (4, "Day 4\n")] {
+            c.rolling
+                .write_with_datetime(
+                    line.as_bytes(),
+                    &Local.with_ymd_and_hms(2021, 3, day, 12, 0, 0).unwrap(),
+                )
+                .unwrap();
+        }
+        assert!(!AsRef::<Path>::as_ref(&c.rolling.filename_for(1)).exists());
         c.rolling
-            .write_with_datetime(b"ZZZ", &Local.ymd(2022, 3, 31).and_hms(1, 2, 3))
+            .write_with_datetime(b"Day 5\n", &Local.with_ymd_and_hms(2021, 3, 5, 12, 0, 0).unwrap())
             .unwrap();
-        assert_eq!(AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists(), false);
-        c.verify_contains("1234567890", 2);
-        c.verify_contains("abcdefghijklmn", 1);
-        c.verify_contains("ZZZ", 0);
+        c.verify_contains("Day 1\nDay 2\nDay 3\nDay 4\n", 1);
+        // a busy day is still cut by the maximum size
+        for _ in 0..8 {
+            c.rolling
+                .write_with_datetime(b"Day 5\n", &Local.with_ymd_and_hms(2021, 3, 5, 13, 0, 0).unwrap())
+                .unwrap();
+        }
+        c.verify_contains(&"Day 5\n".repeat(7), 1);
+        c.verify_contains("Day 5\nDay 5\n", 0);
     }
 
     #[test]
-    fn daily_and_max_size() {
-        let mut c = build_context(RollingConditionBasic::new().daily().max_size(10), 9);
+    fn min_duration_and_max_size() {
+        let mut c = build_context(
+            RollingConditionBasic::new()
+                .daily()
+                .min_duration(Duration::hours(1))
+                .max_size(5),
+            9,
+        );
         c.rolling
-            .write_with_datetime(b"12345", &Local.ymd(2021, 3, 30).and_hms(1, 2, 3))
+            .write_with_datetime(b"12345", &Local.with_ymd_and_hms(2021, 3, 30, 23, 50, 0).unwrap())
             .unwrap();
         c.rolling
-            .write_with_datetime(b"6789", &Local.ymd(2021, 3, 30).and_hms(2, 3, 3))
+            .write_with_datetime(b"6789", &Local.with_ymd_and_hms(2021, 3, 30, 23, 51, 0).unwrap())
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"0", &Local.ymd(2021, 3, 31).and_hms(2, 3, 3))
+        c.verify_contains("12345", 1);
+        c.verify_contains("6789", 0);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_write_and_rollover() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut rolling = BasicRollingFileAppender::new_mmap(
+            tempdir.path().join("test.log"),
+            RollingConditionBasic::new().max_size(10),
+            9,
+            4,
+        )
+        .unwrap();
+        rolling
+            .write_with_datetime(b"12345", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"abcdefghijklmn", &Local.ymd(2021, 3, 31).and_hms(3, 3, 3))
+        rolling
+            .write_with_datetime(b"67890", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
             .unwrap();
-        c.rolling
-            .write_with_datetime(b"ZZZ", &Local.ymd(2021, 3, 31).and_hms(4, 4, 4))
+        rolling
+            .write_with_datetime(b"abc", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
             .unwrap();
-        assert_eq!(AsRef::<Path>::as_ref(&c.rolling.filename_for(3)).exists(), false);
-        c.verify_contains("123456789", 2);
-        c.verify_contains("0abcdefghijklmn", 1);
-        c.verify_contains("ZZZ", 0);
+        assert_eq!(rolling.current_filesize, 3);
+        // the rotated file is trimmed to exactly what was written
+        assert_eq!(fs::read(rolling.filename_for(1)).unwrap(), b"1234567890");
+        // the active file is preallocated until it is closed
+        rolling.flush().unwrap();
+        assert_eq!(fs::metadata(rolling.filename_for(0)).unwrap().len(), 4);
+        let p = rolling.filename_for(0);
+        drop(rolling);
+        assert_eq!(fs::read(&p).unwrap(), b"abc");
     }
 }
 // LCOV_EXCL_STOP
@@ -0,0 +1,124 @@
+//! A memory-mapped writer backend for the active file.
+//!
+//! The file is preallocated in chunks and mapped into memory; writes are plain
+//! memory copies at a manually tracked cursor. The mapping is grown (and the
+//! file extended) one chunk at a time as it fills up, and the file is trimmed
+//! back to the written length when the writer is dropped.
+use memmap2::{MmapMut, MmapOptions};
+use std::{
+    convert::TryFrom,
+    fs::{File, OpenOptions},
+    io,
+    io::Write,
+    mem::ManuallyDrop,
+    path::Path,
+};
+
+/// Appends to a file through a memory-mapped region.
+#[derive(Debug)]
+pub(crate) struct MmapWriter {
+    file: File,
+    /// Dropped by hand, so that the file is unmapped before it's trimmed
+    map: ManuallyDrop<MmapMut>,
+    len: u64,
+    chunk_size: u64,
+}
+
+impl MmapWriter {
+    /// Opens (or creates) the file at the given path, positioning the write
+    /// cursor at the end of its existing content.
+    pub(crate) fn open(path: &Path, chunk_size: u64) -> io::Result<MmapWriter> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = file.metadata()?.len();
+        let chunk_size = chunk_size.max(1);
+        let capacity = round_up(len.max(1), chunk_size);
+        file.set_len(capacity)?;
+        let map = unsafe { MmapOptions::new().len(to_usize(capacity)?).map_mut(&file)? };
+        Ok(MmapWriter {
+            file,
+            map: ManuallyDrop::new(map),
+            len,
+            chunk_size,
+        })
+    }
+
+    /// The number of bytes written to the file, excluding any preallocated space.
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// The mapped file
+    #[cfg(unix)]
+    pub(crate) fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Flushes the mapped region, and makes sure the file's data reaches the
+    /// disk.
+    pub(crate) fn sync_data(&mut self) -> io::Result<()> {
+        self.map.flush()?;
+        self.file.sync_data()
+    }
+
+    /// Makes sure the mapping can hold `additional` more bytes, growing the
+    /// file and remapping it if necessary.
+    fn reserve(&mut self, additional: u64) -> io::Result<()> {
+        let needed = self.len.saturating_add(additional);
+        if needed > self.map.len() as u64 {
+            let capacity = round_up(needed, self.chunk_size);
+            // Make sure everything written so far is on disk before the old
+            // mapping is replaced.
+            self.map.flush()?;
+            self.file.set_len(capacity)?;
+            let map = unsafe { MmapOptions::new().len(to_usize(capacity)?).map_mut(&self.file)? };
+            *self.map = map;
+        }
+        Ok(())
+    }
+}
+
+impl Write for MmapWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.reserve(u64::try_from(buf.len()).unwrap_or(u64::MAX))?;
+        let start = to_usize(self.len)?;
+        self.map[start..start + buf.len()].copy_from_slice(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    /// Synchronously flushes the mapped region to disk (msync).
+    fn flush(&mut self) -> io::Result<()> {
+        self.map.flush()
+    }
+}
+
+impl Drop for MmapWriter {
+    fn drop(&mut self) {
+        // Trim the preallocated tail so the file only holds what was written.
+        // Errors cannot be reported from here; at worst the file keeps its
+        // zero-filled padding.
+        let _ = self.map.flush();
+        // Truncating a file while it is mapped fails on Windows, and makes
+        // accessing the truncated pages fault elsewhere, so unmap it first.
+        // The mapping isn't used after this.
+        unsafe { ManuallyDrop::drop(&mut self.map) };
+        let _ = self.file.set_len(self.len);
+    }
+}
+
+/// Rounds `x` up to the next multiple of `chunk_size`.
+fn round_up(x: u64, chunk_size: u64) -> u64 {
+    match x % chunk_size {
+        0 => x,
+        r => x.saturating_add(chunk_size - r),
+    }
+}
+
+fn to_usize(x: u64) -> io::Result<usize> {
+    usize::try_from(x).map_err(|_| io::Error::other("file too large to map into memory"))
+}
@@ -0,0 +1,475 @@
+//! A handle that hands writes over to a dedicated writer thread.
+use crate::{RollingCondition, RollingFileAppender, RollingFileError};
+use chrono::prelude::*;
+use std::{
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::{Receiver, SyncSender, TryRecvError, TrySendError},
+        Arc,
+    },
+    thread,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// How long to sleep between attempts while waiting for room in the channel
+const FULL_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// What a [`NonBlocking`] writer does with a write when the channel to the
+/// writer thread is full
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WhenFull {
+    /// Drops the write, counting it in [`NonBlocking::dropped`]
+    Drop,
+    /// Waits up to the given time for room, then drops the write like
+    /// [`WhenFull::Drop`]
+    Block(Duration),
+}
+
+/// How important a write to a [`NonBlocking`] writer is. When the channel
+/// fills up, lower priorities are refused first, which keeps room for higher
+/// ones: low-priority writes once the channel is half full, normal ones once
+/// it is three quarters full, and high-priority ones only once it is full.
+/// Writes without a priority are high-priority, so they only ever find the
+/// channel full.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Priority {
+    /// E.g. debug and trace messages
+    Low,
+    /// E.g. informational messages
+    Normal,
+    /// E.g. warnings and errors
+    High,
+}
+
+impl Priority {
+    /// The number of writes that may be queued at most when a write of this
+    /// priority is queued, given the capacity of the channel
+    fn limit(&self, capacity: usize) -> usize {
+        match self {
+            Priority::Low => capacity / 2,
+            Priority::Normal => capacity - capacity / 4,
+            // only the channel itself limits these
+            Priority::High => usize::MAX,
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+enum Message {
+    Record(Vec<u8>, DateTime<Local>),
+    Shutdown,
+}
+
+/// A cloneable writer that never does any file IO itself: every write is
+/// queued on a bounded channel, and a dedicated thread writes it to the
+/// appender, rolls over and flushes. The thread is stopped by dropping the
+/// [`WorkerGuard`] returned along with the writer, which waits for all
+/// queued writes to reach the appender and closes it.
+///
+/// Writes always succeed, even when they are dropped because the channel is
+/// full; failures of the appender are reported by the writer thread, to the
+/// appender's
+/// [`internal_error_handler`](crate::RollingFileAppenderBuilder::internal_error_handler)
+/// if it has one, or on stderr.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn docs() {
+/// # use rolling_file::*;
+/// # use std::io::Write;
+/// let (mut writer, _guard) = NonBlocking::new(
+///     BasicRollingFileAppender::new("/var/log/myprogram", RollingConditionBasic::new().daily(), 9).unwrap(),
+///     1024,
+///     WhenFull::Drop,
+/// )
+/// .unwrap();
+/// writer.write_all(b"hello\n").unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct NonBlocking {
+    sender: SyncSender<Message>,
+    capacity: usize,
+    when_full: WhenFull,
+    /// The number of writes in the channel, approximately
+    queued: Arc<AtomicUsize>,
+    /// The number of writes dropped, by priority
+    dropped: Arc<[AtomicU64; 3]>,
+}
+
+impl NonBlocking {
+    /// Starts a writer thread owning the given appender, returning a writer
+    /// feeding it through a channel of the given capacity, in writes, and the
+    /// guard stopping it. The capacity must be greater than zero.
+    pub fn new<RC>(
+        appender: RollingFileAppender<RC>,
+        capacity: usize,
+        when_full: WhenFull,
+    ) -> io::Result<(NonBlocking, WorkerGuard)>
+    where
+        RC: RollingCondition + Send + 'static,
+    {
+        if capacity == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "capacity must be greater than zero",
+            ));
+        }
+        let (sender, receiver) = std::sync::mpsc::sync_channel(capacity);
+        let queued = Arc::new(AtomicUsize::new(0));
+        let thread_queued = Arc::clone(&queued);
+        let handle = thread::Builder::new()
+            .name("rolling-file-writer".to_string())
+            .spawn(move || run(appender, receiver, thread_queued))?;
+        let writer = NonBlocking {
+            sender: sender.clone(),
+            capacity,
+            when_full,
+            queued,
+            dropped: Arc::new(Default::default()),
+        };
+        let guard = WorkerGuard {
+            sender,
+            handle_opt: Some(handle),
+        };
+        Ok((writer, guard))
+    }
+
+    /// Queues data to be written using the given datetime to calculate the
+    /// rolling condition, with [`Priority::High`].
+    pub fn write_with_datetime(&self, buf: &[u8], now: &DateTime<Local>) {
+        self.write_with_priority(buf, Priority::High, now);
+    }
+
+    /// Queues data to be written using the given datetime to calculate the
+    /// rolling condition, dropping it rather than letting it take up room
+    /// that higher priorities may need, see [`Priority`]. With
+    /// [`WhenFull::Block`], a write waits for the channel to have room for
+    /// its priority.
+    pub fn write_with_priority(&self, buf: &[u8], priority: Priority, now: &DateTime<Local>) {
+        let mut message = Message::Record(buf.to_vec(), *now);
+        let limit = priority.limit(self.capacity);
+        let deadline_opt = match self.when_full {
+            WhenFull::Drop => None,
+            WhenFull::Block(timeout) => Some(Instant::now() + timeout),
+        };
+        loop {
+            // counted before sending, so the writer thread never sees it below zero
+            let r = if self.queued.fetch_add(1, Ordering::Relaxed) < limit {
+                self.sender.try_send(message)
+            } else {
+                Err(TrySendError::Full(message))
+            };
+            if r.is_err() {
+                self.queued.fetch_sub(1, Ordering::Relaxed);
+            }
+            match r {
+                Ok(()) => return,
+                Err(TrySendError::Full(m)) if deadline_opt.is_some_and(|d| Instant::now() < d) => {
+                    message = m;
+                    thread::sleep(FULL_POLL_INTERVAL);
+                },
+                // still full, or the writer thread was stopped by its guard
+                Err(_) => {
+                    self.dropped[priority.index()].fetch_add(1, Ordering::Relaxed);
+                    return;
+                },
+            }
+        }
+    }
+
+    /// Returns the number of writes dropped because the channel was full or
+    /// the writer thread was stopped, summed over all clones of this writer.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.iter().map(|x| x.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Returns the number of writes of the given priority that were dropped,
+    /// like [`dropped`](NonBlocking::dropped).
+    pub fn dropped_with_priority(&self, priority: Priority) -> u64 {
+        self.dropped[priority.index()].load(Ordering::Relaxed)
+    }
+}
+
+impl io::Write for NonBlocking {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_with_datetime(buf, &Local::now());
+        Ok(buf.len())
+    }
+
+    /// Does nothing, as the writer thread flushes whenever it runs out of
+    /// queued writes.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Stops the writer thread of a [`NonBlocking`] writer when dropped, after
+/// all writes queued so far reached the appender. Writes queued after that
+/// are dropped.
+#[derive(Debug)]
+pub struct WorkerGuard {
+    sender: SyncSender<Message>,
+    handle_opt: Option<JoinHandle<()>>,
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        // Fails only if the thread is gone already, which joining reports.
+        let _ = self.sender.send(Message::Shutdown);
+        if let Some(handle) = self.handle_opt.take() {
+            // the thread reports its own panics, unless reporting panicked too
+            if handle.join().is_err() {
+                eprintln!("WARNING: Rolling file writer thread panicked");
+            }
+        }
+    }
+}
+
+/// The writer thread: writes queued records, flushing whenever the queue
+/// runs empty, until told to stop. Failures, and a panic, are reported
+/// through the appender.
+fn run<RC: RollingCondition>(
+    mut appender: RollingFileAppender<RC>,
+    receiver: Receiver<Message>,
+    queued: Arc<AtomicUsize>,
+) {
+    let path = PathBuf::from(&appender.base_filename);
+    let r = panic::catch_unwind(AssertUnwindSafe(|| write_queued(&mut appender, &receiver, &queued)));
+    if r.is_err() {
+        appender.report(RollingFileError::WriterPanicked { path });
+        return;
+    }
+    if let Err(source) = appender.close() {
+        appender.report(RollingFileError::Close { path, source });
+    }
+}
+
+fn write_queued<RC: RollingCondition>(
+    appender: &mut RollingFileAppender<RC>,
+    receiver: &Receiver<Message>,
+    queued: &AtomicUsize,
+) {
+    let mut message_opt = receiver.recv().ok();
+    while let Some(Message::Record(buf, now)) = message_opt {
+        queued.fetch_sub(1, Ordering::Relaxed);
+        if let Err(source) = appender.write_with_datetime(&buf, &now) {
+            let path = PathBuf::from(&appender.base_filename);
+            appender.report(RollingFileError::Write { path, source });
+        }
+        message_opt = match receiver.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => {
+                flush(appender);
+                receiver.recv().ok()
+            },
+            Err(TryRecvError::Disconnected) => None,
+        };
+    }
+}
+
+fn flush<RC: RollingCondition>(appender: &mut RollingFileAppender<RC>) {
+    if let Err(source) = io::Write::flush(appender) {
+        let path = PathBuf::from(&appender.base_filename);
+        appender.report(RollingFileError::Flush { path, source });
+    }
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::{BasicRollingFileAppender, FileMetadata, FileSystem, RollingConditionBasic};
+    use std::{
+        ffi::OsString,
+        fs,
+        io::Write,
+        path::Path,
+        sync::{mpsc::Sender, Mutex},
+    };
+
+    #[test]
+    fn drains_on_drop() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for (when_full, name) in [
+            (WhenFull::Drop, "drop.log"),
+            (WhenFull::Block(Duration::from_secs(10)), "block.log"),
+        ] {
+            let path = tempdir.path().join(name);
+            let (writer, guard) = NonBlocking::new(
+                BasicRollingFileAppender::new(&path, RollingConditionBasic::new(), 9).unwrap(),
+                16,
+                when_full,
+            )
+            .unwrap();
+            let writers: Vec<_> = (0..4)
+                .map(|_| {
+                    let mut writer = writer.clone();
+                    thread::spawn(move || {
+                        for _ in 0..1000 {
+                            writer.write_all(b"line\n").unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for w in writers {
+                w.join().unwrap();
+            }
+            drop(guard);
+            let lines = fs::read_to_string(&path).unwrap().lines().count() as u64;
+            assert_eq!(lines + writer.dropped(), 4000);
+            if when_full != WhenFull::Drop {
+                assert_eq!(writer.dropped(), 0);
+            }
+            // the writer thread is gone
+            writer.write_with_datetime(b"late\n", &Local::now());
+            assert_eq!(writer.dropped(), 4001 - lines);
+        }
+    }
+
+    /// Holds up the first write to its file until told to go on, so that
+    /// the writer thread stops taking writes off the channel
+    #[derive(Debug)]
+    struct GatedFileSystem {
+        data: Arc<Mutex<Vec<u8>>>,
+        entered: Mutex<Sender<()>>,
+        gate: Mutex<Option<Receiver<()>>>,
+    }
+
+    struct GatedFile {
+        data: Arc<Mutex<Vec<u8>>>,
+        entered: Sender<()>,
+        gate_opt: Option<Receiver<()>>,
+    }
+
+    impl Write for GatedFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if let Some(gate) = self.gate_opt.take() {
+                self.entered.send(()).unwrap();
+                gate.recv().unwrap();
+            }
+            self.data.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl FileSystem for GatedFileSystem {
+        fn open_append(&self, _path: &Path) -> io::Result<Box<dyn Write + Send>> {
+            Ok(Box::new(GatedFile {
+                data: Arc::clone(&self.data),
+                entered: self.entered.lock().unwrap().clone(),
+                gate_opt: self.gate.lock().unwrap().take(),
+            }))
+        }
+
+        fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn remove_file(&self, _path: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn metadata(&self, _path: &Path) -> io::Result<FileMetadata> {
+            Err(io::ErrorKind::NotFound.into())
+        }
+
+        fn read_dir(&self, _path: &Path) -> io::Result<Vec<OsString>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn priority() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let data = Arc::new(Mutex::new(Vec::new()));
+        let (entered_tx, entered_rx) = std::sync::mpsc::channel();
+        let (gate_tx, gate_rx) = std::sync::mpsc::channel();
+        let file_system = GatedFileSystem {
+            data: Arc::clone(&data),
+            entered: Mutex::new(entered_tx),
+            gate: Mutex::new(Some(gate_rx)),
+        };
+        let appender =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .file_system(Arc::new(file_system))
+                .build()
+                .unwrap();
+        let (writer, guard) = NonBlocking::new(appender, 64, WhenFull::Drop).unwrap();
+        let now = Local::now();
+        writer.write_with_priority(b"first\n", Priority::Low, &now);
+        // the writer thread is stuck flushing the first write
+        entered_rx.recv().unwrap();
+        for (priority, line, count) in [
+            (Priority::Low, "low\n", 40),
+            (Priority::Normal, "normal\n", 20),
+            (Priority::High, "high\n", 10),
+        ] {
+            for _ in 0..count {
+                writer.write_with_priority(line.as_bytes(), priority, &now);
+            }
+        }
+        assert_eq!(writer.dropped_with_priority(Priority::Low), 8);
+        assert_eq!(writer.dropped_with_priority(Priority::Normal), 4);
+        assert_eq!(writer.dropped_with_priority(Priority::High), 0);
+        assert_eq!(writer.dropped(), 12);
+        gate_tx.send(()).unwrap();
+        drop(guard);
+        let written = String::from_utf8(data.lock().unwrap().clone()).unwrap();
+        let count = |line| written.lines().filter(|l| *l == line).count();
+        assert_eq!(count("low"), 32);
+        assert_eq!(count("normal"), 16);
+        assert_eq!(count("high"), 10);
+    }
+
+    #[test]
+    fn internal_error_handler() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        // every write fails
+        let appender =
+            BasicRollingFileAppender::builder(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9)
+                .rotate_only(true)
+                .internal_error_handler(move |e| tx.send(e.to_string()).unwrap())
+                .build()
+                .unwrap();
+        let (mut writer, guard) = NonBlocking::new(appender, 16, WhenFull::Drop).unwrap();
+        writer.write_all(b"line\n").unwrap();
+        drop(guard);
+        let reported: Vec<_> = rx.try_iter().collect();
+        assert_eq!(reported.len(), 1, "{:?}", reported);
+        assert!(reported[0].starts_with("Failed to write to logfile"), "{}", reported[0]);
+    }
+
+    #[test]
+    fn small_capacity() {
+        for capacity in 1..8 {
+            let low = Priority::Low.limit(capacity);
+            let normal = Priority::Normal.limit(capacity);
+            assert!(low <= normal, "capacity {}", capacity);
+            assert!(normal > 0, "capacity {}", capacity);
+            assert!(normal <= capacity, "capacity {}", capacity);
+        }
+        assert_eq!(Priority::Normal.limit(2), 2);
+        assert_eq!(Priority::Normal.limit(3), 3);
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let appender =
+            BasicRollingFileAppender::new(tempdir.path().join("test.log"), RollingConditionBasic::new(), 9).unwrap();
+        let e = NonBlocking::new(appender, 0, WhenFull::Drop).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+}
+// LCOV_EXCL_STOP
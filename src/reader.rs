@@ -0,0 +1,99 @@
+//! Reading the whole history of an appender's files.
+use crate::{FrameReader, LengthPrefix};
+use flate2::read::MultiGzDecoder;
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io,
+    io::{BufRead, BufReader, Read},
+    path::PathBuf,
+};
+
+/// Reads the rotated files of an appender followed by its current file, from
+/// oldest to newest, as one continuous stream. Compressed files are
+/// decompressed transparently. Created by
+/// [`RollingFileAppender::reader`](crate::RollingFileAppender::reader).
+///
+/// Files are opened one after the other as reading progresses, so rollovers
+/// happening meanwhile may cause data to be skipped or read twice; files that
+/// disappeared are skipped. Data still buffered by the appender is not
+/// included unless it was flushed first.
+pub struct RollingFileReader {
+    files: VecDeque<(PathBuf, bool)>,
+    current_opt: Option<Box<dyn Read + Send>>,
+}
+
+impl fmt::Debug for RollingFileReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RollingFileReader")
+            .field("files", &self.files)
+            .field("is_reading", &self.current_opt.is_some())
+            .finish()
+    }
+}
+
+impl RollingFileReader {
+    /// Creates a reader for the given files, each with whether it is
+    /// gzip-compressed, in the order to read them.
+    pub(crate) fn new(files: Vec<(PathBuf, bool)>) -> RollingFileReader {
+        RollingFileReader {
+            files: files.into(),
+            current_opt: None,
+        }
+    }
+
+    /// Returns an iterator over the lines of all files, in order. Lines are
+    /// split as with [`BufRead::lines`], so a file that doesn't end with a
+    /// newline has its last line joined with the first line of the next one.
+    pub fn lines(self) -> io::Lines<BufReader<RollingFileReader>> {
+        BufReader::new(self).lines()
+    }
+
+    /// Returns an iterator over the records of all files, in order, for an
+    /// appender writing them
+    /// [`length_prefixed`](crate::RollingFileAppenderBuilder::length_prefixed).
+    /// Records are read through a buffer, so a record may span two files.
+    pub fn frames(self, prefix: LengthPrefix) -> FrameReader<BufReader<RollingFileReader>> {
+        FrameReader::new(BufReader::new(self), prefix)
+    }
+
+    /// Opens the next file that still exists, returning false once there is
+    /// none left.
+    fn open_next(&mut self) -> io::Result<bool> {
+        while let Some((path, compressed)) = self.files.pop_front() {
+            match File::open(&path) {
+                Ok(f) if compressed => {
+                    self.current_opt = Some(Box::new(MultiGzDecoder::new(BufReader::new(f))));
+                    return Ok(true);
+                },
+                Ok(f) => {
+                    self.current_opt = Some(Box::new(f));
+                    return Ok(true);
+                },
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Read for RollingFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(current) = self.current_opt.as_mut() {
+                match current.read(buf)? {
+                    0 => self.current_opt = None,
+                    n => return Ok(n),
+                }
+            }
+            if !self.open_next()? {
+                return Ok(0);
+            }
+        }
+    }
+}
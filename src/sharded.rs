@@ -0,0 +1,195 @@
+//! Appenders spreading writes over a fixed number of shard files.
+use crate::{RollingCondition, RollingFileAppender};
+use chrono::prelude::*;
+use std::{
+    fmt,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Picks the shard for a write from its data.
+type ShardFn = Box<dyn Fn(&[u8]) -> usize + Send + Sync>;
+
+/// Spreads writes over a fixed number of rolling file appenders, the shards,
+/// picking one for each write with a function of the data written, e.g. a
+/// hash of a key in it, so that related records end up in the same file.
+/// Each shard rolls over independently. The function's result is taken
+/// modulo the number of shards.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn docs() {
+/// # use rolling_file::*;
+/// # use std::io::Write;
+/// // writes /var/log/myprogram.0.log .. /var/log/myprogram.7.log
+/// let mut sharded = ShardedRollingAppender::new(
+///     "/var/log/myprogram.log",
+///     8,
+///     RollingConditionBasic::new().daily(),
+///     9,
+///     |buf: &[u8]| buf.first().map_or(0, |b| usize::from(*b)),
+/// )
+/// .unwrap();
+/// sharded.write_all(b"hello\n").unwrap();
+/// # }
+/// ```
+pub struct ShardedRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    shards: Vec<RollingFileAppender<RC>>,
+    shard_fn: ShardFn,
+}
+
+impl<RC> ShardedRollingAppender<RC>
+where
+    RC: RollingCondition + Clone,
+{
+    /// Creates the given number of shards, each with a copy of the condition,
+    /// named after the given path with the shard's number before its
+    /// extension, e.g. `app.0.log` to `app.7.log` for `app.log`, see
+    /// [`shard_path`].
+    pub fn new<P, F>(
+        path: P,
+        shards: usize,
+        condition: RC,
+        max_files: usize,
+        shard_fn: F,
+    ) -> io::Result<ShardedRollingAppender<RC>>
+    where
+        P: AsRef<Path>,
+        F: Fn(&[u8]) -> usize + Send + Sync + 'static,
+    {
+        let appenders = (0..shards)
+            .map(|i| RollingFileAppender::new(shard_path(path.as_ref(), i), condition.clone(), max_files))
+            .collect::<io::Result<Vec<_>>>()?;
+        ShardedRollingAppender::from_appenders(appenders, shard_fn)
+    }
+}
+
+impl<RC> ShardedRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Uses the given appenders as the shards, e.g. to build them with
+    /// further options. Fails if there are none.
+    pub fn from_appenders<F>(
+        shards: Vec<RollingFileAppender<RC>>,
+        shard_fn: F,
+    ) -> io::Result<ShardedRollingAppender<RC>>
+    where
+        F: Fn(&[u8]) -> usize + Send + Sync + 'static,
+    {
+        if shards.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a sharded appender needs at least one shard",
+            ));
+        }
+        Ok(ShardedRollingAppender {
+            shards,
+            shard_fn: Box::new(shard_fn),
+        })
+    }
+
+    /// Returns the number of the shard the given data is written to.
+    pub fn shard_for(&self, buf: &[u8]) -> usize {
+        (self.shard_fn)(buf) % self.shards.len()
+    }
+
+    /// Returns the shards, in order of their numbers.
+    pub fn shards(&self) -> &[RollingFileAppender<RC>] {
+        &self.shards
+    }
+
+    /// Writes data to the shard picked for it, using the given datetime to
+    /// calculate its rolling condition.
+    pub fn write_with_datetime(&mut self, buf: &[u8], now: &DateTime<Local>) -> io::Result<usize> {
+        let shard = self.shard_for(buf);
+        self.shards[shard].write_with_datetime(buf, now)
+    }
+}
+
+impl<RC> io::Write for ShardedRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let shard = self.shard_for(buf);
+        self.shards[shard].write(buf)
+    }
+
+    /// Flushes all shards, even if flushing one of them fails, returning the
+    /// first error.
+    fn flush(&mut self) -> io::Result<()> {
+        let mut r = Ok(());
+        for shard in self.shards.iter_mut() {
+            if let Err(e) = shard.flush() {
+                r = r.and(Err(e));
+            }
+        }
+        r
+    }
+}
+
+impl<RC> fmt::Debug for ShardedRollingAppender<RC>
+where
+    RC: RollingCondition + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedRollingAppender")
+            .field("shards", &self.shards)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Determines the path of the i-th shard for the given path, with the
+/// shard's number before the extension, e.g. `app.3.log` for `app.log`, or
+/// at the end if there is none, e.g. `app.3` for `app`.
+pub fn shard_path<P: AsRef<Path>>(path: P, i: usize) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", i));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::RollingConditionBasic;
+    use std::{fs, io::Write};
+
+    #[test]
+    fn routes_by_key() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("app.log");
+        // the key is the first byte, a digit
+        let mut sharded = ShardedRollingAppender::new(&path, 4, RollingConditionBasic::new(), 9, |buf: &[u8]| {
+            usize::from(buf[0] - b'0')
+        })
+        .unwrap();
+        for line in &["0 a\n", "1 b\n", "5 c\n", "3 d\n", "4 e\n", "2 f\n"] {
+            sharded.write_all(line.as_bytes()).unwrap();
+        }
+        sharded.flush().unwrap();
+        let read = |i| fs::read_to_string(tempdir.path().join(format!("app.{}.log", i))).unwrap();
+        assert_eq!(read(0), "0 a\n4 e\n");
+        assert_eq!(read(1), "1 b\n5 c\n");
+        assert_eq!(read(2), "2 f\n");
+        assert_eq!(read(3), "3 d\n");
+        assert_eq!(sharded.shards().len(), 4);
+        assert_eq!(sharded.shard_for(b"7"), 3);
+        assert_eq!(shard_path("app", 2), PathBuf::from("app.2"));
+        assert!(
+            ShardedRollingAppender::from_appenders(Vec::<crate::BasicRollingFileAppender>::new(), |_: &[u8]| 0)
+                .is_err()
+        );
+    }
+}
+// LCOV_EXCL_STOP
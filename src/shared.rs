@@ -0,0 +1,418 @@
+//! A thread-safe handle to an appender.
+use crate::{RollingCondition, RollingFileAppender};
+use chrono::prelude::*;
+use std::{
+    backtrace::Backtrace,
+    fmt,
+    io,
+    io::Write,
+    panic,
+    sync::{Arc, Mutex, MutexGuard, TryLockError},
+    thread,
+    time,
+};
+
+/// A cloneable, thread-safe handle to a rolling file appender. This is the
+/// sanctioned way to use an appender from several threads: every method
+/// holds the lock for the whole operation, so a manual rollover can never
+/// happen in the middle of a write, and vice versa.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn docs() {
+/// # use rolling_file::*;
+/// # use std::io::Write;
+/// let shared = SharedRollingFileAppender::new(
+///     BasicRollingFileAppender::new("/var/log/myprogram", RollingConditionBasic::new().daily(), 9).unwrap(),
+/// );
+/// let mut writer = shared.clone();
+/// std::thread::spawn(move || writer.write_all(b"hello\n").unwrap());
+/// shared.rollover().unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    inner: Arc<Mutex<RollingFileAppender<RC>>>,
+}
+
+impl<RC> Clone for SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn clone(&self) -> Self {
+        SharedRollingFileAppender {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Creates a new handle owning the given appender.
+    pub fn new(appender: RollingFileAppender<RC>) -> SharedRollingFileAppender<RC> {
+        SharedRollingFileAppender {
+            inner: Arc::new(Mutex::new(appender)),
+        }
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<'_, RollingFileAppender<RC>>> {
+        self.inner.lock().map_err(|_| poisoned())
+    }
+
+    /// Writes data using the given datetime to calculate the rolling condition
+    pub fn write_with_datetime(&self, buf: &[u8], now: &DateTime<Local>) -> io::Result<usize> {
+        self.lock()?.write_with_datetime(buf, now)
+    }
+
+    /// Writes data using the given datetime to calculate the rolling
+    /// condition, then forces a rollover if `rollover` is set, without
+    /// letting any other write in between. The data is thus guaranteed to be
+    /// the last thing written to the rotated file.
+    pub fn write_then_maybe_rollover(&self, buf: &[u8], now: &DateTime<Local>, rollover: bool) -> io::Result<usize> {
+        let mut appender = self.lock()?;
+        let n = appender.write_with_datetime(buf, now)?;
+        if rollover {
+            appender.rollover()?;
+        }
+        Ok(n)
+    }
+
+    /// Forces a rollover to happen immediately.
+    pub fn rollover(&self) -> io::Result<()> {
+        self.lock()?.rollover()
+    }
+
+    /// Runs the given function with exclusive access to the appender, for
+    /// combined operations that must not be interleaved with other threads.
+    pub fn with_appender<T, F>(&self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut RollingFileAppender<RC>) -> io::Result<T>,
+    {
+        f(&mut *self.lock()?)
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Installs a panic hook writing the panic message, and the backtrace if
+    /// `backtrace` is set, to the appender and flushing it right away, before
+    /// running the previously installed hook. Call
+    /// [`PanicHook::uninstall`] to restore the previous hook, as long as no
+    /// other hook was installed since.
+    ///
+    /// If the panicking thread holds the appender's lock, e.g. because the
+    /// panic happened within [`with_appender`](Self::with_appender), the
+    /// message can't be written, and is only handled by the previous hook.
+    /// If another thread holds the lock, the hook waits for it briefly.
+    pub fn install_panic_hook(&self, backtrace: bool) -> PanicHook {
+        let inner = Arc::clone(&self.inner);
+        let previous = Arc::new(panic::take_hook());
+        let previous_for_hook = Arc::clone(&previous);
+        let hook: Box<PanicHookFn> = Box::new(move |info| {
+            let mut message = format!("{}\n", info);
+            if backtrace {
+                message.push_str(&format!("{}\n", Backtrace::force_capture()));
+            }
+            if let Some(mut appender) = lock_for_panic(&inner) {
+                let _ = appender.write_all(message.as_bytes());
+                let _ = appender.flush();
+            }
+            previous_for_hook(info);
+        });
+        let ours = hook_address(&*hook);
+        panic::set_hook(hook);
+        PanicHook {
+            restore: Box::new(move || {
+                let current = panic::take_hook();
+                if hook_address(&*current) != ours {
+                    // someone else's hook, which may well run ours in turn
+                    panic::set_hook(current);
+                    return;
+                }
+                // dropping our hook leaves the only other reference
+                drop(current);
+                if let Ok(previous) = Arc::try_unwrap(previous) {
+                    panic::set_hook(previous);
+                }
+            }),
+        }
+    }
+}
+
+type PanicHookFn = dyn Fn(&panic::PanicHookInfo<'_>) + Sync + Send + 'static;
+
+/// The address a panic hook is boxed at, which tells whether the installed
+/// hook is a given one
+fn hook_address(hook: &PanicHookFn) -> usize {
+    hook as *const PanicHookFn as *const () as usize
+}
+
+/// Locks the appender from within a panic hook: a poisoned lock is used
+/// anyway, and a lock held elsewhere is waited for only briefly, since it may
+/// be held by the panicking thread itself.
+fn lock_for_panic<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    for _ in 0..10 {
+        match mutex.try_lock() {
+            Ok(guard) => return Some(guard),
+            Err(TryLockError::Poisoned(e)) => return Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => thread::sleep(time::Duration::from_millis(10)),
+        }
+    }
+    None
+}
+
+/// A panic hook installed by
+/// [`SharedRollingFileAppender::install_panic_hook`]. Dropping it leaves the
+/// hook installed.
+pub struct PanicHook {
+    restore: Box<dyn FnOnce() + Send>,
+}
+
+impl fmt::Debug for PanicHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PanicHook").finish()
+    }
+}
+
+impl PanicHook {
+    /// Removes the hook, and restores the one that was installed before.
+    /// If another hook was installed meanwhile, nothing is changed, as that
+    /// hook may run this one in turn.
+    pub fn uninstall(self) {
+        (self.restore)();
+    }
+}
+
+impl<RC> io::Write for SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock()?.flush()
+    }
+}
+
+/// Flushes a registered appender at exit, returning false once the appender
+/// is gone.
+#[cfg(unix)]
+type ExitFlush = Box<dyn Fn() -> bool + Send>;
+
+/// The appenders registered with [`register_flush_at_exit`]
+#[cfg(unix)]
+static EXIT_FLUSHES: Mutex<Vec<ExitFlush>> = Mutex::new(Vec::new());
+
+/// Registers the appender to be flushed when the process exits normally,
+/// i.e. returns from `main` or calls [`std::process::exit`], on top of the
+/// flush when it's dropped, e.g. for an appender leaked into a global
+/// logger, which is never dropped. The appender isn't kept alive by this.
+///
+/// The flush runs from an `atexit` handler, after `main` has returned. If
+/// another thread holds the appender's lock at that point, the flush waits
+/// for it briefly, and is skipped if it isn't released in time. Nothing is
+/// flushed when the process is killed or aborts.
+#[cfg(unix)]
+pub fn register_flush_at_exit<RC>(handle: &SharedRollingFileAppender<RC>)
+where
+    RC: RollingCondition + Send + 'static,
+{
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        // if this fails, the appenders are still flushed when dropped
+        let _ = unsafe { libc::atexit(flush_at_exit) };
+    });
+    let weak = Arc::downgrade(&handle.inner);
+    let flush = move || match weak.upgrade() {
+        Some(inner) => {
+            if let Some(mut appender) = lock_for_panic(&inner) {
+                let _ = appender.flush();
+            }
+            true
+        },
+        None => false,
+    };
+    exit_flushes().push(Box::new(flush));
+}
+
+/// Locks the registered flushes, even if a flush panicked before.
+#[cfg(unix)]
+fn exit_flushes() -> MutexGuard<'static, Vec<ExitFlush>> {
+    EXIT_FLUSHES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Flushes all registered appenders that are still alive, forgetting the
+/// others, and returns how many were flushed.
+#[cfg(unix)]
+fn flush_registered() -> usize {
+    let mut flushes = exit_flushes();
+    flushes.retain(|flush| flush());
+    flushes.len()
+}
+
+#[cfg(unix)]
+extern "C" fn flush_at_exit() {
+    // unwinding out of the handler would abort the process
+    let _ = panic::catch_unwind(flush_registered);
+}
+
+/// The error returned when the lock of an appender is poisoned
+pub(crate) fn poisoned() -> io::Error {
+    io::Error::other("rolling file appender lock is poisoned")
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::{BasicRollingFileAppender, RollingConditionBasic};
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    #[test]
+    fn concurrent_writes_and_rollovers() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let shared = SharedRollingFileAppender::new(
+            BasicRollingFileAppender::new(tempdir.path().join("test.log"), RollingConditionBasic::new(), 1000).unwrap(),
+        );
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut shared = shared.clone();
+                thread::spawn(move || {
+                    for _ in 0..500 {
+                        shared.write_all(b"0123456789\n").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for i in 0..50 {
+            if i % 2 == 0 {
+                shared.rollover().unwrap();
+            } else {
+                shared
+                    .write_then_maybe_rollover(b"last line\n", &Local::now(), true)
+                    .unwrap();
+            }
+        }
+        for w in writers {
+            w.join().unwrap();
+        }
+        shared.clone().flush().unwrap();
+        let mut lines = 0;
+        for entry in fs::read_dir(tempdir.path()).unwrap() {
+            let content = fs::read_to_string(entry.unwrap().path()).unwrap();
+            assert!(content.is_empty() || content.ends_with('\n'));
+            for line in content.lines() {
+                assert!(line == "0123456789" || line == "last line", "{:?}", line);
+                lines += 1;
+            }
+        }
+        assert_eq!(lines, 2025);
+    }
+
+    #[test]
+    fn panic_hook() {
+        // the hook is process-wide, so it's installed in a process of its own
+        // running only this test, where it can't catch the panics of others
+        let path = match std::env::var_os("PANIC_HOOK_TEST_FILE") {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let tempdir = tempfile::tempdir().unwrap();
+                let path = tempdir.path().join("test.log");
+                let status = std::process::Command::new(std::env::current_exe().unwrap())
+                    .args(["shared::t::panic_hook", "--exact", "--test-threads=1"])
+                    .env("PANIC_HOOK_TEST_FILE", &path)
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .status()
+                    .unwrap();
+                assert!(status.success());
+                let content = fs::read_to_string(&path).unwrap();
+                assert!(content.contains("something went wrong"), "{:?}", content);
+                assert!(!content.contains("while locked"));
+                assert!(!content.contains("after uninstall"));
+                assert!(content.contains("still chained"), "{:?}", content);
+                return;
+            },
+        };
+        let counter = |count: &Arc<AtomicUsize>| {
+            let count = Arc::clone(count);
+            move || count.fetch_add(1, Ordering::Relaxed)
+        };
+        let previous_calls = Arc::new(AtomicUsize::new(0));
+        let previous_called = counter(&previous_calls);
+        panic::set_hook(Box::new(move |_| {
+            previous_called();
+        }));
+        let shared = SharedRollingFileAppender::new(
+            BasicRollingFileAppender::new(&path, RollingConditionBasic::new(), 9).unwrap(),
+        );
+        let hook = shared.install_panic_hook(false);
+        assert!(panic::catch_unwind(|| panic!("something went wrong")).is_err());
+        assert_eq!(previous_calls.load(Ordering::Relaxed), 1);
+        // a panic while holding the lock must not deadlock
+        let r = shared.with_appender(|_| -> io::Result<()> {
+            assert!(panic::catch_unwind(|| panic!("while locked")).is_err());
+            Ok(())
+        });
+        r.unwrap();
+        hook.uninstall();
+        assert!(panic::catch_unwind(|| panic!("after uninstall")).is_err());
+        assert_eq!(previous_calls.load(Ordering::Relaxed), 3);
+
+        // a hook installed on top of ours is left in place
+        let hook = shared.install_panic_hook(false);
+        let ours = panic::take_hook();
+        let other_calls = Arc::new(AtomicUsize::new(0));
+        let other_called = counter(&other_calls);
+        panic::set_hook(Box::new(move |info| {
+            other_called();
+            ours(info);
+        }));
+        hook.uninstall();
+        assert!(panic::catch_unwind(|| panic!("still chained")).is_err());
+        assert_eq!(other_calls.load(Ordering::Relaxed), 1);
+        assert_eq!(previous_calls.load(Ordering::Relaxed), 4);
+        let _ = panic::take_hook();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flush_at_exit() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let shared: Vec<_> = ["a.log", "b.log"]
+            .iter()
+            .map(|name| {
+                SharedRollingFileAppender::new(
+                    BasicRollingFileAppender::new(tempdir.path().join(name), RollingConditionBasic::new(), 9).unwrap(),
+                )
+            })
+            .collect();
+        for s in &shared {
+            register_flush_at_exit(s);
+            s.clone().write_all(b"buffered\n").unwrap();
+        }
+        assert_eq!(fs::read_to_string(tempdir.path().join("a.log")).unwrap(), "");
+        assert_eq!(flush_registered(), 2);
+        for name in &["a.log", "b.log"] {
+            assert_eq!(fs::read_to_string(tempdir.path().join(name)).unwrap(), "buffered\n");
+        }
+        // dropped appenders are forgotten
+        drop(shared);
+        assert_eq!(flush_registered(), 0);
+    }
+}
+// LCOV_EXCL_STOP
@@ -0,0 +1,278 @@
+//! Building an appender from a configuration string.
+use crate::{
+    BasicRollingFileAppender,
+    Compression,
+    NumberingScheme,
+    RollingConditionBasic,
+    RollingFileAppender,
+    RollingFrequency,
+};
+use std::{error, fmt, io, path::Path};
+
+/// An error building an appender from a configuration string
+#[derive(Debug)]
+pub enum SpecError {
+    /// The string holds a key that isn't known
+    UnknownKey(String),
+    /// A key is given a value it can't take
+    InvalidValue {
+        /// The key
+        key: String,
+        /// The value that is not valid for the key
+        value: String,
+    },
+    /// A required key is missing
+    MissingKey(&'static str),
+    /// The appender can't be built with the given options
+    Io(io::Error),
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecError::UnknownKey(key) => write!(f, "unknown key {:?} in rolling file spec", key),
+            SpecError::InvalidValue { key, value } => {
+                write!(f, "invalid value {:?} for key {:?} in rolling file spec", value, key)
+            },
+            SpecError::MissingKey(key) => write!(f, "missing key {:?} in rolling file spec", key),
+            SpecError::Io(e) => write!(f, "failed to build rolling file appender: {}", e),
+        }
+    }
+}
+
+impl error::Error for SpecError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SpecError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SpecError {
+    fn from(e: io::Error) -> Self {
+        SpecError::Io(e)
+    }
+}
+
+impl BasicRollingFileAppender {
+    /// Builds an appender from a comma-separated configuration string, e.g.
+    /// from an environment variable, like
+    /// `"daily,max_size=10MB,max_files=7,compress=gzip,tz=UTC"`. The keys
+    /// are:
+    ///
+    /// - `daily`, `hourly`, `minutely`: the frequency to roll over at, at most one of them
+    /// - `max_size=N`: the size to roll over at, in bytes, or with a `KB`, `MB` or `GB` suffix (powers of
+    ///   1024), greater than zero
+    /// - `max_files=N`: the maximum number of rotated files, 0 for unlimited (required)
+    /// - `compress=gzip|bgzip|none`: how rotated files are compressed
+    /// - `numbering=shift|monotonic|date`: how rotated files are numbered
+    /// - `tz=local|UTC`: whether `daily` follows the local or the UTC date, defaulting to local. `UTC`
+    ///   requires `daily`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # fn docs() {
+    /// # use rolling_file::*;
+    /// let file_appender =
+    ///     BasicRollingFileAppender::from_spec("/var/log/myprogram", "daily,max_size=10MB,max_files=7").unwrap();
+    /// # }
+    /// ```
+    pub fn from_spec<P>(path: P, spec: &str) -> Result<BasicRollingFileAppender, SpecError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut condition = RollingConditionBasic::new();
+        let mut max_files_opt = None;
+        let mut compression_opt = None;
+        let mut numbering = NumberingScheme::default();
+        let mut utc_opt = None;
+        for token in spec.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = match token.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (token, None),
+            };
+            let invalid = || SpecError::InvalidValue {
+                key: key.to_string(),
+                value: value.unwrap_or_default().to_string(),
+            };
+            match (key, value) {
+                // only one frequency can be given
+                ("daily" | "hourly" | "minutely", None) if condition.frequency_opt.is_some() => return Err(invalid()),
+                ("daily", None) => condition = condition.daily(),
+                ("hourly", None) => condition = condition.hourly(),
+                ("minutely", None) => condition = condition.frequency(RollingFrequency::EveryMinute),
+                ("max_size", Some(x)) => {
+                    condition = condition.max_size(parse_size(x).filter(|x| *x > 0).ok_or_else(invalid)?)
+                },
+                ("max_files", Some(x)) => max_files_opt = Some(x.parse().map_err(|_| invalid())?),
+                ("compress", Some(x)) => {
+                    compression_opt = match x.to_ascii_lowercase().as_str() {
+                        "gzip" => Some(Compression::Gzip),
+                        "bgzip" => Some(Compression::BlockGzip),
+                        "none" => None,
+                        _ => return Err(invalid()),
+                    }
+                },
+                ("numbering", Some(x)) => {
+                    numbering = match x.to_ascii_lowercase().as_str() {
+                        "shift" => NumberingScheme::ShiftUp,
+                        "monotonic" => NumberingScheme::MonotonicIncrement,
+                        "date" => NumberingScheme::DateGrouped,
+                        _ => return Err(invalid()),
+                    }
+                },
+                ("tz", Some(x)) => {
+                    utc_opt = match x.to_ascii_lowercase().as_str() {
+                        "local" => None,
+                        "utc" => Some(x),
+                        _ => return Err(invalid()),
+                    }
+                },
+                ("daily" | "hourly" | "minutely" | "max_size" | "max_files" | "compress" | "numbering" | "tz", _) => {
+                    return Err(invalid())
+                },
+                _ => return Err(SpecError::UnknownKey(key.to_string())),
+            }
+        }
+        if let Some(utc) = utc_opt {
+            condition = match condition.frequency_opt {
+                Some(RollingFrequency::EveryDay) => condition.daily_utc(),
+                // only the daily frequency can follow the UTC date
+                _ => {
+                    return Err(SpecError::InvalidValue {
+                        key: "tz".to_string(),
+                        value: utc.to_string(),
+                    })
+                },
+            };
+        }
+        let max_files = max_files_opt.ok_or(SpecError::MissingKey("max_files"))?;
+        let mut builder = RollingFileAppender::builder(path, condition, max_files).numbering(numbering);
+        if let Some(compression) = compression_opt {
+            builder = builder.compression(compression);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Parses a size in bytes, with an optional `KB`, `MB` or `GB` suffix.
+fn parse_size(x: &str) -> Option<u64> {
+    let upper = x.to_ascii_uppercase();
+    let (digits, factor) = match upper.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => {
+            let factor = match upper[i..].trim() {
+                "B" => 1,
+                "K" | "KB" => 1 << 10,
+                "M" | "MB" => 1 << 20,
+                "G" | "GB" => 1 << 30,
+                _ => return None,
+            };
+            (&upper[..i], factor)
+        },
+        None => (&upper[..], 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(factor)
+}
+
+// LCOV_EXCL_START
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    #[test]
+    fn valid_specs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let rolling =
+            BasicRollingFileAppender::from_spec(&path, "daily,max_size=10MB,max_files=7,compress=gzip,tz=UTC").unwrap();
+        assert_eq!(
+            rolling.condition,
+            RollingConditionBasic::new().daily_utc().max_size(10 * 1024 * 1024)
+        );
+        let config = rolling.config();
+        assert_eq!(config.max_files, 7);
+        assert_eq!(config.compression, Some(Compression::Gzip));
+
+        let rolling =
+            BasicRollingFileAppender::from_spec(&path, " hourly , max_size = 512 , max_files=0, numbering=monotonic")
+                .unwrap();
+        assert_eq!(rolling.condition, RollingConditionBasic::new().hourly().max_size(512));
+        assert_eq!(rolling.config().max_files, 0);
+        assert_eq!(rolling.config().numbering, NumberingScheme::MonotonicIncrement);
+        assert_eq!(rolling.config().compression, None);
+
+        let rolling = BasicRollingFileAppender::from_spec(&path, "daily,max_files=7,numbering=date").unwrap();
+        assert_eq!(rolling.config().numbering, NumberingScheme::DateGrouped);
+    }
+
+    #[test]
+    fn invalid_specs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("test.log");
+        let error = |spec| {
+            BasicRollingFileAppender::from_spec(&path, spec)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("daily,weekly,max_files=7"),
+            "unknown key \"weekly\" in rolling file spec"
+        );
+        assert_eq!(
+            error("max_size=10XB,max_files=7"),
+            "invalid value \"10XB\" for key \"max_size\" in rolling file spec"
+        );
+        assert_eq!(
+            error("max_size=0,max_files=7"),
+            "invalid value \"0\" for key \"max_size\" in rolling file spec"
+        );
+        assert_eq!(
+            error("daily=yes,max_files=7"),
+            "invalid value \"yes\" for key \"daily\" in rolling file spec"
+        );
+        assert_eq!(
+            error("max_files"),
+            "invalid value \"\" for key \"max_files\" in rolling file spec"
+        );
+        assert_eq!(
+            error("compress=zstd,max_files=7"),
+            "invalid value \"zstd\" for key \"compress\" in rolling file spec"
+        );
+        assert_eq!(
+            error("daily,hourly,max_files=7"),
+            "invalid value \"\" for key \"hourly\" in rolling file spec"
+        );
+        assert_eq!(
+            error("daily,daily,max_files=7"),
+            "invalid value \"\" for key \"daily\" in rolling file spec"
+        );
+        assert_eq!(
+            error("tz=UTC,hourly,max_files=7"),
+            "invalid value \"UTC\" for key \"tz\" in rolling file spec"
+        );
+        assert_eq!(
+            error("max_size=1MB,tz=utc,max_files=7"),
+            "invalid value \"utc\" for key \"tz\" in rolling file spec"
+        );
+        assert_eq!(error("daily"), "missing key \"max_files\" in rolling file spec");
+        assert!(matches!(
+            BasicRollingFileAppender::from_spec(tempdir.path().join("missing").join("test.log"), "max_files=1"),
+            Err(SpecError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("100B"), Some(100));
+        assert_eq!(parse_size("2kb"), Some(2048));
+        assert_eq!(parse_size("3 MB"), Some(3 << 20));
+        assert_eq!(parse_size("1G"), Some(1 << 30));
+        assert_eq!(parse_size("MB"), None);
+        assert_eq!(parse_size("1TB"), None);
+        assert_eq!(parse_size("-1"), None);
+    }
+}
+// LCOV_EXCL_STOP